@@ -2,7 +2,7 @@ use library::Library;
 use document::{Document, DocumentId};
 use node::{Node, NodeId};
 use std::collections::HashMap;
-use timestamp::{Date, Timestamp, TimestampKind};
+use timestamp::{today, Date, DelayMark, Timestamp, TimestampKind};
 use headline::Headline;
 use chrono::{Datelike, Duration};
 use std::cmp::Ordering;
@@ -15,13 +15,24 @@ pub enum AgendaRange {
 pub struct Agenda {
     pub start_date: Date,
     pub range: AgendaRange,
+    /// The day that overdue items and deadline warnings are shown on
+    pub today: Date,
+    /// Nodes that are not done and were scheduled before today, unless a delay still hides them
     pub past_scheduled: Vec<AgendaEntry>,
+    /// Nodes that are not done and had a deadline before today
     pub past_deadline: Vec<AgendaEntry>,
+    /// Nodes that are not done and have a deadline after today, within its warning period
+    pub upcoming_deadlines: Vec<AgendaEntry>,
     entries: HashMap<Date, Vec<AgendaEntry>>
 }
 
 impl Agenda {
     pub fn new(library: &Library, date: Date, range: AgendaRange) -> Self {
+        Agenda::new_at(library, date, range, today())
+    }
+
+    /// Builds the agenda with overdue items and deadline warnings relative to the given day
+    pub fn new_at(library: &Library, date: Date, range: AgendaRange, today: Date) -> Self {
         let start_date = match range {
             AgendaRange::Day => date,
             AgendaRange::Week => date - Duration::days(date.weekday().number_from_monday() as i64 - 1)
@@ -29,46 +40,80 @@ impl Agenda {
         let mut agenda = Agenda {
             start_date,
             range,
+            today,
             past_scheduled: Vec::new(),
             past_deadline: Vec::new(),
+            upcoming_deadlines: Vec::new(),
             entries: HashMap::new()
         };
         let dates: Vec<Date> = agenda.dates().collect();
         for document in library.documents() {
-            // agenda.past_scheduled = document
-            //     .nodes_past_scheduled()
-            //     .map(|node| AgendaEntry::from_node(document, node, Some(TimestampKind::Scheduled)))
-            //     .collect();
-            // agenda.past_deadline = document
-            //     .nodes_past_deadline()
-            //     .map(|node| AgendaEntry::from_node(document, node, Some(TimestampKind::Deadline)))
-            //     .collect();
-
-            for date in &dates {
-                let entries = agenda.entries.entry(*date).or_insert_with(Vec::new);
-                let new_entries: Vec<AgendaEntry> = document
-                    .nodes_for_date(date)
-                    .filter(|(_timestamp, node)| !node.is_habit())
-                    .map(|(timestamp, node)| AgendaEntry::from_node(document, node, timestamp))
-                    .collect();
-                entries.extend(new_entries);
+            for node in document.all_nodes() {
+                if !node.is_done() {
+                    agenda.add_past_and_upcoming(document, node);
+                }
+                if node.is_habit() {
+                    continue;
+                }
+
+                for date in &dates {
+                    let entries = agenda.entries.entry(*date).or_default();
+                    entries.extend(
+                        node.timestamps_for_date_at(date, today)
+                            .filter(|timestamp| !is_delayed(timestamp, node))
+                            .filter_map(|timestamp| AgendaEntry::from_node(document, node, timestamp))
+                    );
+
+                    // After today, a delayed occurrence shows up on the day its delay runs out
+                    if *date > today && !node.is_done() {
+                        let delayed = node.scheduled_for
+                            .as_ref()
+                            .and_then(|scheduled| delayed_occurrence(scheduled, date, today));
+                        if let Some(scheduled) = delayed {
+                            entries.extend(AgendaEntry::from_node(document, node, scheduled));
+                        }
+                    }
+                }
             }
         }
-        for date in &dates {
-            let entries = agenda.entries.get_mut(date).unwrap();
+        agenda.past_scheduled.sort();
+        agenda.past_deadline.sort();
+        agenda.upcoming_deadlines.sort();
+        for entries in agenda.entries.values_mut() {
             entries.sort();
         }
         agenda
     }
 
+    /// Adds a node that isn't done to the overdue items and deadline warnings
+    fn add_past_and_upcoming(&mut self, document: &Document, node: &Node) {
+        let today = self.today;
+        if let Some(ref scheduled) = node.scheduled_for {
+            let shown_from = scheduled.date + Duration::days(scheduled_delay(scheduled, &scheduled.date));
+            if scheduled.date < today && shown_from <= today {
+                self.past_scheduled.extend(AgendaEntry::from_node(document, node, scheduled.clone()));
+            }
+        }
+        if let Some(ref deadline) = node.deadline {
+            let warning_days = match deadline.delay {
+                Some(ref delay) => delay.num_days(),
+                None => i64::from(document.settings.deadline_warning_days)
+            };
+            if deadline.date < today {
+                self.past_deadline.extend(AgendaEntry::from_node(document, node, deadline.clone()));
+            } else if deadline.date > today && deadline.date - Duration::days(warning_days) <= today {
+                self.upcoming_deadlines.extend(AgendaEntry::from_node(document, node, deadline.clone()));
+            }
+        }
+    }
+
     pub fn dates(&self) -> impl Iterator<Item=Date> + '_ {
         let range = match self.range {
-            AgendaRange::Day => (0..1),
-            AgendaRange::Week => (0..7)
+            AgendaRange::Day => 0..1,
+            AgendaRange::Week => 0..7
         };
         range.map(move |offset| {
-            let date = self.start_date.clone();
-            date + Duration::days(offset)
+            self.start_date + Duration::days(offset)
         })
     }
 
@@ -77,12 +122,49 @@ impl Agenda {
     }
 }
 
+/// The number of days a scheduled timestamp is hidden for, from a delay such as `-2d`. A delay
+/// with `--` only applies to the first occurrence of a repeating timestamp.
+fn scheduled_delay(timestamp: &Timestamp, first_date: &Date) -> i64 {
+    match timestamp.delay {
+        Some(ref delay) => match delay.mark {
+            DelayMark::All => delay.num_days(),
+            DelayMark::First if timestamp.date == *first_date => delay.num_days(),
+            DelayMark::First => 0
+        },
+        None => 0
+    }
+}
+
+/// The occurrence of a scheduled timestamp whose delay runs out on the date, if there is one
+fn delayed_occurrence(scheduled: &Timestamp, date: &Date, today: Date) -> Option<Timestamp> {
+    let delay = scheduled.delay.as_ref()?.num_days();
+    if delay == 0 {
+        return None;
+    }
+    let occurrence = scheduled.timestamp_for_date_at(&(*date - Duration::days(delay)), today)?;
+    if scheduled_delay(&occurrence, &scheduled.date) > 0 {
+        Some(occurrence)
+    } else {
+        None
+    }
+}
+
+/// Whether a scheduled timestamp on its day is hidden by a delay
+fn is_delayed(timestamp: &Timestamp, node: &Node) -> bool {
+    match (&timestamp.kind, &node.scheduled_for) {
+        (TimestampKind::Scheduled, Some(scheduled)) => scheduled_delay(timestamp, &scheduled.date) > 0,
+        _ => false
+    }
+}
+
 #[derive(PartialEq, Eq)]
 pub struct AgendaEntry {
     pub doc_id: DocumentId,
     pub node_id: NodeId,
     pub headline: Headline,
     pub category: String,
+    /// The tags of the node, including inherited tags
+    pub tags: Vec<String>,
     pub timestamp: Timestamp,
     pub kind: AgendaEntryKind,
     pub time_spent: Duration,
@@ -97,25 +179,28 @@ pub enum AgendaEntryKind {
 }
 
 impl AgendaEntry {
-    fn from_node(document: &Document, node: &Node, timestamp: Timestamp) -> Self {
+    /// Creates an entry for an active timestamp, or returns `None` for inactive timestamps, which
+    /// never appear in the agenda
+    fn from_node(document: &Document, node: &Node, timestamp: Timestamp) -> Option<Self> {
         let category = document.node_category(node.id).unwrap_or("").to_string();
         let kind = match timestamp.kind {
             TimestampKind::Scheduled => AgendaEntryKind::Scheduled,
             TimestampKind::Deadline => AgendaEntryKind::Deadline,
             TimestampKind::Active => AgendaEntryKind::Normal,
-            ref kind => panic!("Unexpected timestamp kind '{:?}' for \"{}\"", kind, node.headline.title)
+            TimestampKind::Closed | TimestampKind::Inactive => return None
         };
 
-        AgendaEntry {
+        Some(AgendaEntry {
             doc_id: document.id,
             node_id: node.id,
             headline: node.headline.clone(),
             category,
+            tags: document.node_tags(node.id).into_iter().map(String::from).collect(),
             timestamp,
             kind,
             time_spent: document.node_time_spent(node.id),
             effort: node.effort()
-        }
+        })
     }
 }
 
@@ -131,3 +216,66 @@ impl PartialOrd for AgendaEntry {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_past_and_upcoming() {
+        let mut library = Library::new();
+        library.add(Document::from_string(
+            "* TODO Overdue\n\
+             SCHEDULED: <2018-10-15 Mon>\n\
+             * TODO Delayed\n\
+             SCHEDULED: <2018-10-16 Tue -3d>\n\
+             * TODO No longer delayed\n\
+             SCHEDULED: <2018-10-14 Sun -2d>\n\
+             * DONE Finished\n\
+             SCHEDULED: <2018-10-10 Wed> DEADLINE: <2018-10-11 Thu>\n\
+             * TODO Missed\n\
+             DEADLINE: <2018-10-12 Fri>\n\
+             * TODO Soon\n\
+             DEADLINE: <2018-10-27 Sat>\n\
+             * TODO Later\n\
+             DEADLINE: <2018-11-20 Tue -5d>\n\
+             * TODO Warned\n\
+             DEADLINE: <2018-10-20 Sat -5d>",
+        ));
+        let today = Date::from_ymd(2018, 10, 17);
+        let agenda = Agenda::new_at(&library, today, AgendaRange::Week, today);
+        let titles = |entries: &[AgendaEntry]| {
+            entries.iter().map(|entry| entry.headline.title.clone()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(titles(&agenda.past_scheduled), vec!["No longer delayed", "Overdue"]);
+        assert_eq!(titles(&agenda.past_deadline), vec!["Missed"]);
+        assert_eq!(titles(&agenda.upcoming_deadlines), vec!["Warned", "Soon"]);
+        assert!(titles(agenda.entries(&Date::from_ymd(2018, 10, 16))).is_empty());
+        assert_eq!(titles(agenda.entries(&Date::from_ymd(2018, 10, 19))), vec!["Delayed"]);
+        assert_eq!(titles(agenda.entries(&Date::from_ymd(2018, 10, 20))), vec!["Warned"]);
+    }
+
+    #[test]
+    fn test_repeating_delays() {
+        let mut library = Library::new();
+        library.add(Document::from_string(
+            "* TODO Every occurrence\n\
+             SCHEDULED: <2018-10-15 Mon +1w -2d>\n\
+             * TODO First occurrence\n\
+             SCHEDULED: <2018-10-15 Mon +1w --2d>",
+        ));
+        let today = Date::from_ymd(2018, 10, 17);
+        let agenda = Agenda::new_at(&library, Date::from_ymd(2018, 10, 22), AgendaRange::Week, today);
+        let entries = |day| {
+            agenda.entries(&Date::from_ymd(2018, 10, day))
+                .iter()
+                .map(|entry| (entry.headline.title.clone(), entry.timestamp.date.day()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(entries(22), vec![("First occurrence".to_string(), 22)]);
+        assert_eq!(entries(24), vec![("Every occurrence".to_string(), 22)]);
+        assert!(entries(23).is_empty());
+    }
+}
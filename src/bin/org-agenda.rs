@@ -1,4 +1,4 @@
-/// org-agenda is a simple command-line application for displaying today's or this week's agenda
+//! org-agenda is a simple command-line application for displaying today's or this week's agenda
 
 extern crate orgmode;
 extern crate colored;
@@ -6,7 +6,7 @@ extern crate chrono;
 
 use std::env;
 use std::path::Path;
-use orgmode::{Library, Agenda, AgendaRange, AgendaEntryKind, Timestamp, today, format_duration};
+use orgmode::{Library, Agenda, AgendaEntry, AgendaRange, AgendaEntryKind, Timestamp, format_duration};
 use colored::Colorize;
 
 fn main() {
@@ -17,6 +17,10 @@ fn main() {
             .expect("Unable to open path");
     }
 
+    for diagnostic in library.diagnostics() {
+        eprintln!("{}", diagnostic);
+    }
+
     let agenda = library.agenda_this_week();
 
     print_agenda(&agenda);
@@ -29,7 +33,7 @@ fn print_agenda(agenda: &Agenda) {
     };
     println!("{}", format!("==================== {} Agenda ====================", title).white().bold());
     let mut first = true;
-    let today = today();
+    let today = agenda.today;
     for date in agenda.dates() {
         let date_format = if first {
             first = false;
@@ -42,46 +46,64 @@ fn print_agenda(agenda: &Agenda) {
                                           date.format(date_format)).bold().color(color));
 
         if date == today {
+            // Leaders like in Org's `org-agenda-scheduled-leaders` and `org-agenda-deadline-leaders`
             for entry in agenda.past_scheduled.iter() {
-
+                let days = (today - entry.timestamp.date).num_days();
+                print_entry(entry, &format!("Sched.{:2}x:", days + 1));
             }
             for entry in agenda.past_deadline.iter() {
-
+                let days = (today - entry.timestamp.date).num_days();
+                print_entry(entry, &format!("{:2} d. ago:", days));
+            }
+            for entry in agenda.upcoming_deadlines.iter() {
+                let days = (entry.timestamp.date - today).num_days();
+                print_entry(entry, &format!("In {:3} d.:", days));
             }
         }
 
         for entry in agenda.entries(&date) {
-            print!("  {:10}", format!("{}:", entry.category));
-
-            print_time(&entry.timestamp);
+            let leader = match entry.kind {
+                AgendaEntryKind::Deadline => "Deadline:",
+                AgendaEntryKind::Scheduled => "Scheduled:",
+                _ => ""
+            };
+            print_entry(entry, leader);
+        }
+    }
+}
 
-            match entry.kind {
-                AgendaEntryKind::Deadline =>  print!(" Deadline:  "),
-                AgendaEntryKind::Scheduled => print!(" Scheduled: "),
-                _ => {}
-            }
+fn print_entry(entry: &AgendaEntry, leader: &str) {
+    print!("  {:10}", format!("{}:", entry.category));
 
-            if let Some(ref keyword) = entry.headline.keyword {
-                print!(" {}", keyword.blue());
-            }
-            if let Some(ref priority) = entry.headline.priority {
-                print!(" {}", format!("[#{}]", priority).red());
-            }
-            print!(" {}", entry.headline.title);
+    print_time(&entry.timestamp);
 
-            if !entry.time_spent.is_zero() || entry.effort.is_some() {
-                print!(" {}", format!("[{}", format_duration(&entry.time_spent)).bold());
+    if !leader.is_empty() {
+        print!(" {:11}", leader);
+    }
 
-                if let Some(ref effort) = entry.effort {
-                    print!("{}", format!("/{}", format_duration(effort)).bold());
-                }
+    if let Some(ref keyword) = entry.headline.keyword {
+        print!(" {}", keyword.blue());
+    }
+    if let Some(ref priority) = entry.headline.priority {
+        print!(" {}", format!("[#{}]", priority).red());
+    }
+    print!(" {}", entry.headline.title);
 
-                print!("{}", "]".bold());
-            }
+    if !entry.time_spent.is_zero() || entry.effort.is_some() {
+        print!(" {}", format!("[{}", format_duration(&entry.time_spent)).bold());
 
-            println!();
+        if let Some(ref effort) = entry.effort {
+            print!("{}", format!("/{}", format_duration(effort)).bold());
         }
+
+        print!("{}", "]".bold());
     }
+
+    if !entry.tags.is_empty() {
+        print!(" {}", format!(":{}:", entry.tags.join(":")).dimmed());
+    }
+
+    println!();
 }
 
 fn print_time(timestamp: &Timestamp) {
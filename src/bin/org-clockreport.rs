@@ -1,67 +1,110 @@
+//! org-clockreport shows the time clocked in Org files, grouped by project, or exports the
+//! individual clocks as a timesheet
+//!
+//! Usage: `org-clockreport [OPTIONS] FILES...`
+//!
+//! - `--block NAME`: the period to report on, such as `today` (the default), `thisweek` or
+//!   `lastmonth`
+//! - `--from DATE` and `--to DATE`: the first and last day to report on, as `YYYY-MM-DD`,
+//!   instead of a block. Either can be left out to report on all time before or after a day.
+//! - `--maxlevel N`: the deepest level of nodes to show
+//! - `--csv` or `--json`: export each clock in the period instead, with the `--block` or
+//!   `--from` and `--to` period, so only today's clocks are exported by default
+//! - `--property NAME`: a property to include in each exported clock, such as `CLIENT`
+
 extern crate colored;
-extern crate itertools;
 extern crate orgmode;
+extern crate serde_json;
 
 use colored::Colorize;
-use itertools::Itertools;
-use orgmode::{format_duration, Document, Duration, Library, Node};
+use orgmode::{
+    format_duration, today, ClockBlock, ClockGrouping, ClockReportOptions, ClockScope, Date,
+    Duration, Library, Timesheet,
+};
 use std::env;
 use std::path::Path;
+use std::process;
 
 fn main() {
     let mut library = Library::new();
+    let mut options = ClockReportOptions {
+        grouping: ClockGrouping::Tag("PROJECT".to_string()),
+        ..ClockReportOptions::default()
+    };
+    let mut format = None;
+    let mut properties: Vec<String> = Vec::new();
+    let mut from = None;
+    let mut to = None;
+
+    let mut arguments = env::args().skip(1);
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--block" => {
+                let block = arguments.next().unwrap_or_default();
+                options.block = ClockBlock::parse(&block).unwrap_or_else(|| {
+                    eprintln!("Unknown block: {}", block);
+                    process::exit(1);
+                });
+            }
+            "--maxlevel" => {
+                let level = arguments.next().unwrap_or_default();
+                options.max_level = level.parse().unwrap_or_else(|_| {
+                    eprintln!("Invalid max level: {}", level);
+                    process::exit(1);
+                });
+            }
+            "--from" => from = Some(parse_date(arguments.next())),
+            "--to" => to = Some(parse_date(arguments.next())),
+            "--csv" | "--json" => format = Some(argument.clone()),
+            "--property" => properties.extend(arguments.next()),
+            path => library.open(Path::new(path)).expect("Unable to open path"),
+        }
+    }
+
+    if from.is_some() || to.is_some() {
+        // The range includes all of the last day
+        options.block = ClockBlock::Range {
+            start: from.map(|date| date.and_hms(0, 0, 0)),
+            end: to.map(|date| (date + Duration::days(1)).and_hms(0, 0, 0)),
+        };
+    }
 
-    for argument in env::args().skip(1) {
-        library
-            .open(Path::new(&argument))
-            .expect("Unable to open path");
+    for diagnostic in library.diagnostics() {
+        eprintln!("{}", diagnostic);
     }
 
-    let mut total_time_spent = Duration::zero();
+    if let Some(format) = format {
+        let (start, end) = options.block.range(today());
+        let properties: Vec<&str> = properties.iter().map(|name| name.as_str()).collect();
+        let timesheet = Timesheet::new(&library, start, end, &properties);
 
-    for (project_name, nodes) in library
-        .nodes_clocked_to_today()
-        .group_by(|node| get_project_name(&library[node.document_id], node))
-        .into_iter()
-    {
-        let nodes: Vec<&Node> = nodes.collect();
-        let time_spent = nodes
-            .iter()
-            .map(|node| node.time_spent_today())
-            .fold(Duration::zero(), |total, duration| total + duration);
+        if format == "--csv" {
+            print!("{}", timesheet.to_csv());
+        } else {
+            println!("{}", serde_json::to_string_pretty(&timesheet.rows).unwrap());
+        }
+        return;
+    }
 
-        total_time_spent = total_time_spent + time_spent;
+    let report = library.clock_report(&ClockScope::Library, &options);
 
+    for group in report.groups.iter() {
         println!(
             "{}",
             format!(
                 "{}\t[{}]",
-                project_name,
-                format_duration(&round_duration(&time_spent))
+                group.name.as_deref().unwrap_or(""),
+                format_duration(&round_duration(&library, &group.total))
             ).bold()
             .blue()
         );
 
-        let mut other = false;
-        let mut other_duration = Duration::zero();
-
-        for node in nodes.iter() {
-            if node.title() == project_name {
-                other = true;
-                other_duration = other_duration + node.time_spent_today();
-            } else {
-                println!(
-                    " - {}\t{}",
-                    node.title(),
-                    format!("[{}]", format_duration(&node.time_spent_today())).green()
-                );
-            }
-        }
-
-        if other {
+        for row in group.rows.iter() {
             println!(
-                " - General tasks\t{}",
-                format!("[{}]", format_duration(&other_duration)).green()
+                "{}- {}\t{}",
+                " ".repeat(2 * row.level - 1),
+                row.title,
+                format!("[{}]", format_duration(&row.time)).green()
             );
         }
         println!();
@@ -71,19 +114,21 @@ fn main() {
         "{}",
         format!(
             "Total time spent\t[{}]",
-            format_duration(&round_duration(&total_time_spent))
+            format_duration(&round_duration(&library, &report.total))
         ).bold()
         .white()
     );
 }
 
-fn round_duration(duration: &Duration) -> Duration {
-    Duration::minutes(15 * (duration.num_minutes() / 15))
+fn parse_date(argument: Option<String>) -> Date {
+    let argument = argument.unwrap_or_default();
+    Date::parse_from_str(&argument, "%Y-%m-%d").unwrap_or_else(|_| {
+        eprintln!("Invalid date: {}", argument);
+        process::exit(1);
+    })
 }
 
-fn get_project_name<'a>(document: &'a Document, node: &'a Node) -> &'a str {
-    document
-        .parent_with_tag(node.id, "PROJECT")
-        .map(|node| node.title())
-        .unwrap_or_else(|| &document.title)
+fn round_duration(library: &Library, duration: &Duration) -> Duration {
+    let minutes = i64::from(library.settings().clock_rounding_minutes.max(1));
+    Duration::minutes(minutes * (duration.num_minutes() / minutes))
 }
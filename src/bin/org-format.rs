@@ -1,4 +1,4 @@
-/// org-format is a simple command-line interface for reformatting org mode files
+//! org-format is a simple command-line interface for reformatting org mode files
 
 extern crate orgmode;
 
@@ -9,6 +9,9 @@ fn main() {
     for argument in env::args().skip(1) {
         let document = Document::open_file(&argument)
             .expect("Unable to open file");
+        for diagnostic in document.diagnostics() {
+            eprintln!("{}", diagnostic);
+        }
         println!("{}", document);
     }
 }
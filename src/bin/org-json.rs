@@ -1,4 +1,4 @@
-/// org-json is a simple command-line interface for debugging the structure of an org mode document
+//! org-json is a simple command-line interface for debugging the structure of an org mode document
 extern crate orgmode;
 extern crate serde_json;
 
@@ -14,5 +14,9 @@ fn main() {
             .expect("Unable to open path");
     }
 
+    for diagnostic in library.diagnostics() {
+        eprintln!("{}", diagnostic);
+    }
+
     println!("{}", serde_json::to_string(&library).unwrap());
 }
@@ -0,0 +1,238 @@
+use keyword::AffiliatedKeywords;
+use parser::Parser;
+use regex::Regex;
+use std::fmt;
+use utils::StringUtils;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Center,
+    Comment,
+    Example,
+    Export,
+    Quote,
+    Src,
+    Verse,
+    Special,
+}
+
+impl BlockKind {
+    fn from_name(name: &str) -> BlockKind {
+        match name.to_uppercase().as_str() {
+            "CENTER" => BlockKind::Center,
+            "COMMENT" => BlockKind::Comment,
+            "EXAMPLE" => BlockKind::Example,
+            "EXPORT" => BlockKind::Export,
+            "QUOTE" => BlockKind::Quote,
+            "SRC" => BlockKind::Src,
+            "VERSE" => BlockKind::Verse,
+            _ => BlockKind::Special,
+        }
+    }
+
+    /// Whether the first parameter names a language (`SRC`) or backend (`EXPORT`)
+    fn has_language(self) -> bool {
+        self == BlockKind::Src || self == BlockKind::Export
+    }
+}
+
+/// A block is defined as:
+///
+/// ```text
+/// #+BEGIN_NAME PARAMETERS
+/// CONTENTS
+/// #+END_NAME
+/// ```
+///
+/// The contents are kept verbatim, so the block is printed back exactly as it was parsed.
+///
+/// See <https://orgmode.org/worg/dev/org-syntax.html#Blocks> and
+/// <https://orgmode.org/worg/dev/org-syntax.html#Greater_Blocks>
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Block {
+    pub kind: BlockKind,
+    pub name: String,
+    pub language: Option<String>,
+    pub parameters: Option<String>,
+    /// The raw lines between the begin and end lines, each followed by a newline
+    pub contents: String,
+    #[serde(default, skip_serializing_if = "AffiliatedKeywords::is_empty")]
+    pub affiliated: AffiliatedKeywords,
+    #[serde(skip)]
+    indent: usize,
+}
+
+impl Block {
+    pub fn parse(line: &str, parser: &mut Parser) -> Option<Block> {
+        lazy_static! {
+            static ref REGEX: Regex = Regex::new(r#"(?xi)
+                ^
+                \s*
+                \#\+BEGIN_(?P<name>\S+)
+                (\s+(?P<parameters>.*?))?
+                \s*
+                $
+            "#).unwrap();
+        }
+
+        let captures = REGEX.captures(line)?;
+        let name = captures.name("name").unwrap().as_str().to_string();
+        let kind = BlockKind::from_name(&name);
+        let parameters = captures.name("parameters").map(|c| c.as_str());
+
+        let (language, parameters) = match parameters {
+            Some(parameters) if kind.has_language() => {
+                let mut split = parameters.splitn(2, char::is_whitespace);
+                let language = split.next().map(|language| language.to_string());
+                let parameters = split.next()
+                    .map(|parameters| parameters.trim().to_string())
+                    .filter(|parameters| !parameters.is_empty());
+                (language, parameters)
+            }
+            parameters => (None, parameters.map(|parameters| parameters.to_string())),
+        };
+
+        // Without an end line, the begin line is read as plain text instead
+        let end = format!("#+END_{}", name);
+        let is_end = |line: &str| line.trim().eq_ignore_ascii_case(&end);
+        if !parser.has_end_before_headline(is_end) {
+            org_warning!(Unterminated, "Expected `{}` before the next headline", end);
+            return None;
+        }
+
+        let mut contents = String::new();
+        while let Some(line) = parser.next() {
+            if is_end(&line) {
+                break;
+            }
+            contents += &line;
+            contents += "\n";
+        }
+
+        Some(Block {
+            kind,
+            name,
+            language,
+            parameters,
+            contents,
+            affiliated: AffiliatedKeywords::default(),
+            indent: line.indent(),
+        })
+    }
+
+    /// The contents with the comma escapes in front of `*` and `#+` lines removed
+    ///
+    /// See <https://orgmode.org/manual/Literal-Examples.html>
+    pub fn unescaped_contents(&self) -> String {
+        self.contents
+            .lines()
+            .map(|line| {
+                let rest = line.trim_start();
+                let indent = line.len() - rest.len();
+                if rest.starts_with(",*") || rest.starts_with(",#+") {
+                    format!("{}{}\n", &line[..indent], &rest[1..])
+                } else {
+                    format!("{}\n", line)
+                }
+            })
+            .collect()
+    }
+
+    fn keyword(&self, keyword: &str) -> String {
+        if self.name.chars().any(|c| c.is_lowercase()) {
+            keyword.to_lowercase()
+        } else {
+            keyword.to_string()
+        }
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let indent = " ".repeat(self.indent);
+
+        write!(f, "{}", self.affiliated)?;
+        write!(f, "{}{}{}", indent, self.keyword("#+BEGIN_"), self.name)?;
+        if let Some(ref language) = self.language {
+            write!(f, " {}", language)?;
+        }
+        if let Some(ref parameters) = self.parameters {
+            write!(f, " {}", parameters)?;
+        }
+        write!(f, "\n{}", self.contents)?;
+        write!(f, "{}{}{}", indent, self.keyword("#+END_"), self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Option<Block> {
+        let mut parser = Parser::from_string(source);
+        let line = parser.next().unwrap();
+        Block::parse(&line, &mut parser)
+    }
+
+    #[test]
+    fn test_parse_src_block() {
+        let block = parse("#+BEGIN_SRC rust -n :tangle main.rs\nfn main() {\n    println!(\"*hi*\");\n}\n#+END_SRC").unwrap();
+
+        assert_eq!(block.kind, BlockKind::Src);
+        assert_eq!(block.language, Some("rust".to_string()));
+        assert_eq!(block.parameters, Some("-n :tangle main.rs".to_string()));
+        assert_eq!(block.contents, "fn main() {\n    println!(\"*hi*\");\n}\n");
+    }
+
+    #[test]
+    fn test_parse_lowercase_block() {
+        let block = parse("#+begin_quote\nSome quote\n#+end_quote").unwrap();
+
+        assert_eq!(block.kind, BlockKind::Quote);
+        assert_eq!(block.language, None);
+        assert_eq!(block.parameters, None);
+        assert_eq!(block.contents, "Some quote\n");
+    }
+
+    #[test]
+    fn test_not_a_block() {
+        assert!(parse("#+TITLE: Not a block").is_none());
+        assert!(parse("# A comment").is_none());
+    }
+
+    #[test]
+    fn test_unterminated_block() {
+        let mut parser = Parser::from_string("#+BEGIN_SRC rust\nfn x() {}\n* Heading\n#+END_SRC");
+        let line = parser.next().unwrap();
+
+        assert!(Block::parse(&line, &mut parser).is_none());
+        assert_eq!(parser.next(), Some("fn x() {}".to_string()));
+        assert!(parse("#+BEGIN_QUOTE\nNo end").is_none());
+        assert!(parse("#+BEGIN_QUOTE\n***\n#+END_QUOTE").is_none());
+    }
+
+    #[test]
+    fn test_unescaped_contents() {
+        let block = parse("#+BEGIN_EXAMPLE\n,* Not a headline\n  ,#+TITLE: indented\n,,* kept\n#+END_EXAMPLE").unwrap();
+
+        assert_eq!(block.unescaped_contents(), "* Not a headline\n  #+TITLE: indented\n,,* kept\n");
+    }
+
+    #[test]
+    fn test_parse_and_display_blocks() {
+        let blocks = [
+            "#+BEGIN_SRC rust\nfn main() {\n\n    let x = 1;\n}\n#+END_SRC",
+            "#+begin_example\n  indented   text\n#+end_example",
+            "#+BEGIN_EXPORT html\n<b>bold</b>\n#+END_EXPORT",
+            "#+BEGIN_VERSE\n  Great clouds overhead\n#+END_VERSE",
+            "  #+BEGIN_CENTER\n  centered\n  #+END_CENTER",
+            "#+BEGIN_COMMENT\n#+END_COMMENT",
+            "#+BEGIN_QUOTE\n\n#+END_QUOTE",
+        ];
+
+        for block in blocks.iter() {
+            assert_eq!(&parse(block).unwrap().to_string(), block);
+        }
+    }
+}
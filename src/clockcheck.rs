@@ -0,0 +1,222 @@
+use document::DocumentId;
+use library::Library;
+use logbook::ClockEntry;
+use node::NodeId;
+use std::fmt;
+use std::path::PathBuf;
+use timestamp::{format_duration, DateTime, Duration};
+
+/// Where a `CLOCK:` line is, for reporting problems with it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClockLocation {
+    pub document_id: DocumentId,
+    pub node_id: NodeId,
+    pub path: Option<PathBuf>,
+    /// The name of the document, which identifies it when it wasn't opened from a file
+    pub name: String,
+    /// The 1-based line number, if the clock was parsed from a file
+    pub line: Option<u32>,
+}
+
+impl fmt::Display for ClockLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.path {
+            Some(ref path) => write!(f, "{}", path.display())?,
+            None if self.name.is_empty() => write!(f, "document {}", self.document_id)?,
+            None => write!(f, "{}", self.name)?,
+        }
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClockProblem {
+    /// The clock overlaps with an earlier clock, which may be in another file
+    Overlap { other: ClockLocation },
+    /// The clock was started but never stopped. Only the most recent clock in the library is
+    /// expected to be running, and only for less than the maximum duration.
+    Dangling,
+    /// The clock ran for longer than the maximum duration
+    TooLong { duration: Duration },
+    /// The clock was stopped before it was started
+    EndBeforeStart,
+    /// The duration after `=>` doesn't match the start and end, usually because one of them was
+    /// edited by hand
+    WrongDuration { recorded: Duration, actual: Duration },
+}
+
+impl fmt::Display for ClockProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClockProblem::Overlap { other } => write!(f, "Clock overlaps with the clock at {}", other),
+            ClockProblem::Dangling => write!(f, "Clock was never stopped"),
+            ClockProblem::TooLong { duration } => {
+                write!(f, "Clock ran for {}", format_duration(duration))
+            }
+            ClockProblem::EndBeforeStart => write!(f, "Clock ends before it starts"),
+            ClockProblem::WrongDuration { recorded, actual } => write!(
+                f,
+                "Clock records {} but ran for {}",
+                format_duration(recorded),
+                format_duration(actual)
+            ),
+        }
+    }
+}
+
+/// A problem with a `CLOCK:` line, found by `check_clocks`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClockIssue {
+    pub location: ClockLocation,
+    pub problem: ClockProblem,
+}
+
+impl fmt::Display for ClockIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.problem)
+    }
+}
+
+/// Checks every clock in the library for overlapping, dangling, overly long, negative and
+/// miscounted entries. Issues are sorted by document and line.
+///
+/// A running clock is dangling if a later clock was started anywhere in the library, or if it
+/// was started more than `max_duration` before `now`.
+pub fn check_clocks(library: &Library, max_duration: Duration, now: &DateTime) -> Vec<ClockIssue> {
+    let mut clocks: Vec<(ClockLocation, &ClockEntry)> = Vec::new();
+    for document in library.documents() {
+        for node in document.all_nodes() {
+            for clock in node.logbook().clocks() {
+                let location = ClockLocation {
+                    document_id: document.id,
+                    node_id: node.id,
+                    path: document.path.clone(),
+                    name: document.name(),
+                    line: clock.line,
+                };
+                clocks.push((location, clock));
+            }
+        }
+    }
+    clocks.retain(|(_, clock)| clock.start().is_some());
+    clocks.sort_by_key(|(location, clock)| (clock.start(), location.document_id, location.line));
+
+    let mut issues = Vec::new();
+    let latest_start = clocks.iter().filter_map(|(_, clock)| clock.start()).max();
+    // The clock that has ended the latest so far, which the next clock must not start before
+    let mut latest_end: Option<(DateTime, &ClockLocation)> = None;
+
+    for (location, clock) in clocks.iter() {
+        let start = clock.start().unwrap();
+        let mut report = |problem| {
+            issues.push(ClockIssue {
+                location: location.clone(),
+                problem,
+            })
+        };
+
+        let end = match clock.end() {
+            Some(end) => end,
+            None => {
+                if Some(start) < latest_start || now.signed_duration_since(start) > max_duration {
+                    report(ClockProblem::Dangling);
+                }
+                continue;
+            }
+        };
+        let actual = end.signed_duration_since(start);
+
+        if end < start {
+            report(ClockProblem::EndBeforeStart);
+        } else if actual > max_duration {
+            report(ClockProblem::TooLong { duration: actual });
+        }
+        if let Some(recorded) = clock.duration {
+            if recorded.num_minutes() != actual.num_minutes() {
+                report(ClockProblem::WrongDuration { recorded, actual });
+            }
+        }
+
+        if end > start {
+            match latest_end {
+                Some((latest, other)) if start < latest => {
+                    report(ClockProblem::Overlap { other: other.clone() });
+                    if end > latest {
+                        latest_end = Some((end, location));
+                    }
+                }
+                _ => latest_end = Some((end, location)),
+            }
+        }
+    }
+
+    issues.sort_by_key(|issue| (issue.location.document_id, issue.location.line));
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use document::Document;
+    use timestamp::Date;
+
+    #[test]
+    fn test_check_clocks() {
+        let mut library = Library::new();
+        library.add(Document::from_string(
+            "#+TITLE: Work\n\
+             * Website\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 09:00]--[2018-10-27 Sat 10:30] =>  1:00\n\
+             CLOCK: [2018-10-26 Fri 09:00]--[2018-10-26 Fri 23:00] => 14:00\n\
+             CLOCK: [2018-10-25 Thu 09:00]\n\
+             :END:\n\
+             * Email\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 20:00]--[2018-10-27 Sat 19:30] => -0:30\n\
+             CLOCK: [2018-10-28 Sun 08:00]\n\
+             :END:",
+        ));
+        library.add(Document::from_string(
+            "#+TITLE: Home\n\
+             * Taxes\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 10:00]--[2018-10-27 Sat 11:00] =>  1:00\n\
+             :END:",
+        ));
+        let now = Date::from_ymd(2018, 10, 28).and_hms(12, 0, 0);
+
+        let issues: Vec<String> = check_clocks(&library, Duration::hours(10), &now)
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                "Work:4: Clock records 1:00 but ran for 1:30",
+                "Work:5: Clock ran for 14:00",
+                "Work:6: Clock was never stopped",
+                "Work:10: Clock ends before it starts",
+                "Home:4: Clock overlaps with the clock at Work:4",
+            ]
+        );
+
+        let issues = check_clocks(&library, Duration::hours(15), &now);
+        assert_eq!(issues.len(), 4);
+        assert_eq!(
+            issues[3].problem,
+            ClockProblem::Overlap {
+                other: ClockLocation {
+                    document_id: 0,
+                    node_id: library[0].root_ids().next().unwrap(),
+                    path: None,
+                    name: "Work".to_string(),
+                    line: Some(4),
+                },
+            }
+        );
+    }
+}
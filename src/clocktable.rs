@@ -0,0 +1,550 @@
+use chrono::{Datelike, Duration};
+use diagnostic;
+use document::{Document, DocumentId};
+use dynamic_block::DynamicBlock;
+use keyword::Keyword;
+use library::Library;
+use node::NodeId;
+use std::collections::{BTreeMap, HashMap};
+use table::Table;
+use timestamp::{format_duration, Date, DateTime, Time, Timestamp, TimestampKind};
+
+/// The period of time that a clock report covers, like the `:block` parameter of a clocktable
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClockBlock {
+    Today,
+    Yesterday,
+    /// The week starting on Monday
+    ThisWeek,
+    LastWeek,
+    ThisMonth,
+    LastMonth,
+    ThisYear,
+    LastYear,
+    /// From the start up to, but not including, the end. Without a start or an end, the range is
+    /// open on that side.
+    Range { start: Option<DateTime>, end: Option<DateTime> },
+}
+
+impl ClockBlock {
+    /// Parses a block name, such as `today` or `lastmonth`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "today" => Some(ClockBlock::Today),
+            "yesterday" => Some(ClockBlock::Yesterday),
+            "thisweek" => Some(ClockBlock::ThisWeek),
+            "lastweek" => Some(ClockBlock::LastWeek),
+            "thismonth" => Some(ClockBlock::ThisMonth),
+            "lastmonth" => Some(ClockBlock::LastMonth),
+            "thisyear" => Some(ClockBlock::ThisYear),
+            "lastyear" => Some(ClockBlock::LastYear),
+            _ => None,
+        }
+    }
+
+    /// The start and the end of the block, relative to the given day
+    pub fn range(&self, today: Date) -> (Option<DateTime>, Option<DateTime>) {
+        let week_start = today - Duration::days(i64::from(today.weekday().num_days_from_monday()));
+        let month_start = Date::from_ymd(today.year(), today.month(), 1);
+        let year_start = Date::from_ymd(today.year(), 1, 1);
+
+        let (start, end) = match self {
+            ClockBlock::Range { start, end } => return (*start, *end),
+            ClockBlock::Today => (today, today + Duration::days(1)),
+            ClockBlock::Yesterday => (today - Duration::days(1), today),
+            ClockBlock::ThisWeek => (week_start, week_start + Duration::weeks(1)),
+            ClockBlock::LastWeek => (week_start - Duration::weeks(1), week_start),
+            ClockBlock::ThisMonth => (month_start, next_month(month_start)),
+            ClockBlock::LastMonth => (previous_month(month_start), month_start),
+            ClockBlock::ThisYear => (year_start, Date::from_ymd(today.year() + 1, 1, 1)),
+            ClockBlock::LastYear => (Date::from_ymd(today.year() - 1, 1, 1), year_start),
+        };
+        (Some(start.and_hms(0, 0, 0)), Some(end.and_hms(0, 0, 0)))
+    }
+}
+
+fn next_month(date: Date) -> Date {
+    if date.month() == 12 {
+        Date::from_ymd(date.year() + 1, 1, 1)
+    } else {
+        Date::from_ymd(date.year(), date.month() + 1, 1)
+    }
+}
+
+fn previous_month(date: Date) -> Date {
+    if date.month() == 1 {
+        Date::from_ymd(date.year() - 1, 12, 1)
+    } else {
+        Date::from_ymd(date.year(), date.month() - 1, 1)
+    }
+}
+
+/// Which nodes a clock report includes, like the `:scope` parameter of a clocktable
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClockScope {
+    Library,
+    File(DocumentId),
+    /// A node and its descendants
+    Subtree(DocumentId, NodeId),
+}
+
+/// How the rows of a clock report are grouped
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClockGrouping {
+    /// A group for each document, like the separate tables of a clocktable
+    File,
+    /// A group for the closest node with the tag, such as `PROJECT`. Nodes without the tag are
+    /// grouped by the name of their document.
+    Tag(String),
+    Category,
+    /// A group for each value of the property. Nodes without the property are grouped together.
+    Property(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ClockReportOptions {
+    pub block: ClockBlock,
+    /// Nodes below this level are left out, and their time is counted in their parents
+    pub max_level: usize,
+    pub grouping: ClockGrouping,
+}
+
+impl ClockReportOptions {
+    /// The options of a `clocktable` dynamic block, from its `:block`, `:tstart`, `:tend` and
+    /// `:maxlevel` parameters. Without a block or start and end, all clocked time is included.
+    ///
+    /// Returns `None` with a warning for a block name or timestamp that isn't supported, such as
+    /// a relative timestamp like `<-1w>`, rather than silently covering the wrong period. Any
+    /// other parameter, such as `:step day`, is warned about too, since the report would leave
+    /// it out.
+    pub fn from_dynamic_block(block: &DynamicBlock) -> Option<Self> {
+        // `:scope` is read by `Document::update_dynamic_blocks`
+        const SUPPORTED: &[&str] = &[":block", ":tstart", ":tend", ":maxlevel", ":scope"];
+
+        let unsupported: Vec<String> = block
+            .parameter_names()
+            .into_iter()
+            .filter(|name| !SUPPORTED.iter().any(|supported| name.eq_ignore_ascii_case(supported)))
+            .collect();
+        for name in unsupported.iter() {
+            org_warning!(Unsupported, "Unsupported clocktable parameter `{}`", name);
+        }
+        if !unsupported.is_empty() {
+            return None;
+        }
+
+        let mut options = ClockReportOptions::default();
+        let datetime = |key: &str| match block.parameter(key) {
+            // The warning of the timestamp parser is replaced with one that names the parameter
+            Some(value) => match diagnostic::collect(|| Timestamp::parse(&value)).0 {
+                Some(timestamp) => {
+                    let time = timestamp.time.unwrap_or_else(|| Time::from_hms(0, 0, 0));
+                    Ok(Some(timestamp.date.and_time(time)))
+                }
+                None => {
+                    org_warning!(Unsupported, "Expected an absolute timestamp for {}, found `{}`", key, value);
+                    Err(())
+                }
+            },
+            None => Ok(None),
+        };
+
+        if let Some(level) = block.parameter(":maxlevel").and_then(|level| level.parse().ok()) {
+            options.max_level = level;
+        }
+        options.block = match block.parameter(":block") {
+            Some(name) => match ClockBlock::parse(&name) {
+                Some(clock_block) => clock_block,
+                None => {
+                    org_warning!(Unsupported, "Unsupported clocktable block `{}`", name);
+                    return None;
+                }
+            },
+            None => ClockBlock::Range {
+                start: datetime(":tstart").ok()?,
+                end: datetime(":tend").ok()?,
+            },
+        };
+        Some(options)
+    }
+}
+
+impl Default for ClockReportOptions {
+    fn default() -> Self {
+        ClockReportOptions {
+            block: ClockBlock::Today,
+            max_level: 3,
+            grouping: ClockGrouping::File,
+        }
+    }
+}
+
+/// The time clocked to nodes during a period of time, see
+/// <https://orgmode.org/manual/The-clock-table.html>
+///
+/// Clock entries that are only partly in the period are cut off at its start and end. Clocks
+/// that are still running are left out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClockReport {
+    pub start: Option<DateTime>,
+    pub end: Option<DateTime>,
+    pub groups: Vec<ClockGroup>,
+    /// The time clocked on each day of the period, with clocks that run past midnight split
+    /// between the days
+    pub days: Vec<(Date, Duration)>,
+    pub total: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClockGroup {
+    /// The name of the document, tagged node, category or property value, or `None` for the
+    /// nodes without a property
+    pub name: Option<String>,
+    /// The nodes with clocked time in the group, in document order
+    pub rows: Vec<ClockRow>,
+    pub total: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClockRow {
+    pub document_id: DocumentId,
+    pub node_id: NodeId,
+    /// The level of the node in its document, starting from 1
+    pub level: usize,
+    pub title: String,
+    /// The time clocked to the node and its descendants that are in the same group
+    pub time: Duration,
+}
+
+impl ClockReport {
+    pub fn new(library: &Library, scope: &ClockScope, options: &ClockReportOptions, today: Date) -> Self {
+        let roots: Vec<(&Document, Option<NodeId>)> = match scope {
+            ClockScope::Library => {
+                let mut documents: Vec<&Document> = library.documents().collect();
+                documents.sort_by_key(|document| document.id);
+                documents.into_iter().map(|document| (document, None)).collect()
+            }
+            ClockScope::File(document_id) => {
+                library.document(*document_id).map(|document| (document, None)).into_iter().collect()
+            }
+            ClockScope::Subtree(document_id, node_id) => library
+                .document(*document_id)
+                .filter(|document| document.node(*node_id).is_some())
+                .map(|document| (document, Some(*node_id)))
+                .into_iter()
+                .collect(),
+        };
+        ClockReport::build(&roots, options, today)
+    }
+
+    /// A report for a single document, or for a subtree of it
+    pub fn for_document(
+        document: &Document,
+        subtree: Option<NodeId>,
+        options: &ClockReportOptions,
+        today: Date,
+    ) -> Self {
+        ClockReport::build(&[(document, subtree)], options, today)
+    }
+
+    fn build(roots: &[(&Document, Option<NodeId>)], options: &ClockReportOptions, today: Date) -> Self {
+        let (start, end) = options.block.range(today);
+        let mut report = ClockReport {
+            start,
+            end,
+            groups: Vec::new(),
+            days: Vec::new(),
+            total: Duration::zero(),
+        };
+        let mut days: BTreeMap<Date, Duration> = BTreeMap::new();
+
+        for &(document, subtree) in roots {
+            let node_ids: Vec<NodeId> = match subtree {
+                Some(id) => Some(id).into_iter().chain(document.descendant_ids(id)).collect(),
+                None => document.all_ids().collect(),
+            };
+
+            // The time clocked to each node itself, along with its group
+            let mut own_time: HashMap<NodeId, (Option<String>, Duration)> = HashMap::new();
+            for &id in node_ids.iter() {
+                let mut time = Duration::zero();
+                for clock in document.node_logbook(id).clocks() {
+                    let (clock_start, clock_end) = match clock.clipped(start, end) {
+                        Some(clipped) => clipped,
+                        None => continue,
+                    };
+                    for (day, duration) in split_by_day(clock_start, clock_end) {
+                        let day_total = days.entry(day).or_insert_with(Duration::zero);
+                        *day_total = *day_total + duration;
+                        time = time + duration;
+                    }
+                }
+                if time > Duration::zero() {
+                    own_time.insert(id, (group_name(document, id, &options.grouping), time));
+                }
+            }
+
+            let mut names: Vec<Option<String>> =
+                own_time.values().map(|(name, _)| name.clone()).collect();
+            names.sort();
+            names.dedup();
+
+            for name in names {
+                let rows = clock_rows(document, &node_ids, &own_time, &name, options.max_level);
+                let total = group_total(&own_time, &name);
+
+                match report.groups.iter_mut().find(|group| group.name == name) {
+                    Some(group) => {
+                        group.rows.extend(rows);
+                        group.total = group.total + total;
+                    }
+                    None => report.groups.push(ClockGroup { name, rows, total }),
+                }
+            }
+        }
+
+        if options.grouping != ClockGrouping::File {
+            // Groups are sorted by name, with the nodes that aren't in a group last
+            report.groups.sort_by(|a, b| match (&a.name, &b.name) {
+                (Some(a), Some(b)) => a.cmp(b),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            });
+        }
+        report.total = report.groups.iter().fold(Duration::zero(), |total, group| total + group.total);
+        report.days = days.into_iter().collect();
+        report
+    }
+
+    /// The report as an Org table, laid out like the contents of a clocktable block, with a
+    /// time column for each level
+    pub fn to_table(&self, now: &DateTime) -> Table {
+        let levels = self
+            .groups
+            .iter()
+            .flat_map(|group| group.rows.iter().map(|row| row.level))
+            .max()
+            .unwrap_or(1);
+        let row = |title: String, time: String, level: usize| {
+            let mut cells = vec![String::new(); levels + 1];
+            cells[0] = title;
+            cells[level] = time;
+            cells
+        };
+
+        let mut table = Table::default();
+        let caption = format!(
+            "Clock summary at {}",
+            Timestamp::from_datetime(now, TimestampKind::Inactive)
+        );
+        table.affiliated.push(Keyword::new("CAPTION", &caption));
+
+        table.push_row(&["Headline", "Time"]);
+        table.push_rule();
+        table.push_row(&row("*Total time*".to_string(), format!("*{}*", format_duration(&self.total)), 1));
+        table.push_rule();
+
+        for group in self.groups.iter() {
+            if self.groups.len() > 1 {
+                let name = group.name.as_deref().unwrap_or("Other");
+                table.push_row(&row(format!("*{}*", name), format!("*{}*", format_duration(&group.total)), 1));
+            }
+            for clock_row in group.rows.iter() {
+                let title = if clock_row.level > 1 {
+                    format!("\\_{}{}", " ".repeat(2 * (clock_row.level - 1)), clock_row.title)
+                } else {
+                    clock_row.title.clone()
+                };
+                table.push_row(&row(title, format_duration(&clock_row.time), clock_row.level));
+            }
+        }
+        table
+    }
+}
+
+fn group_name(document: &Document, id: NodeId, grouping: &ClockGrouping) -> Option<String> {
+    match grouping {
+        ClockGrouping::File => Some(document.name()),
+        ClockGrouping::Tag(tag) => {
+            // The tag may come from `#+FILETAGS`, or be excluded from inheritance
+            let node = if document.node_tags(id).contains(&tag.as_str()) {
+                document.parent_with_tag(id, tag)
+            } else {
+                None
+            };
+            Some(node.map(|node| node.title().to_string()).unwrap_or_else(|| document.name()))
+        }
+        ClockGrouping::Category => document.node_category(id).map(|category| category.to_string()),
+        ClockGrouping::Property(name) => document.node_property(id, name),
+    }
+}
+
+fn group_total(own_time: &HashMap<NodeId, (Option<String>, Duration)>, name: &Option<String>) -> Duration {
+    own_time
+        .values()
+        .filter(|(group, _)| group == name)
+        .fold(Duration::zero(), |total, (_, time)| total + *time)
+}
+
+/// The rows of a group, where the time of each node includes the time of its descendants in the
+/// same group
+fn clock_rows(
+    document: &Document,
+    node_ids: &[NodeId],
+    own_time: &HashMap<NodeId, (Option<String>, Duration)>,
+    name: &Option<String>,
+    max_level: usize,
+) -> Vec<ClockRow> {
+    let time_in_group = |id: NodeId| match own_time.get(&id) {
+        Some((group, time)) if group == name => *time,
+        _ => Duration::zero(),
+    };
+
+    node_ids
+        .iter()
+        .filter_map(|&id| {
+            let level = document.ancestor_ids(id).count() + 1;
+            if level > max_level {
+                return None;
+            }
+            let time = document
+                .descendant_ids(id)
+                .map(time_in_group)
+                .fold(time_in_group(id), |total, time| total + time);
+            if time == Duration::zero() {
+                return None;
+            }
+
+            Some(ClockRow {
+                document_id: document.id,
+                node_id: id,
+                level,
+                title: document.node(id)?.title().to_string(),
+                time,
+            })
+        })
+        .collect()
+}
+
+/// Splits the time between the start and the end at each midnight, returning nothing if the end
+/// is before the start
+pub fn split_by_day(start: DateTime, end: DateTime) -> Vec<(Date, Duration)> {
+    let mut days = Vec::new();
+    let mut start = start;
+
+    while start < end {
+        let midnight = (start.date() + Duration::days(1)).and_hms(0, 0, 0);
+        let day_end = midnight.min(end);
+        days.push((start.date(), day_end.signed_duration_since(start)));
+        start = day_end;
+    }
+    days
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_by_day() {
+        let start = Date::from_ymd(2018, 10, 27).and_hms(22, 0, 0);
+        let end = Date::from_ymd(2018, 10, 29).and_hms(1, 30, 0);
+
+        assert_eq!(
+            split_by_day(start, end),
+            vec![
+                (Date::from_ymd(2018, 10, 27), Duration::hours(2)),
+                (Date::from_ymd(2018, 10, 28), Duration::hours(24)),
+                (Date::from_ymd(2018, 10, 29), Duration::minutes(90)),
+            ]
+        );
+        assert!(split_by_day(end, start).is_empty());
+    }
+
+    #[test]
+    fn test_block_range() {
+        let today = Date::from_ymd(2018, 1, 10);
+        let day = |year, month, day| Some(Date::from_ymd(year, month, day).and_hms(0, 0, 0));
+
+        assert_eq!(ClockBlock::Today.range(today), (day(2018, 1, 10), day(2018, 1, 11)));
+        assert_eq!(ClockBlock::ThisWeek.range(today), (day(2018, 1, 8), day(2018, 1, 15)));
+        assert_eq!(ClockBlock::LastMonth.range(today), (day(2017, 12, 1), day(2018, 1, 1)));
+        assert_eq!(ClockBlock::parse("ThisYear"), Some(ClockBlock::ThisYear));
+        assert_eq!(ClockBlock::parse("someday"), None);
+    }
+
+    #[test]
+    fn test_clock_report() {
+        let mut library = Library::new();
+        let (work_id, _) = library.add(Document::from_string(
+            "#+TITLE: Work\n\
+             * Website :PROJECT:\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-26 Fri 23:00]--[2018-10-27 Sat 01:00] =>  2:00\n\
+             :END:\n\
+             ** Design\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 09:00]--[2018-10-27 Sat 10:30] =>  1:30\n\
+             :END:\n\
+             *** Mockups\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 11:00]--[2018-10-27 Sat 11:45] =>  0:45\n\
+             :END:\n\
+             * Email\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 08:00]--[2018-10-27 Sat 08:15] =>  0:15\n\
+             CLOCK: [2018-10-20 Sat 08:00]--[2018-10-20 Sat 09:00] =>  1:00\n\
+             :END:",
+        ));
+        library.add(Document::from_string(
+            "#+TITLE: Home\n\
+             * Taxes\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 20:00]--[2018-10-28 Sun 00:30] =>  4:30\n\
+             :END:",
+        ));
+        let today = Date::from_ymd(2018, 10, 27);
+        let rows = |group: &ClockGroup| {
+            group
+                .rows
+                .iter()
+                .map(|row| format!("{} {} {}", row.level, row.title, row.time.num_minutes()))
+                .collect::<Vec<_>>()
+        };
+
+        let report = ClockReport::new(&library, &ClockScope::Library, &ClockReportOptions::default(), today);
+        assert_eq!(report.groups.len(), 2);
+        assert_eq!(report.groups[0].name, Some("Work".to_string()));
+        assert_eq!(
+            rows(&report.groups[0]),
+            vec!["1 Website 195", "2 Design 135", "3 Mockups 45", "1 Email 15"]
+        );
+        assert_eq!(report.groups[0].total, Duration::minutes(210));
+        assert_eq!(report.groups[1].total, Duration::minutes(240));
+        assert_eq!(report.total, Duration::minutes(450));
+        assert_eq!(report.days, vec![(today, Duration::minutes(450))]);
+
+        let options = ClockReportOptions {
+            block: ClockBlock::ThisWeek,
+            max_level: 1,
+            grouping: ClockGrouping::Tag("PROJECT".to_string()),
+        };
+        let report = ClockReport::new(&library, &ClockScope::File(work_id), &options, today);
+        assert_eq!(
+            report.groups.iter().map(|group| group.name.clone().unwrap()).collect::<Vec<_>>(),
+            vec!["Website", "Work"]
+        );
+        assert_eq!(rows(&report.groups[0]), vec!["1 Website 255"]);
+        assert_eq!(report.days.len(), 2);
+
+        let website_id = library[work_id].root_ids().next().unwrap();
+        let design_id = library[work_id].child_ids(Some(website_id)).next().unwrap();
+        let report = ClockReport::new(
+            &library,
+            &ClockScope::Subtree(work_id, design_id),
+            &ClockReportOptions { max_level: 5, ..ClockReportOptions::default() },
+            today,
+        );
+        assert_eq!(rows(&report.groups[0]), vec!["2 Design 135", "3 Mockups 45"]);
+        assert_eq!(report.total, Duration::minutes(135));
+    }
+}
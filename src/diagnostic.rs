@@ -0,0 +1,137 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticCode {
+    /// A block or drawer without an end line
+    Unterminated,
+    /// Planning info or a property drawer that doesn't directly follow a headline
+    Misplaced,
+    /// Planning info that is set more than once
+    Duplicate,
+    /// A headline with more stars than its parent allows
+    IndentTooDeep,
+    InvalidTimestamp,
+    /// Syntax that is valid Org, but not supported yet
+    Unsupported,
+    /// An inconsistency in the parser itself
+    Internal,
+}
+
+impl DiagnosticCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DiagnosticCode::Unterminated => "unterminated",
+            DiagnosticCode::Misplaced => "misplaced",
+            DiagnosticCode::Duplicate => "duplicate",
+            DiagnosticCode::IndentTooDeep => "indent-too-deep",
+            DiagnosticCode::InvalidTimestamp => "invalid-timestamp",
+            DiagnosticCode::Unsupported => "unsupported",
+            DiagnosticCode::Internal => "internal",
+        }
+    }
+}
+
+/// A problem found while parsing a document, such as an unterminated block
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub message: String,
+    pub path: Option<PathBuf>,
+    /// The 1-based line number of the last line read when the problem was found
+    pub line: Option<u32>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.path, self.line) {
+            (Some(path), Some(line)) => write!(f, "{}:{}: ", path.display(), line)?,
+            (Some(path), None) => write!(f, "{}: ", path.display())?,
+            (None, Some(line)) => write!(f, "line {}: ", line)?,
+            (None, None) => {}
+        }
+        write!(f, "{}: {} [{}]", self.severity, self.message, self.code.as_str())
+    }
+}
+
+// Diagnostics are reported from deep inside the parser, such as while parsing timestamps, so
+// they are collected per thread rather than threaded through every parse function.
+thread_local! {
+    static DIAGNOSTICS: RefCell<Option<Vec<Diagnostic>>> = const { RefCell::new(None) };
+    static CURRENT_LINE: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+/// Records a diagnostic if diagnostics are being collected, otherwise it is dropped
+pub(crate) fn report(severity: Severity, code: DiagnosticCode, message: String) {
+    DIAGNOSTICS.with(|diagnostics| {
+        if let Some(ref mut diagnostics) = *diagnostics.borrow_mut() {
+            diagnostics.push(Diagnostic {
+                severity,
+                code,
+                message,
+                path: None,
+                line: CURRENT_LINE.with(|line| line.get()),
+            });
+        }
+    });
+}
+
+pub(crate) fn set_current_line(line: u32) {
+    CURRENT_LINE.with(|current_line| current_line.set(Some(line)));
+}
+
+/// Runs `f`, returning its result along with every diagnostic reported while it ran
+pub(crate) fn collect<T>(f: impl FnOnce() -> T) -> (T, Vec<Diagnostic>) {
+    let previous = DIAGNOSTICS.with(|diagnostics| diagnostics.replace(Some(Vec::new())));
+    let previous_line = CURRENT_LINE.with(|line| line.replace(None));
+
+    let result = f();
+
+    let collected = DIAGNOSTICS.with(|diagnostics| diagnostics.replace(previous));
+    CURRENT_LINE.with(|line| line.set(previous_line));
+
+    (result, collected.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collect() {
+        let ((), diagnostics) = collect(|| {
+            set_current_line(3);
+            report(Severity::Warning, DiagnosticCode::Unsupported, "Outer".to_string());
+
+            let ((), inner) = collect(|| {
+                report(Severity::Error, DiagnosticCode::Internal, "Inner".to_string());
+            });
+            assert_eq!(inner.len(), 1);
+            assert_eq!(inner[0].line, None);
+        });
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].to_string(), "line 3: warning: Outer [unsupported]");
+
+        report(Severity::Warning, DiagnosticCode::Unsupported, "Dropped".to_string());
+    }
+}
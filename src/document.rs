@@ -1,16 +1,28 @@
 use headline::Headline;
+use clocktable::{ClockReport, ClockReportOptions};
+use diagnostic::{self, Diagnostic};
+use dynamic_block::DynamicBlock;
+use element::Element;
+use footnote::{FootnoteDefinition, FootnoteReference};
 use itertools::Itertools;
+use keyword::Keyword;
+use link::Link;
+use logbook::{ClockEntry, Logbook, LogbookItem};
 use node::{Node, NodeId};
+use object::Object;
 use parser::Parser;
-use regex::Regex;
+use properties::Properties;
+use query::Query;
 use section::Section;
-use std::collections::HashMap;
+use settings::Settings;
+use source::{Source, Span};
 use std::fmt;
 use std::fs::File;
 use std::io::Error as IoError;
 use std::iter::repeat;
 use std::path::{Path, PathBuf};
-use timestamp::{Date, Duration, Timestamp};
+use timestamp::{now, Date, DateTime, Duration, Timestamp, TimestampKind};
+use todo_keywords::{TodoKeywords, TodoSequenceKind};
 use tree::Tree;
 
 pub type DocumentId = usize;
@@ -21,9 +33,17 @@ pub struct Document {
     pub path: Option<PathBuf>,
     pub title: String,
     pub section: Section,
-    pub properties: HashMap<String, String>,
+    /// The properties that apply to the whole document, from a property drawer before the first
+    /// headline and `#+PROPERTY` keywords
+    pub properties: Properties,
+    /// The settings the document was parsed with, including any in-buffer settings
+    pub settings: Settings,
     #[serde(flatten)]
     tree: Tree<Node>,
+    #[serde(skip)]
+    diagnostics: Vec<Diagnostic>,
+    #[serde(skip)]
+    ends_with_newline: bool,
 }
 
 impl Document {
@@ -33,8 +53,11 @@ impl Document {
             path,
             title: String::new(),
             section: Section::new(),
-            properties: HashMap::new(),
+            properties: Properties::new(),
+            settings: Settings::default(),
             tree: Tree::new(),
+            diagnostics: Vec::new(),
+            ends_with_newline: true,
         }
     }
 
@@ -47,66 +70,214 @@ impl Document {
     }
 
     pub fn open_file(path: impl AsRef<Path>) -> Result<Self, IoError> {
+        Document::open_file_with_settings(path, &Settings::default())
+    }
+
+    pub fn from_string(source: &str) -> Self {
+        Document::from_string_with_settings(source, &Settings::default())
+    }
+
+    /// Opens a file in lossless mode, which keeps the original text of every node and element.
+    /// When printed, anything that hasn't been changed is written back exactly as it was.
+    pub fn open_file_lossless(path: impl AsRef<Path>) -> Result<Self, IoError> {
+        let settings = Settings { lossless: true, ..Settings::default() };
+        Document::open_file_with_settings(path, &settings)
+    }
+
+    /// Parses a string in lossless mode, see `open_file_lossless`
+    pub fn from_string_lossless(source: &str) -> Self {
+        let settings = Settings { lossless: true, ..Settings::default() };
+        Document::from_string_with_settings(source, &settings)
+    }
+
+    pub fn open_file_with_settings(
+        path: impl AsRef<Path>,
+        settings: &Settings,
+    ) -> Result<Self, IoError> {
         let path = path.as_ref();
         let file = File::open(path)?;
-        Document::parse(Some(path.into()), Parser::from_file(file))
+        Document::parse(Some(path.into()), Parser::from_file(file), settings)
     }
 
-    pub fn from_string(source: &str) -> Self {
-        Document::parse(None, Parser::from_string(source)).unwrap()
+    pub fn from_string_with_settings(source: &str, settings: &Settings) -> Self {
+        Document::parse(None, Parser::from_string(source), settings).unwrap()
+    }
+
+    /// The file name of the document, or its title if it wasn't opened from a file
+    pub fn name(&self) -> String {
+        self.path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.title.clone())
+    }
+
+    pub fn is_lossless(&self) -> bool {
+        self.settings.lossless
+    }
+
+    /// The problems found while parsing the document or updating its dynamic blocks
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn parse(path: Option<PathBuf>, parser: Parser, settings: &Settings) -> Result<Self, IoError> {
+        let (document, mut diagnostics) =
+            diagnostic::collect(|| Document::parse_lines(path.clone(), parser, settings));
+
+        for diagnostic in diagnostics.iter_mut() {
+            diagnostic.path = path.clone();
+        }
+
+        document.map(|mut document| {
+            document.diagnostics = diagnostics;
+            document
+        })
     }
 
-    fn parse(path: Option<PathBuf>, mut parser: Parser) -> Result<Self, IoError> {
+    fn parse_lines(
+        path: Option<PathBuf>,
+        mut parser: Parser,
+        settings: &Settings,
+    ) -> Result<Self, IoError> {
         use drawer::Drawer;
         use element::Element;
         use headline::Headline;
         use planning::Planning;
 
-        let todo_keywords = vec![
-            "TODO".to_string(),
-            "IN-PROGRESS".to_string(),
-            "DONE".to_string(),
-        ];
-
         let mut document = Document::new(path);
+        document.settings = settings.clone();
+        let lossless = settings.lossless;
         let mut current_id = None;
+        let mut has_in_buffer_keywords = false;
+        let initial_settings = document.settings.clone();
+        let mut start = parser.offset();
+
+        if lossless {
+            parser.record_raw();
+        }
 
         while let Some(line) = parser.next() {
-            if let Some(headline) = Headline::parse(&line, &todo_keywords) {
+            let line_number = parser.current_line();
+            // Whether the line belongs to the headline, planning info and properties of the
+            // current node rather than its section
+            let is_head = if let Some(headline) = Headline::parse(&line, &document.settings) {
                 current_id = Some(document.add_new_node(current_id, headline));
+                true
             } else if let Some(drawer) = Drawer::parse(&line, &mut parser) {
-                if let Some(properties) = drawer.as_properties() {
-                    if let Some(current_id) = current_id {
-                        // TODO: Must property drawers come immediately after the headline
-                        // and/or planning info?
-                        document.node_mut(current_id).unwrap().properties = properties;
-                    } else {
-                        // TODO: Are property drawers valid outside of a headlien?
+                let section_is_empty = document.section(current_id).unwrap().is_empty();
+
+                match (drawer.as_properties(), current_id) {
+                    (Some(properties), Some(id)) if section_is_empty => {
+                        document.node_mut(id).unwrap().properties = properties;
+                        true
+                    }
+                    (Some(properties), None) => {
+                        // TODO: Are property drawers valid outside of a headline?
                         document.properties.extend(properties);
+                        document.section.add_drawer(drawer);
+                        false
+                    }
+                    (properties, _) => {
+                        if properties.is_some() {
+                            org_warning!(
+                                Misplaced,
+                                "Property drawers must come immediately after the headline"
+                            );
+                        }
+                        let is_logbook =
+                            current_id.is_some() && drawer.name == document.settings.log_drawer;
+                        let section = document.section_mut(current_id).unwrap();
+                        if is_logbook {
+                            let logbook =
+                                Logbook::parse_at(&drawer.name, &drawer.contents, Some(line_number + 1));
+                            section.add_element(Element::Logbook(logbook));
+                        } else {
+                            section.add_drawer(drawer);
+                        }
+                        false
                     }
-                } else {
-                    document.section_mut(current_id).unwrap().add_drawer(drawer);
                 }
             } else if let Some(planning) = Planning::parse(&line) {
                 if let Some(current_id) = current_id {
-                    document
-                        .node_mut(current_id)
-                        .unwrap()
-                        .set_planning(planning, &line);
+                    let node = document.node_mut(current_id).unwrap();
+                    node.set_planning(planning, &line);
+                    node.section.is_empty()
                 } else {
-                    org_warning!("planning info found above first headline");
+                    org_warning!(Misplaced, "Planning info found above first headline");
                     document.section_mut(current_id).unwrap().add_line(&line);
+                    false
                 }
             } else if let Some(element) = Element::parse(&line, &mut parser) {
-                document
-                    .section_mut(current_id)
-                    .unwrap()
-                    .elements
-                    .push(element);
-            } else if let Some(title) = parse_title(&line) {
-                document.title = title;
+                if let Element::Keyword(ref keyword) = element {
+                    if keyword.is("TITLE") {
+                        // Multiple title lines are joined together, like in Emacs
+                        if !document.title.is_empty() {
+                            document.title += " ";
+                        }
+                        document.title += &keyword.value;
+                    } else if let Some(sequence) = TodoKeywords::parse_line(&line) {
+                        // In-buffer keywords replace the default ones, for the whole file
+                        if !has_in_buffer_keywords {
+                            document.settings.todo_keywords = TodoKeywords::new();
+                            has_in_buffer_keywords = true;
+                        }
+                        document.settings.todo_keywords.add_sequence(sequence);
+                    } else if keyword.is("PROPERTY") {
+                        let mut parts = keyword.value.splitn(2, char::is_whitespace);
+                        let name = parts.next().unwrap_or("");
+                        if name.is_empty() {
+                            org_warning!(Unsupported, "Expected a property name");
+                        } else {
+                            document.properties.push(name, parts.next().unwrap_or("").trim());
+                        }
+                    } else if keyword.is("STARTUP") {
+                        document.settings.apply_startup(&keyword.value);
+                    } else if keyword.is("PRIORITIES")
+                        && !document.settings.set_priorities(&keyword.value)
+                    {
+                        org_warning!(Unsupported, "Invalid priorities: {}", keyword.value);
+                    }
+                }
+                document.section_mut(current_id).unwrap().add_element(element);
+                false
             } else {
                 document.section_mut(current_id).unwrap().add_line(&line);
+                false
+            };
+
+            if lossless {
+                let span = Span { start, end: parser.offset() };
+                let source = Source::new(span, parser.take_raw());
+                document.ends_with_newline = source.text.ends_with('\n');
+
+                match current_id {
+                    Some(id) if is_head => document.node_mut(id).unwrap().add_source(source),
+                    _ => document.section_mut(current_id).unwrap().add_source(source),
+                }
+            }
+            start = parser.offset();
+        }
+
+        // In-buffer settings apply to the whole file, so the headlines above them are read again
+        if document.settings.todo_keywords != initial_settings.todo_keywords
+            || document.settings.highest_priority != initial_settings.highest_priority
+            || document.settings.lowest_priority != initial_settings.lowest_priority
+        {
+            let settings = document.settings.clone();
+            for id in document.all_ids().collect::<Vec<_>>() {
+                let node = document.node_mut(id).unwrap();
+                if let Some(headline) = Headline::parse(&node.headline.format(&settings), &settings) {
+                    node.headline = headline;
+                }
+            }
+        }
+
+        if lossless {
+            let settings = document.settings.clone();
+            document.section.finish_sources(&settings);
+            for id in document.all_ids().collect::<Vec<_>>() {
+                document.node_mut(id).unwrap().finish_sources(&settings);
             }
         }
 
@@ -125,14 +296,75 @@ impl Document {
         self.tree.node_mut(id)
     }
 
+    /// All node ids in document order
     pub fn all_ids(&self) -> impl Iterator<Item = NodeId> + '_ {
         self.tree.all_ids()
     }
 
+    /// All nodes in document order
     pub fn all_nodes(&self) -> impl Iterator<Item = &Node> {
         self.tree.all_nodes()
     }
 
+    /// Walks the nodes in document order, along with the depth of each node, starting from 1 for
+    /// top-level nodes
+    pub fn pre_order(&self) -> impl Iterator<Item = (usize, &Node)> {
+        self.tree
+            .pre_order(self.tree.root_id())
+            .map(move |(id, depth)| (depth, &self.tree[id]))
+    }
+
+    /// Walks the nodes so that each node comes after all of its children, along with the depth of
+    /// each node, starting from 1 for top-level nodes
+    pub fn post_order(&self) -> impl Iterator<Item = (usize, &Node)> {
+        self.tree
+            .post_order(self.tree.root_id())
+            .map(move |(id, depth)| (depth, &self.tree[id]))
+    }
+
+    /// The ids of the children, grandchildren and so on of a node in document order
+    pub fn descendant_ids(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.tree.pre_order(id).map(|(id, _)| id)
+    }
+
+    pub fn descendants(&self, id: NodeId) -> impl Iterator<Item = &Node> {
+        self.descendant_ids(id).map(move |id| &self.tree[id])
+    }
+
+    /// The ids of the parent, grandparent and so on of a node, nearest first
+    pub fn ancestor_ids(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        let root_id = self.tree.root_id();
+        self.tree.ancestor_ids(id).take_while(move |&id| id != root_id)
+    }
+
+    pub fn ancestors(&self, id: NodeId) -> impl Iterator<Item = &Node> {
+        self.ancestor_ids(id).map(move |id| &self.tree[id])
+    }
+
+    /// The titles of the ancestors of a node, outermost first, followed by the node's own title
+    pub fn outline_path(&self, id: NodeId) -> Vec<&str> {
+        let mut path: Vec<&str> = self.ancestors(id).map(|node| node.title()).collect();
+        path.reverse();
+        path.extend(self.node(id).map(|node| node.title()));
+        path
+    }
+
+    pub fn next_sibling_id(&self, id: NodeId) -> Option<NodeId> {
+        self.tree.next_sibling_id(id)
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<&Node> {
+        self.next_sibling_id(id).and_then(|id| self.node(id))
+    }
+
+    pub fn prev_sibling_id(&self, id: NodeId) -> Option<NodeId> {
+        self.tree.prev_sibling_id(id)
+    }
+
+    pub fn prev_sibling(&self, id: NodeId) -> Option<&Node> {
+        self.prev_sibling_id(id).and_then(|id| self.node(id))
+    }
+
     pub fn root_ids(&self) -> impl Iterator<Item = NodeId> + '_ {
         self.tree.child_ids(self.tree.root_id())
     }
@@ -166,9 +398,11 @@ impl Document {
     pub fn find_parent(&self, current_id: Option<NodeId>, indent: u16) -> Option<NodeId> {
         let mut parent_id = current_id;
 
-        if parent_id.is_some() && self.node(parent_id.unwrap()).is_none() {
-            org_warning!("Node not found: {}", parent_id.unwrap());
-            return None;
+        if let Some(id) = parent_id {
+            if self.node(id).is_none() {
+                org_warning!(Internal, "Node not found: {}", id);
+                return None;
+            }
         }
 
         while parent_id.is_some() && self.node(parent_id.unwrap()).unwrap().indent >= indent {
@@ -178,10 +412,15 @@ impl Document {
         if let Some(parent_id) = parent_id {
             let expected_indent = self.node(parent_id).unwrap().indent + 1;
             if indent > expected_indent {
-                org_warning!("Indent is too deep: {} > {}", indent, expected_indent);
+                org_warning!(
+                    IndentTooDeep,
+                    "Indent is too deep: {} > {}",
+                    indent,
+                    expected_indent
+                );
             }
         } else if indent > 1 {
-            org_warning!("Indent is too deep: {} > 0", indent);
+            org_warning!(IndentTooDeep, "Indent is too deep: {} > 0", indent);
         }
 
         parent_id
@@ -200,6 +439,170 @@ impl Document {
         new_id
     }
 
+    /// Inserts a new node before a sibling, at the same level as the sibling
+    pub fn insert_before(&mut self, sibling_id: NodeId, headline: Headline) -> Option<NodeId> {
+        let index = self.tree.index_in_parent(sibling_id)?;
+        let parent_id = self.tree.parent_id(sibling_id)?;
+        Some(self.insert_node_at(parent_id, index, headline))
+    }
+
+    /// Inserts a new node after a sibling, at the same level as the sibling
+    pub fn insert_after(&mut self, sibling_id: NodeId, headline: Headline) -> Option<NodeId> {
+        let index = self.tree.index_in_parent(sibling_id)?;
+        let parent_id = self.tree.parent_id(sibling_id)?;
+        Some(self.insert_node_at(parent_id, index + 1, headline))
+    }
+
+    /// Inserts a new node as the last child of a parent, or as the last top-level node for `None`
+    pub fn insert_child(&mut self, parent_id: Option<NodeId>, headline: Headline) -> Option<NodeId> {
+        if let Some(id) = parent_id {
+            self.node(id)?;
+        }
+        let parent_id = parent_id.unwrap_or(self.tree.root_id());
+        Some(self.insert_node_at(parent_id, usize::MAX, headline))
+    }
+
+    fn insert_node_at(&mut self, parent_id: NodeId, index: usize, headline: Headline) -> NodeId {
+        let indent = self.child_indent(parent_id);
+        let new_id = self
+            .tree
+            .insert_node_at(parent_id, index, Node::from_headline(headline));
+        let node = &mut self.tree[new_id];
+        node.document_id = self.id;
+        node.id = new_id;
+        node.set_indent(indent);
+        new_id
+    }
+
+    /// Removes a node along with all of its children
+    pub fn delete_subtree(&mut self, id: NodeId) -> Option<Node> {
+        self.tree.remove_node(id)
+    }
+
+    /// Removes a node along with all of its children, so that it can be inserted elsewhere with
+    /// `insert_subtree`
+    pub fn take_subtree(&mut self, id: NodeId) -> Option<Subtree> {
+        let nodes = self.tree.take_subtree(id);
+        if nodes.is_empty() {
+            None
+        } else {
+            Some(Subtree { nodes })
+        }
+    }
+
+    /// Inserts a subtree taken from this or another document as the last child of a parent, or
+    /// as the last top-level node for `None`. Every node in the subtree gets a new id, and the
+    /// id of its root is returned.
+    pub fn insert_subtree(&mut self, parent_id: Option<NodeId>, subtree: Subtree) -> Option<NodeId> {
+        if let Some(id) = parent_id {
+            self.node(id)?;
+        }
+        let parent_id = parent_id.unwrap_or(self.tree.root_id());
+        // The ids of the most recently inserted node at each depth
+        let mut ancestor_ids: Vec<NodeId> = Vec::new();
+
+        for (depth, mut node) in subtree.nodes {
+            ancestor_ids.truncate(depth);
+            let node_parent_id = ancestor_ids.last().copied().unwrap_or(parent_id);
+
+            node.document_id = self.id;
+            let new_id = self.tree.insert_node(node_parent_id, node);
+            self.tree[new_id].id = new_id;
+            ancestor_ids.push(new_id);
+        }
+
+        let root_id = *ancestor_ids.first()?;
+        let indent = self.child_indent(parent_id);
+        self.set_subtree_indent(root_id, indent);
+        Some(root_id)
+    }
+
+    /// Moves a node along with all of its children to the end of a new parent, or to the end of
+    /// the document for `None`. Returns false if the new parent is inside the subtree.
+    pub fn move_subtree(&mut self, id: NodeId, parent_id: Option<NodeId>) -> bool {
+        let parent_id = parent_id.unwrap_or(self.tree.root_id());
+        if !self.tree.move_node(id, parent_id, usize::MAX) {
+            return false;
+        }
+
+        let indent = self.child_indent(parent_id);
+        self.set_subtree_indent(id, indent);
+        true
+    }
+
+    /// Moves a node up a level, like `org-promote-subtree`. The node is placed after its parent
+    /// and the siblings that followed it become its children, so the order of the document is
+    /// unchanged. Returns false for top-level nodes.
+    pub fn promote_subtree(&mut self, id: NodeId) -> bool {
+        let parent_id = match self.parent_id(id) {
+            Some(parent_id) => parent_id,
+            None => return false,
+        };
+        let grandparent_id = self.tree.parent_id(parent_id).unwrap();
+        let index = self.tree.index_in_parent(parent_id).unwrap();
+        let following_ids: Vec<NodeId> = self.tree.child_ids(parent_id)
+            .skip_while(|&sibling_id| sibling_id != id)
+            .skip(1)
+            .collect();
+
+        let indent = self.tree[parent_id].indent;
+        self.set_subtree_indent(id, indent);
+        for sibling_id in following_ids {
+            self.tree.move_node(sibling_id, id, usize::MAX);
+        }
+        self.tree.move_node(id, grandparent_id, index + 1)
+    }
+
+    /// Moves a node down a level, making it the last child of its previous sibling, like
+    /// `org-demote-subtree`. Returns false for nodes without a previous sibling.
+    pub fn demote_subtree(&mut self, id: NodeId) -> bool {
+        match self.prev_sibling_id(id) {
+            Some(sibling_id) => self.move_subtree(id, Some(sibling_id)),
+            None => false,
+        }
+    }
+
+    /// Swaps a node with its previous sibling, like `org-move-subtree-up`
+    pub fn move_subtree_up(&mut self, id: NodeId) -> bool {
+        match (self.tree.parent_id(id), self.tree.index_in_parent(id)) {
+            (Some(parent_id), Some(index)) if index > 0 => {
+                self.tree.move_node(id, parent_id, index - 1)
+            }
+            _ => false,
+        }
+    }
+
+    /// Swaps a node with its next sibling, like `org-move-subtree-down`
+    pub fn move_subtree_down(&mut self, id: NodeId) -> bool {
+        match (self.tree.parent_id(id), self.tree.index_in_parent(id)) {
+            (Some(parent_id), Some(index)) if self.next_sibling_id(id).is_some() => {
+                self.tree.move_node(id, parent_id, index + 1)
+            }
+            _ => false,
+        }
+    }
+
+    /// The level of the children of a node in the tree, where the root has level 0
+    fn child_indent(&self, parent_id: NodeId) -> u16 {
+        if parent_id == self.tree.root_id() {
+            1
+        } else {
+            self.tree[parent_id].indent.saturating_add(1)
+        }
+    }
+
+    /// Changes the level of a node, shifting its descendants by the same amount
+    fn set_subtree_indent(&mut self, id: NodeId, indent: u16) {
+        let delta = i32::from(indent) - i32::from(self.tree[id].indent);
+        let ids: Vec<NodeId> = Some(id).into_iter().chain(self.descendant_ids(id)).collect();
+
+        for id in ids {
+            let node = &mut self.tree[id];
+            let indent = (i32::from(node.indent) + delta).max(1).min(i32::from(u16::MAX));
+            node.set_indent(indent as u16);
+        }
+    }
+
     pub fn section(&self, id: Option<NodeId>) -> Option<&Section> {
         if let Some(id) = id {
             self.node(id).map(|node| &node.section)
@@ -216,6 +619,95 @@ impl Document {
         }
     }
 
+    /// All keywords in the document, such as `#+AUTHOR` or `#+FILETAGS`. Affiliated keywords are
+    /// not included, as they belong to the element that follows them.
+    pub fn keywords(&self) -> impl Iterator<Item = &Keyword> {
+        let sections = Some(&self.section)
+            .into_iter()
+            .chain(self.all_nodes().map(|node| &node.section));
+
+        sections.flat_map(|section| section.keywords())
+    }
+
+    /// The value of the first keyword with the given key, ignoring case
+    pub fn keyword(&self, key: &str) -> Option<&str> {
+        self.keywords()
+            .find(|keyword| keyword.is(key))
+            .map(|keyword| keyword.value.as_str())
+    }
+
+    /// All links in the document, along with the node that contains each link, if any
+    pub fn links(&self) -> impl Iterator<Item = (Option<&Node>, &Link)> {
+        let section_objects = self.section.objects().map(|object| (None, object));
+        let node_objects = self
+            .all_nodes()
+            .flat_map(|node| node.objects().map(move |object| (Some(node), object)));
+
+        section_objects
+            .chain(node_objects)
+            .filter_map(|(node, object)| match object {
+                Object::Link(link) => Some((node, link)),
+                _ => None,
+            })
+    }
+
+    /// All footnote definitions in the document
+    pub fn footnotes(&self) -> impl Iterator<Item = &FootnoteDefinition> {
+        let sections = Some(&self.section)
+            .into_iter()
+            .chain(self.all_nodes().map(|node| &node.section));
+
+        sections
+            .flat_map(|section| section.elements.iter())
+            .filter_map(|element| match element {
+                Element::FootnoteDefinition(definition) => Some(definition),
+                _ => None,
+            })
+    }
+
+    pub fn footnote(&self, label: &str) -> Option<&FootnoteDefinition> {
+        self.footnotes().find(|definition| definition.label == label)
+    }
+
+    /// All footnote references in the document, along with the node that contains each reference
+    pub fn footnote_references(&self) -> impl Iterator<Item = (Option<&Node>, &FootnoteReference)> {
+        let section_objects = self.section.objects().map(|object| (None, object));
+        let node_objects = self
+            .all_nodes()
+            .flat_map(|node| node.objects().map(move |object| (Some(node), object)));
+
+        section_objects
+            .chain(node_objects)
+            .filter_map(|(node, object)| match object {
+                Object::FootnoteReference(reference) => Some((node, reference)),
+                _ => None,
+            })
+    }
+
+    /// Footnote references with a label that has no definition, either as a footnote definition
+    /// or as a named inline footnote
+    pub fn dangling_footnote_references(&self) -> Vec<(Option<&Node>, &FootnoteReference)> {
+        let inline_labels: Vec<&str> = self
+            .footnote_references()
+            .filter(|(_, reference)| reference.is_inline())
+            .filter_map(|(_, reference)| reference.label.as_deref())
+            .collect();
+
+        self.footnote_references()
+            .filter(|(_, reference)| match reference.label {
+                Some(ref label) if !reference.is_inline() => {
+                    self.footnote(label).is_none() && !inline_labels.contains(&label.as_str())
+                }
+                _ => false,
+            })
+            .collect()
+    }
+
+    /// The nodes that match a tags/property query, in document order
+    pub fn select<'a>(&'a self, query: &'a Query) -> impl Iterator<Item = &'a Node> + 'a {
+        self.all_nodes().filter(move |node| query.matches(self, node))
+    }
+
     pub fn nodes_for_date<'a>(
         &'a self,
         date: &'a Date,
@@ -224,17 +716,43 @@ impl Document {
             .flat_map(move |node| node.timestamps_for_date(date).zip(repeat(node)))
     }
 
-    pub fn nodes_past_scheduled(&self) -> impl Iterator<Item = &Node> {
-        self.all_nodes()
-            .filter(move |node| node.is_past_scheduled())
-    }
+    /// The value of a property of a node, like `org-entry-get`. `NAME+` entries add to the value,
+    /// and inherited properties fall back to the node's ancestors and then to the properties of
+    /// the document.
+    pub fn node_property(&self, node_id: NodeId, name: &str) -> Option<String> {
+        let node = self.node(node_id)?;
+        if !self.settings.property_inheritance.inherits(name) {
+            return node.properties.value(name);
+        }
+
+        // The `NAME+` additions of each node up to the one with a value for `NAME`, innermost first
+        let mut additions: Vec<Vec<&str>> = Vec::new();
+        let mut value = None;
+        let sources = Some(&node.properties)
+            .into_iter()
+            .chain(self.ancestors(node_id).map(|node| &node.properties))
+            .chain(Some(&self.properties));
+
+        for properties in sources {
+            additions.push(properties.additions(name));
+            if let Some(base) = properties.get(name) {
+                value = Some(base);
+                break;
+            }
+        }
 
-    pub fn nodes_past_deadline(&self) -> impl Iterator<Item = &Node> {
-        self.all_nodes().filter(move |node| node.is_past_deadline())
+        let parts: Vec<&str> = value
+            .into_iter()
+            .chain(Iterator::flatten(additions.into_iter().rev()))
+            .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" "))
+        }
     }
 
-    // TODO: Support extending parent properties via NAME+
-    pub fn node_property(&self, node_id: NodeId, name: &str) -> Option<&str> {
+    fn inherited_property(&self, node_id: NodeId, name: &str) -> Option<&str> {
         let mut node_id = Some(node_id);
 
         while let Some(id) = node_id {
@@ -246,11 +764,14 @@ impl Document {
             node_id = self.parent_id(id);
         }
 
-        None
+        self.properties.get(name)
     }
 
     pub fn node_category(&self, node_id: NodeId) -> Option<&str> {
-        if let Some(category) = self.node_property(node_id, "CATEGORY") {
+        // Like in Emacs, the category is always inherited
+        if let Some(category) = self.inherited_property(node_id, "CATEGORY") {
+            Some(category)
+        } else if let Some(category) = self.keyword("CATEGORY") {
             Some(category)
         } else if let Some(ref path) = self.path {
             path.file_stem().and_then(|stem| stem.to_str())
@@ -259,11 +780,135 @@ impl Document {
         }
     }
 
+    /// The priority of a node, or the default priority if it doesn't have one
+    pub fn node_priority(&self, node_id: NodeId) -> Option<char> {
+        let node = self.node(node_id)?;
+        let priority = node.headline.priority.as_ref().and_then(|p| p.chars().next());
+        Some(priority.unwrap_or(self.settings.default_priority))
+    }
+
+    /// Changes the TODO keyword of a node, or removes it with `None`, like `org-todo`. Returns
+    /// false if the node doesn't exist or the keyword isn't one of the document's TODO keywords.
+    ///
+    /// Marking a node done adds a `CLOSED:` timestamp, and leaving a done state removes it. If the
+    /// node has a repeating `SCHEDULED:` or `DEADLINE:` timestamp, it is moved to the next
+    /// repeat and the node goes back to the first keyword of its sequence instead. State changes
+    /// are logged into the log drawer according to the settings.
+    pub fn set_todo(&mut self, node_id: NodeId, keyword: Option<&str>) -> bool {
+        self.set_todo_at(node_id, keyword, &now())
+    }
+
+    /// Changes the TODO keyword of a node as if it was done at the given time, see `set_todo`
+    pub fn set_todo_at(&mut self, node_id: NodeId, keyword: Option<&str>, now: &DateTime) -> bool {
+        let settings = &self.settings;
+        let keywords = &settings.todo_keywords;
+        if keyword.is_some_and(|keyword| !keywords.contains(keyword)) {
+            return false;
+        }
+        let node = match self.tree.node_mut(node_id) {
+            Some(node) => node,
+            None => return false,
+        };
+
+        let from = node.headline.keyword.clone();
+        if from.as_deref() == keyword {
+            return true;
+        }
+        let was_done = node.is_done();
+        let is_done = keyword.is_some_and(|keyword| keywords.is_done(keyword));
+        let is_repeating = |timestamp: &Option<Timestamp>| {
+            timestamp.as_ref().is_some_and(|timestamp| timestamp.repeater.is_some())
+        };
+        let log_entry = LogbookItem::state_change(keyword, from.as_deref(), now);
+
+        if is_done && !was_done && (is_repeating(&node.scheduled_for) || is_repeating(&node.deadline)) {
+            let sequence = keywords.sequence(keyword.unwrap()).unwrap();
+            let to_state = match node.property("REPEAT_TO_STATE") {
+                Some(state) if keywords.contains(state) => Some(state.to_string()),
+                _ if sequence.kind == TodoSequenceKind::Type => from,
+                _ => sequence.keywords().next().map(|keyword| keyword.to_string()),
+            };
+
+            node.headline.is_done = to_state.as_ref().is_some_and(|state| keywords.is_done(state));
+            node.headline.keyword = to_state;
+            if let Some(ref mut scheduled) = node.scheduled_for {
+                scheduled.repeat(now);
+            }
+            if let Some(ref mut deadline) = node.deadline {
+                deadline.repeat(now);
+            }
+            node.closed_at = None;
+
+            if settings.log_repeat {
+                let last_repeat = Timestamp::from_datetime(now, TimestampKind::Inactive);
+                node.set_property("LAST_REPEAT", &last_repeat.to_string());
+                node.logbook_mut(&settings.log_drawer).add(log_entry);
+            }
+        } else {
+            node.headline.keyword = keyword.map(|keyword| keyword.to_string());
+            node.headline.is_done = is_done;
+
+            if is_done && !was_done && settings.log_done {
+                node.closed_at = Some(Timestamp::from_datetime(now, TimestampKind::Closed));
+            } else if !is_done {
+                node.closed_at = None;
+            }
+            if settings.log_state_changes {
+                node.logbook_mut(&settings.log_drawer).add(log_entry);
+            }
+        }
+
+        node.update_planning_timestamps();
+        true
+    }
+
+    /// The logbook of a node, which is empty if the node doesn't exist or has no logbook
+    pub fn node_logbook(&self, node_id: NodeId) -> &Logbook {
+        lazy_static! {
+            static ref EMPTY: Logbook = Logbook::default();
+        }
+        self.node(node_id).map(|node| node.logbook()).unwrap_or(&EMPTY)
+    }
+
+    /// The node with a clock that was started but not stopped yet, along with the time it was
+    /// started. If several clocks are running, this is the one that was started last.
+    pub fn running_clock(&self) -> Option<(&Node, DateTime)> {
+        self.all_nodes().filter_map(|node| {
+            let start = self.node_logbook(node.id).running_clock()?.start()?;
+            Some((node, start))
+        }).max_by_key(|&(_, start)| start)
+    }
+
+    /// Starts the clock on a node by adding an open `CLOCK:` line to the top of its log drawer,
+    /// like `org-clock-in`. A clock that is already running in the document is stopped first.
+    /// Returns false if the node doesn't exist.
+    pub fn clock_in(&mut self, node_id: NodeId, now: &DateTime) -> bool {
+        if self.node(node_id).is_none() {
+            return false;
+        }
+        self.clock_out(now);
+
+        let node = self.tree.node_mut(node_id).unwrap();
+        let clock = LogbookItem::Clock(ClockEntry::new(now));
+        node.logbook_mut(&self.settings.log_drawer).add(clock);
+        true
+    }
+
+    /// Stops the running clock, like `org-clock-out`, by adding the end time and duration to its
+    /// `CLOCK:` line. Returns the node the clock was running on, if any.
+    pub fn clock_out(&mut self, now: &DateTime) -> Option<NodeId> {
+        let (node_id, start) = self.running_clock().map(|(node, start)| (node.id, start))?;
+        let end = if *now > start { *now } else { start };
+
+        let node = self.tree.node_mut(node_id)?;
+        node.logbook_mut(&self.settings.log_drawer).running_clock_mut()?.stop(&end);
+        Some(node_id)
+    }
+
     pub fn node_time_spent(&self, node_id: NodeId) -> Duration {
         let node_time = self
-            .node(node_id)
-            .map(|node| node.logbook().time_spent())
-            .unwrap_or_else(Duration::zero);
+            .node_logbook(node_id)
+            .time_spent();
         let children_time = self
             .child_ids(Some(node_id))
             .map(|child_id| self.node_time_spent(child_id))
@@ -274,9 +919,8 @@ impl Document {
 
     pub fn node_time_spent_today(&self, node_id: NodeId) -> Duration {
         let node_time = self
-            .node(node_id)
-            .map(|node| node.logbook().time_spent_today())
-            .unwrap_or_else(Duration::zero);
+            .node_logbook(node_id)
+            .time_spent_today();
         let children_time = self
             .child_ids(Some(node_id))
             .map(|child_id| self.node_time_spent_today(child_id))
@@ -289,6 +933,115 @@ impl Document {
         self.all_nodes().filter(|node| node.was_clocked_to_today())
     }
 
+    /// Recomputes the contents of the dynamic blocks in the document from their parameters, like
+    /// `org-update-all-dblocks`. Returns the number of blocks that were updated.
+    ///
+    /// Only `clocktable` blocks are supported, with a `:scope` of `file`, `subtree` or `tree`.
+    /// Other blocks are left unchanged, and so are clocktables with parameters that aren't
+    /// supported, which are reported in `diagnostics`.
+    pub fn update_dynamic_blocks(&mut self) -> usize {
+        self.update_dynamic_blocks_at(&now())
+    }
+
+    /// Updates the dynamic blocks as if it was the given time, see `update_dynamic_blocks`
+    pub fn update_dynamic_blocks_at(&mut self, now: &DateTime) -> usize {
+        let section_ids = Some(None).into_iter().chain(self.all_ids().map(Some));
+        let mut updates = Vec::new();
+
+        let ((), diagnostics) = diagnostic::collect(|| {
+            for section_id in section_ids {
+                for (index, element) in self.section(section_id).unwrap().elements.iter().enumerate() {
+                    if let Element::DynamicBlock(block) = element {
+                        if let Some(contents) = self.dynamic_block_contents(section_id, block, now) {
+                            updates.push((section_id, index, contents));
+                        }
+                    }
+                }
+            }
+        });
+        // Blocks that can't be updated are left unchanged, with a warning that isn't repeated
+        // when updating again
+        for mut diagnostic in diagnostics {
+            diagnostic.path = self.path.clone();
+            if !self.diagnostics.contains(&diagnostic) {
+                self.diagnostics.push(diagnostic);
+            }
+        }
+
+        let count = updates.len();
+        for (section_id, index, contents) in updates {
+            let section = self.section_mut(section_id).unwrap();
+            if let Some(Element::DynamicBlock(block)) = section.elements.get_mut(index) {
+                block.contents = contents;
+            }
+        }
+        count
+    }
+
+    fn dynamic_block_contents(&self, section_id: Option<NodeId>, block: &DynamicBlock, now: &DateTime) -> Option<String> {
+        if !block.name.eq_ignore_ascii_case("clocktable") {
+            return None;
+        }
+        let subtree = match block.parameter(":scope").as_deref() {
+            None | Some("file") => None,
+            Some("subtree") => section_id,
+            Some("tree") => section_id.map(|id| self.ancestor_ids(id).last().unwrap_or(id)),
+            Some(scope) => {
+                org_warning!(Unsupported, "Unsupported clocktable scope `{}`", scope);
+                return None;
+            }
+        };
+
+        let options = ClockReportOptions::from_dynamic_block(block)?;
+        let report = ClockReport::for_document(self, subtree, &options, now.date());
+        Some(report.to_table(now).to_string() + "\n")
+    }
+
+    /// The tags from every `#+FILETAGS` keyword, which apply to every node in the document
+    pub fn file_tags(&self) -> Vec<&str> {
+        self.keywords()
+            .filter(|keyword| keyword.is("FILETAGS"))
+            .flat_map(|keyword| keyword.value.split(|c: char| c == ':' || c.is_whitespace()))
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+
+    /// The tags of a node along with the tags it inherits from its ancestors and `#+FILETAGS`,
+    /// outermost first
+    pub fn node_tags(&self, node_id: NodeId) -> Vec<&str> {
+        let node = match self.node(node_id) {
+            Some(node) => node,
+            None => return Vec::new(),
+        };
+
+        let mut inherited = Vec::new();
+        if self.settings.tag_inheritance {
+            let mut ancestors: Vec<&Node> = self.ancestors(node_id).collect();
+            ancestors.reverse();
+
+            inherited = self.file_tags();
+            inherited.extend(
+                ancestors
+                    .into_iter()
+                    .flat_map(|ancestor| ancestor.headline.tags.iter().map(|tag| tag.as_str())),
+            );
+
+            let excluded = &self.settings.tags_exclude_from_inheritance;
+            inherited.retain(|tag| !excluded.iter().any(|excluded| excluded == tag));
+        }
+
+        let mut tags: Vec<&str> = Vec::new();
+        for tag in inherited
+            .into_iter()
+            .chain(node.headline.tags.iter().map(|tag| tag.as_str()))
+        {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        tags
+    }
+
     pub fn parent_with_tag(&self, node_id: NodeId, tag: &str) -> Option<&Node> {
         let mut node_id = Some(node_id);
 
@@ -305,13 +1058,53 @@ impl Document {
     }
 }
 
+impl Document {
+    /// Writes a node and its children, or the document section and all nodes for `None`, using
+    /// the original text of each region that hasn't changed
+    fn write_lossless(&self, output: &mut String, id: Option<NodeId>) {
+        match id {
+            Some(id) => self.node(id).unwrap().write_lossless(output, &self.settings),
+            None => self.section.write_lossless(output, &self.settings),
+        }
+
+        for child_id in self.child_ids(id) {
+            self.write_lossless(output, Some(child_id));
+        }
+    }
+}
+
 impl fmt::Display for Document {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.section)?;
+        if self.settings.lossless {
+            let mut output = String::new();
+            self.write_lossless(&mut output, None);
+            if !self.ends_with_newline && output.ends_with('\n') {
+                output.pop();
+            }
+            return write!(f, "{}", output);
+        }
+
+        write!(f, "{}", self.section.format(&self.settings))?;
         if !self.section.is_empty() && !self.tree.is_empty() {
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
-        write!(f, "{}", self.all_nodes().join("\n"))
+        write!(f, "{}", self.all_nodes().map(|node| node.format(&self.settings)).join("\n"))
+    }
+}
+
+/// A node and its descendants that have been taken out of a document
+pub struct Subtree {
+    /// Each node along with its depth below the root of the subtree, in document order
+    nodes: Vec<(usize, Node)>,
+}
+
+impl Subtree {
+    pub fn root(&self) -> &Node {
+        &self.nodes[0].1
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().map(|(_, node)| node)
     }
 }
 
@@ -325,14 +1118,710 @@ impl fmt::Debug for Document {
     }
 }
 
-fn parse_title(line: &str) -> Option<String> {
-    lazy_static! {
-        static ref REGEX: Regex = Regex::new(r#"^#\+TITLE:\s*(.*)$"#).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diagnostic::{DiagnosticCode, Severity};
+    use keyword::AffiliatedKeywords;
+    use settings::PropertyInheritance;
+
+    #[test]
+    fn test_links() {
+        let document = Document::from_string(
+            "See [[https://orgmode.org][the manual]]\n\
+             * Projects <file:projects.org>\n\
+             - A list item with [[*Projects]]",
+        );
+        let mut links: Vec<(Option<&str>, String)> = document
+            .links()
+            .map(|(node, link)| (node.map(|node| node.title()), link.to_string()))
+            .collect();
+        links.sort();
+
+        assert_eq!(
+            links,
+            vec![
+                (None, "[[https://orgmode.org][the manual]]".to_string()),
+                (Some("Projects <file:projects.org>"), "<file:projects.org>".to_string()),
+                (Some("Projects <file:projects.org>"), "[[*Projects]]".to_string()),
+            ]
+        );
     }
 
-    if let Some(captures) = REGEX.captures(line) {
-        Some(captures[1].to_string())
-    } else {
-        None
+    #[test]
+    fn test_footnotes() {
+        let document = Document::from_string(
+            "A paragraph with a footnote[fn:1], a missing one[fn:2]\n\
+             and an inline one[fn:3:Inline].\n\
+             [fn:1] The definition\n\
+             * Heading[fn:3]\n\
+             [fn:4] Another definition[fn:5]\n\
+             with a second line\n\
+             \n\
+             \n\
+             Not part of the definition",
+        );
+
+        assert_eq!(document.footnotes().count(), 2);
+        assert_eq!(document.footnote("1").unwrap().text().as_str(), "The definition");
+        assert_eq!(
+            document.footnote("4").unwrap().text().as_str(),
+            "Another definition[fn:5] with a second line"
+        );
+        assert_eq!(document.footnote_references().count(), 5);
+
+        let mut dangling: Vec<&str> = document
+            .dangling_footnote_references()
+            .into_iter()
+            .filter_map(|(_, reference)| reference.label.as_deref())
+            .collect();
+        dangling.sort();
+        assert_eq!(dangling, vec!["2", "5"]);
+
+        let heading = document.roots().next().unwrap();
+        assert_eq!(heading.section.elements.len(), 2);
+        assert_eq!(heading.section.elements[1].to_string(), "\n\nNot part of the definition");
+    }
+
+    #[test]
+    fn test_in_buffer_todo_keywords() {
+        let document = Document::from_string(
+            "#+TODO: TODO NEXT WAITING | DONE CANCELLED\n\
+             * NEXT Call the bank\n\
+             * CANCELLED Buy a boat\n\
+             * IN-PROGRESS Not a keyword here",
+        );
+        let nodes: Vec<&Node> = document.roots().collect();
+
+        assert_eq!(nodes[0].headline.keyword, Some("NEXT".to_string()));
+        assert_eq!(nodes[0].title(), "Call the bank");
+        assert!(!nodes[0].is_done());
+        assert_eq!(nodes[1].headline.keyword, Some("CANCELLED".to_string()));
+        assert!(nodes[1].is_done());
+        assert_eq!(nodes[2].headline.keyword, None);
+        assert_eq!(nodes[2].title(), "IN-PROGRESS Not a keyword here");
+    }
+
+    #[test]
+    fn test_in_buffer_settings_apply_to_whole_file() {
+        let source = "* NEXT [#D] foo\n\
+                      * TODO bar\n\
+                      #+TODO: NEXT | DONE\n\
+                      #+PRIORITIES: A D B\n\
+                      #+STARTUP: logdone";
+        let document = Document::from_string(source);
+        let nodes: Vec<&Node> = document.roots().collect();
+
+        assert_eq!(nodes[0].headline.keyword, Some("NEXT".to_string()));
+        assert_eq!(nodes[0].headline.priority, Some("D".to_string()));
+        assert_eq!(nodes[0].title(), "foo");
+        assert_eq!(nodes[1].headline.keyword, None);
+        assert_eq!(nodes[1].title(), "TODO bar");
+        assert!(document.settings.log_done);
+        assert_eq!(Document::from_string_lossless(source).to_string(), source);
+    }
+
+    #[test]
+    fn test_keywords() {
+        let source = "#+TITLE: My document\n\
+                      #+AUTHOR: Jane Doe\n\
+                      #+FILETAGS: :work:\n\
+                      \n\
+                      #+NAME: numbers\n\
+                      #+CAPTION[Short]: Some numbers\n\
+                      | 1 | 2 |\n\
+                      \n\
+                      #+ATTR_HTML: :class steps\n\
+                      - First\n\
+                      - Second\n\
+                      * Heading\n\
+                      #+CATEGORY: chores\n\
+                      #+name: example\n\
+                      #+begin_example\n\
+                      Some example\n\
+                      #+end_example\n\
+                      #+CAPTION: A cat\n\
+                      \x20 #+ATTR_HTML: :width 50%\n\
+                      [[./cat.png]]";
+        let document = Document::from_string(source);
+
+        assert_eq!(document.title, "My document");
+        assert_eq!(document.keyword("author"), Some("Jane Doe"));
+        assert_eq!(document.keyword("FILETAGS"), Some(":work:"));
+        assert_eq!(document.keyword("NAME"), None);
+        assert_eq!(
+            document.keywords().map(|keyword| keyword.key.as_str()).collect::<Vec<_>>(),
+            vec!["TITLE", "AUTHOR", "FILETAGS", "CATEGORY"]
+        );
+
+        let affiliated: Vec<&AffiliatedKeywords> = document
+            .section
+            .elements
+            .iter()
+            .filter_map(|element| element.affiliated())
+            .filter(|affiliated| !affiliated.is_empty())
+            .collect();
+        assert_eq!(affiliated.len(), 2);
+        assert_eq!(affiliated[0].name(), Some("numbers"));
+        assert_eq!(affiliated[0].caption(), Some("Some numbers"));
+        assert_eq!(affiliated[1].attributes("HTML"), Some(":class steps"));
+
+        let node = document.roots().next().unwrap();
+        assert_eq!(document.node_category(node.id), Some("chores"));
+        assert_eq!(node.section.elements[1].affiliated().unwrap().name(), Some("example"));
+        assert_eq!(node.section.elements.len(), 3);
+        let paragraph = node.section.elements[2].affiliated().unwrap();
+        assert_eq!(paragraph.caption(), Some("A cat"));
+        assert_eq!(paragraph.attributes("HTML"), Some(":width 50%"));
+        assert!(document.to_string().ends_with("#+CAPTION: A cat\n  #+ATTR_HTML: :width 50%\n[[./cat.png]]"));
+
+        let output = document.to_string();
+        let keyword_lines = |text: &str| -> Vec<String> {
+            text.lines()
+                .filter(|line| line.starts_with("#+"))
+                .map(|line| line.to_string())
+                .collect()
+        };
+        assert_eq!(keyword_lines(&output), keyword_lines(source));
+    }
+
+    #[test]
+    fn test_lossless_round_trip() {
+        let source = "#+TITLE:   Lossless\r\n\
+                      Some text that is\n\
+                      wrapped   by hand.\n\
+                      \n\
+                      * TODO A headline          :tag:\n\
+                      SCHEDULED: <2018-10-27 Sat>\n\
+                      :PROPERTIES:\n\
+                      :Zebra: 1\n\
+                      :Apple:   2\n\
+                      :END:\n\
+                      - a list\n\
+                      -   item\n\
+                      ** Child\n\
+                      | a |   b |\n\
+                      * Another headline";
+        let document = Document::from_string_lossless(source);
+
+        assert!(document.is_lossless());
+        assert_eq!(document.title, "Lossless");
+        assert_eq!(document.to_string(), source);
+
+        let node = document.roots().next().unwrap();
+        assert_eq!(node.source().unwrap().text.lines().count(), 6);
+        assert_eq!(node.source().unwrap().span.start, source.find("* TODO").unwrap());
+    }
+
+    #[test]
+    fn test_lossless_edits() {
+        let source = "Intro  text\n\
+                      * First   :tag:\n\
+                      -  item\n\
+                      * Second\n\
+                      Some   text\n";
+        let mut document = Document::from_string_lossless(source);
+        let ids: Vec<NodeId> = document.root_ids().collect();
+
+        document.node_mut(ids[1]).unwrap().headline.set_title("Renamed");
+        document
+            .section_mut(Some(ids[0]))
+            .unwrap()
+            .add_element(Element::new_paragraph("New paragraph"));
+
+        assert_eq!(
+            document.to_string(),
+            "Intro  text\n\
+             * First   :tag:\n\
+             -  item\n\
+             New paragraph\n\
+             * Renamed\n\
+             Some   text\n"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let document = Document::from_string(
+            "* Heading\n\
+             Some text\n\
+             SCHEDULED: <2018-10-27 Sat>\n\
+             #+BEGIN_SRC rust\n\
+             fn main() {}",
+        );
+        let diagnostics = document.diagnostics();
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].code, DiagnosticCode::Misplaced);
+        assert_eq!(diagnostics[0].line, Some(3));
+        assert_eq!(diagnostics[1].severity, Severity::Warning);
+        assert_eq!(diagnostics[1].code, DiagnosticCode::Unterminated);
+        assert_eq!(diagnostics[1].line, Some(4));
+        assert_eq!(
+            diagnostics[1].to_string(),
+            "line 4: warning: Expected `#+END_SRC` before the next headline [unterminated]"
+        );
+    }
+
+    #[test]
+    fn test_invalid_planning_line() {
+        let source = "* Heading\nSCHEDULED: <2018-02-31 Sat>";
+        let document = Document::from_string(source);
+        let node = document.roots().next().unwrap();
+
+        assert_eq!(node.scheduled_for, None);
+        assert_eq!(document.to_string(), source);
+        assert_eq!(document.diagnostics()[0].code, DiagnosticCode::InvalidTimestamp);
+    }
+
+    #[test]
+    fn test_unterminated_blocks() {
+        let sources = [
+            "#+BEGIN_SRC rust\nfn x() {}\n* Heading\n** Child",
+            "#+BEGIN: clocktable :maxlevel 2\n* Heading\n** Child",
+        ];
+
+        for source in sources.iter() {
+            let mut document = Document::from_string_lossless(source);
+
+            assert_eq!(document.root_ids().count(), 1);
+            assert_eq!(document.all_ids().count(), 2);
+            assert_eq!(document.update_dynamic_blocks(), 0);
+            assert_eq!(&document.to_string(), source);
+        }
+    }
+
+    #[test]
+    fn test_settings() {
+        let settings = Settings {
+            fill_column: 20,
+            tag_column: 30,
+            log_drawer: "CLOCKING".to_string(),
+            property_inheritance: PropertyInheritance::Off,
+            ..Settings::default()
+        };
+        let document = Document::from_string_with_settings(
+            "#+PRIORITIES: A E C\n\
+             * TODO [#D] Heading :tag:\n\
+             :PROPERTIES:\n\
+             :CATEGORY: work\n\
+             :OWNER: Jane\n\
+             :END:\n\
+             ** Child\n\
+             :CLOCKING:\n\
+             CLOCK: [2018-09-15 Sat 10:00]--[2018-09-15 Sat 11:30] =>  1:30\n\
+             :END:\n\
+             A paragraph that is longer than the fill column",
+            &settings,
+        );
+        let parent_id = document.root_ids().next().unwrap();
+        let child_id = document.child_ids(Some(parent_id)).next().unwrap();
+
+        assert_eq!(document.settings.lowest_priority, 'E');
+        assert_eq!(document.node_priority(parent_id), Some('D'));
+        assert_eq!(document.node_priority(child_id), Some('C'));
+        assert_eq!(document.node_property(child_id, "OWNER"), None);
+        assert_eq!(document.node_category(child_id), Some("work"));
+        assert_eq!(document.node_time_spent(parent_id), Duration::minutes(90));
+        let format = |id| document.node(id).unwrap().format(&document.settings);
+        assert!(format(parent_id).starts_with("* TODO [#D] Heading      :tag:\n"));
+        assert!(format(child_id).ends_with("A paragraph that is\nlonger than the fill\ncolumn"));
+
+        let document = Document::from_string("* TODO [#D] Heading");
+        let id = document.root_ids().next().unwrap();
+        assert_eq!(document.node(id).unwrap().title(), "[#D] Heading");
+        assert_eq!(document.node_priority(id), Some('B'));
+    }
+
+    #[test]
+    fn test_traversal() {
+        let source = "* One\n\
+                      ** Two\n\
+                      *** Three\n\
+                      ** Four\n\
+                      * Five\n\
+                      ** Six";
+        let document = Document::from_string(source);
+        let titles = |nodes: Vec<&Node>| nodes.iter().map(|node| node.title()).join(" ");
+
+        assert_eq!(document.to_string(), source);
+        assert_eq!(titles(document.all_nodes().collect()), "One Two Three Four Five Six");
+        assert_eq!(
+            document
+                .pre_order()
+                .map(|(depth, node)| format!("{}{}", depth, node.title()))
+                .join(" "),
+            "1One 2Two 3Three 2Four 1Five 2Six"
+        );
+        assert_eq!(
+            document
+                .post_order()
+                .map(|(depth, node)| format!("{}{}", depth, node.title()))
+                .join(" "),
+            "3Three 2Two 2Four 1One 2Six 1Five"
+        );
+
+        let find = |title: &str| document.all_nodes().find(|node| node.title() == title).unwrap().id;
+        assert_eq!(titles(document.descendants(find("One")).collect()), "Two Three Four");
+        assert_eq!(titles(document.ancestors(find("Three")).collect()), "Two One");
+        assert_eq!(document.next_sibling(find("Two")).unwrap().title(), "Four");
+        assert_eq!(document.prev_sibling(find("Four")).unwrap().title(), "Two");
+        assert_eq!(document.prev_sibling(find("Five")).unwrap().title(), "One");
+        assert!(document.next_sibling(find("Five")).is_none());
+        assert!(document.prev_sibling(find("Two")).is_none());
+    }
+
+    #[test]
+    fn test_tree_editing() {
+        let mut document = Document::from_string(
+            "* One\n\
+             Text\n\
+             ** Two\n\
+             *** Three\n\
+             ** Four\n\
+             * Five",
+        );
+        let find = |document: &Document, title: &str| {
+            document.all_nodes().find(|node| node.title() == title).unwrap().id
+        };
+        let (one, two, four, five) = (
+            find(&document, "One"),
+            find(&document, "Two"),
+            find(&document, "Four"),
+            find(&document, "Five"),
+        );
+
+        let zero = document.insert_before(one, Headline::new("Zero")).unwrap();
+        document.insert_after(two, Headline::new("Two and a half")).unwrap();
+        document.insert_child(Some(zero), Headline::new("Child")).unwrap();
+        assert_eq!(
+            document.to_string(),
+            "* Zero\n** Child\n* One\nText\n** Two\n*** Three\n** Two and a half\n** Four\n* Five"
+        );
+
+        assert!(document.delete_subtree(zero).is_some());
+        assert!(document.node(zero).is_none());
+        assert_eq!(document.all_nodes().count(), 6);
+
+        assert!(document.promote_subtree(two));
+        assert_eq!(
+            document.to_string(),
+            "* One\nText\n* Two\n** Three\n** Two and a half\n** Four\n* Five"
+        );
+
+        assert!(document.demote_subtree(two));
+        assert!(!document.demote_subtree(one));
+        assert!(!document.promote_subtree(one));
+        assert_eq!(
+            document.to_string(),
+            "* One\nText\n** Two\n*** Three\n*** Two and a half\n*** Four\n* Five"
+        );
+
+        assert!(document.move_subtree(four, None));
+        assert!(document.move_subtree_up(four));
+        assert!(!document.move_subtree(one, Some(two)));
+        assert!(document.move_subtree_down(one));
+        assert!(document.move_subtree_down(one));
+        assert!(!document.move_subtree_down(one));
+        assert!(!document.move_subtree_up(four));
+        assert_eq!(
+            document.to_string(),
+            "* Four\n* Five\n* One\nText\n** Two\n*** Three\n*** Two and a half"
+        );
+        assert_eq!(document.prev_sibling_id(one), Some(five));
+
+        let mut document = Document::from_string_lossless("* A  \n  Some   text\n* B\n** C\n");
+        let a = find(&document, "A");
+        let c = find(&document, "C");
+        assert!(document.move_subtree_down(a));
+        assert!(document.promote_subtree(c));
+        assert_eq!(document.to_string(), "* B\n* C\n* A  \n  Some   text\n");
+    }
+
+    #[test]
+    fn test_node_tags() {
+        let source = "#+FILETAGS: :work:\n\
+                      * Project :project:crypt:\n\
+                      ** Task :urgent:\n\
+                      *** Subtask :work:";
+        let document = Document::from_string(source);
+        let subtask_id = document.all_nodes().find(|node| node.title() == "Subtask").unwrap().id;
+
+        assert_eq!(document.file_tags(), vec!["work"]);
+        assert_eq!(document.node_tags(subtask_id), vec!["work", "project", "crypt", "urgent"]);
+
+        let settings = Settings {
+            tags_exclude_from_inheritance: vec!["crypt".to_string(), "work".to_string()],
+            ..Settings::default()
+        };
+        let document = Document::from_string_with_settings(source, &settings);
+        assert_eq!(document.node_tags(subtask_id), vec!["project", "urgent", "work"]);
+
+        let settings = Settings { tag_inheritance: false, ..Settings::default() };
+        let document = Document::from_string_with_settings(source, &settings);
+        assert_eq!(document.node_tags(subtask_id), vec!["work"]);
+        assert_eq!(document.node_tags(document.root_ids().next().unwrap()), vec!["project", "crypt"]);
+    }
+
+    #[test]
+    fn test_node_properties() {
+        let source = "#+PROPERTY: header-args :results silent\n\
+                      #+PROPERTY: Owner Nobody\n\
+                      * Parent\n\
+                      :PROPERTIES:\n\
+                      :VAR:      x=1\n\
+                      :Effort:   1:00\n\
+                      :END:\n\
+                      ** Child\n\
+                      :PROPERTIES:\n\
+                      :var+:     y=2\n\
+                      :Zeta:     last\n\
+                      :Alpha:    first\n\
+                      :END:";
+        let document = Document::from_string(source);
+        let child_id = document.all_nodes().find(|node| node.title() == "Child").unwrap().id;
+
+        assert_eq!(document.node_property(child_id, "var"), Some("x=1 y=2".to_string()));
+        assert_eq!(document.node_property(child_id, "EFFORT"), Some("1:00".to_string()));
+        assert_eq!(document.node_property(child_id, "owner"), Some("Nobody".to_string()));
+        assert_eq!(
+            document.node_property(child_id, "header-args"),
+            Some(":results silent".to_string())
+        );
+        assert!(document.to_string().ends_with(
+            ":PROPERTIES:\n:var+:  y=2\n:Zeta:  last\n:Alpha: first\n:END:"
+        ));
+
+        let settings = Settings {
+            property_inheritance: PropertyInheritance::Only(vec!["VAR".to_string()]),
+            ..Settings::default()
+        };
+        let mut document = Document::from_string_with_settings(source, &settings);
+        assert_eq!(document.node_property(child_id, "VAR"), Some("x=1 y=2".to_string()));
+        assert_eq!(document.node_property(child_id, "Effort"), None);
+
+        let child = document.node_mut(child_id).unwrap();
+        child.set_property("VAR", "z=3");
+        child.set_property("Effort", "0:30");
+        assert_eq!(child.delete_property("zeta"), Some("last".to_string()));
+        assert_eq!(document.node_property(child_id, "VAR"), Some("z=3".to_string()));
+        assert!(document.to_string().ends_with(
+            ":PROPERTIES:\n:Alpha:  first\n:VAR:    z=3\n:Effort: 0:30\n:END:"
+        ));
+    }
+
+    #[test]
+    fn test_set_todo() {
+        let source = "#+TODO: TODO NEXT | DONE CANCELLED\n\
+                      #+STARTUP: logdone\n\
+                      * TODO Write report\n\
+                      SCHEDULED: <2018-10-20 Sat>\n\
+                      * NEXT Water plants\n\
+                      SCHEDULED: <2018-10-20 Sat ++1w>\n\
+                      Some text";
+        let mut document = Document::from_string(source);
+        let ids: Vec<NodeId> = document.root_ids().collect();
+        let now = Date::from_ymd(2018, 10, 27).and_hms(20, 9, 30);
+
+        assert!(!document.set_todo_at(ids[0], Some("WAITING"), &now));
+        assert!(document.set_todo_at(ids[0], Some("DONE"), &now));
+        assert!(document.node(ids[0]).unwrap().is_done());
+        assert_eq!(
+            document.node(ids[0]).unwrap().to_string(),
+            "* DONE Write report\n\
+             SCHEDULED: <2018-10-20 Sat> CLOSED: [2018-10-27 Sat 20:09]"
+        );
+
+        assert!(document.set_todo_at(ids[0], Some("TODO"), &now));
+        assert_eq!(
+            document.node(ids[0]).unwrap().to_string(),
+            "* TODO Write report\n\
+             SCHEDULED: <2018-10-20 Sat>"
+        );
+
+        // Without `logdone`, no `CLOSED:` timestamp is added, as in Org
+        let mut plain = Document::from_string("* TODO Write report");
+        let id = plain.root_ids().next().unwrap();
+        assert!(plain.set_todo_at(id, Some("DONE"), &now));
+        assert_eq!(plain.node(id).unwrap().to_string(), "* DONE Write report");
+
+        assert!(document.set_todo_at(ids[1], Some("DONE"), &now));
+        let node = document.node(ids[1]).unwrap();
+        assert!(!node.is_done());
+        assert!(node.matches_date(&Date::from_ymd(2018, 11, 3)));
+        assert_eq!(
+            node.to_string(),
+            "* TODO Water plants\n\
+             SCHEDULED: <2018-11-03 Sat ++1w>\n\
+             :PROPERTIES:\n\
+             :LAST_REPEAT: [2018-10-27 Sat 20:09]\n\
+             :END:\n\
+             :LOGBOOK:\n\
+             - State \"DONE\"       from \"NEXT\"       [2018-10-27 Sat 20:09]\n\
+             :END:\n\
+             Some text"
+        );
+
+        let mut document = Document::from_string_with_settings(
+            "#+STARTUP: nologdone\n* TODO Heading",
+            &Settings {
+                log_state_changes: true,
+                ..Settings::default()
+            },
+        );
+        let id = document.root_ids().next().unwrap();
+        assert!(document.set_todo_at(id, Some("DONE"), &now));
+        assert!(document.set_todo_at(id, None, &now));
+        assert_eq!(
+            document.node(id).unwrap().to_string(),
+            "* Heading\n\
+             :LOGBOOK:\n\
+             - State              from \"DONE\"       [2018-10-27 Sat 20:09]\n\
+             - State \"DONE\"       from \"TODO\"       [2018-10-27 Sat 20:09]\n\
+             :END:"
+        );
+    }
+
+    #[test]
+    fn test_clock_in_and_out() {
+        let source = "* Task\n\
+                      :LOGBOOK:\n\
+                      CLOCK: [2018-10-26 Fri 09:00]--[2018-10-26 Fri 09:30] =>  0:30\n\
+                      :END:\n\
+                      * Other task\n\
+                      Some text";
+        let mut document = Document::from_string(source);
+        let ids: Vec<NodeId> = document.root_ids().collect();
+        let time = |hour, minute| Date::from_ymd(2018, 10, 27).and_hms(hour, minute, 42);
+
+        assert!(document.running_clock().is_none());
+        assert!(document.clock_in(ids[0], &time(9, 0)));
+        assert_eq!(document.running_clock().map(|(node, _)| node.id), Some(ids[0]));
+        assert!(document.clock_in(ids[1], &time(10, 23)));
+        assert_eq!(
+            document.running_clock().map(|(node, start)| (node.id, start)),
+            Some((ids[1], Date::from_ymd(2018, 10, 27).and_hms(10, 23, 0)))
+        );
+        assert_eq!(document.clock_out(&time(22, 0)), Some(ids[1]));
+        assert_eq!(document.clock_out(&time(22, 0)), None);
+
+        assert_eq!(document.node_time_spent(ids[0]), Duration::minutes(113));
+        assert_eq!(
+            document.to_string(),
+            "* Task\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 09:00]--[2018-10-27 Sat 10:23] =>  1:23\n\
+             CLOCK: [2018-10-26 Fri 09:00]--[2018-10-26 Fri 09:30] =>  0:30\n\
+             :END:\n\
+             * Other task\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 10:23]--[2018-10-27 Sat 22:00] => 11:37\n\
+             :END:\n\
+             Some text"
+        );
+    }
+
+    #[test]
+    fn test_update_dynamic_blocks() {
+        let source = "#+TITLE: Work\n\
+                      #+BEGIN: clocktable :maxlevel 2 :scope file\n\
+                      #+END:\n\
+                      * Website\n\
+                      #+begin: clocktable :scope subtree :block today\n\
+                      | Old | table |\n\
+                      #+end:\n\
+                      ** Design\n\
+                      :LOGBOOK:\n\
+                      CLOCK: [2018-10-27 Sat 09:00]--[2018-10-27 Sat 10:30] =>  1:30\n\
+                      CLOCK: [2018-10-20 Sat 09:00]--[2018-10-20 Sat 10:00] =>  1:00\n\
+                      :END:\n\
+                      *** Mockups\n\
+                      :LOGBOOK:\n\
+                      CLOCK: [2018-10-27 Sat 11:00]--[2018-10-27 Sat 11:45] =>  0:45\n\
+                      :END:\n\
+                      * Email\n\
+                      :LOGBOOK:\n\
+                      CLOCK: [2018-10-26 Fri 08:00]--[2018-10-26 Fri 08:15] =>  0:15\n\
+                      :END:\n\
+                      #+BEGIN: columnview :id local\n\
+                      #+END:\n";
+        let mut document = Document::from_string_lossless(source);
+        let now = Date::from_ymd(2018, 10, 27).and_hms(12, 0, 0);
+
+        assert_eq!(document.update_dynamic_blocks_at(&now), 2);
+        assert_eq!(
+            document.to_string(),
+            "#+TITLE: Work\n\
+             #+BEGIN: clocktable :maxlevel 2 :scope file\n\
+             #+CAPTION: Clock summary at [2018-10-27 Sat 12:00]\n\
+             | Headline     | Time   |      |\n\
+             |--------------+--------+------|\n\
+             | *Total time* | *3:30* |      |\n\
+             |--------------+--------+------|\n\
+             | Website      | 3:15   |      |\n\
+             | \\_  Design   |        | 3:15 |\n\
+             | Email        | 0:15   |      |\n\
+             #+END:\n\
+             * Website\n\
+             #+begin: clocktable :scope subtree :block today\n\
+             #+CAPTION: Clock summary at [2018-10-27 Sat 12:00]\n\
+             | Headline      | Time   |      |      |\n\
+             |---------------+--------+------+------|\n\
+             | *Total time*  | *2:15* |      |      |\n\
+             |---------------+--------+------+------|\n\
+             | Website       | 2:15   |      |      |\n\
+             | \\_  Design    |        | 2:15 |      |\n\
+             | \\_    Mockups |        |      | 0:45 |\n\
+             #+end:\n\
+             ** Design\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 09:00]--[2018-10-27 Sat 10:30] =>  1:30\n\
+             CLOCK: [2018-10-20 Sat 09:00]--[2018-10-20 Sat 10:00] =>  1:00\n\
+             :END:\n\
+             *** Mockups\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 11:00]--[2018-10-27 Sat 11:45] =>  0:45\n\
+             :END:\n\
+             * Email\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-26 Fri 08:00]--[2018-10-26 Fri 08:15] =>  0:15\n\
+             :END:\n\
+             #+BEGIN: columnview :id local\n\
+             #+END:\n"
+        );
+    }
+
+    #[test]
+    fn test_unsupported_dynamic_block_parameters() {
+        let source = "#+BEGIN: clocktable :tstart \"<-1w>\"\n\
+                      | Old | table |\n\
+                      #+END:\n\
+                      #+BEGIN: clocktable :block 2018-W42\n\
+                      #+END:\n\
+                      #+BEGIN: clocktable :scope agenda\n\
+                      #+END:\n\
+                      #+BEGIN: clocktable :block thisweek :step day :link\n\
+                      | Weekly | review |\n\
+                      #+END:\n\
+                      * Heading\n\
+                      :LOGBOOK:\n\
+                      CLOCK: [2018-10-27 Sat 09:00]--[2018-10-27 Sat 10:30] =>  1:30\n\
+                      :END:\n";
+        let mut document = Document::from_string_lossless(source);
+        let now = Date::from_ymd(2018, 10, 27).and_hms(12, 0, 0);
+
+        assert_eq!(document.update_dynamic_blocks_at(&now), 0);
+        assert_eq!(document.update_dynamic_blocks_at(&now), 0);
+        assert_eq!(document.to_string(), source);
+        assert_eq!(
+            document.diagnostics().iter().map(|diagnostic| diagnostic.to_string()).collect::<Vec<_>>(),
+            vec![
+                "warning: Expected an absolute timestamp for :tstart, found `<-1w>` [unsupported]",
+                "warning: Unsupported clocktable block `2018-W42` [unsupported]",
+                "warning: Unsupported clocktable scope `agenda` [unsupported]",
+                "warning: Unsupported clocktable parameter `:step` [unsupported]",
+                "warning: Unsupported clocktable parameter `:link` [unsupported]",
+            ]
+        );
     }
 }
@@ -1,6 +1,6 @@
 use parser::Parser;
+use properties::Properties;
 use regex::Regex;
-use std::collections::HashMap;
 use std::fmt;
 
 const DRAWER_END: &str = ":END:";
@@ -15,18 +15,14 @@ pub struct Drawer {
 
 impl Drawer {
     pub fn parse(line: &str, lines: &mut Parser) -> Option<Drawer> {
-        if let Some(name) = parse_drawername(line) {
-            Some(Drawer {
-                name: name.to_string(),
-                contents: lines.take_until(DRAWER_END)
-            })
-        } else {
-            None
-        }
+        parse_drawername(line).map(|name| Drawer {
+            name: name.to_string(),
+            contents: lines.take_until(DRAWER_END)
+        })
     }
 
-    pub fn from_properties(properties: &HashMap<String, String>) -> Self {
-        let name_width = properties.keys().map(|name| name.len()).max().unwrap_or(0);
+    pub fn from_properties(properties: &Properties) -> Self {
+        let name_width = properties.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
 
         Drawer {
             name: PROPERTIES_DRAWER_NAME.to_string(),
@@ -45,7 +41,7 @@ impl Drawer {
     }
 
     /// See <https://orgmode.org/worg/dev/org-syntax.html#Node_Properties>
-    pub fn as_properties(&self) -> Option<HashMap<String, String>> {
+    pub fn as_properties(&self) -> Option<Properties> {
         lazy_static! {
             static ref REGEX: Regex = Regex::new(r#"^\s*:([^\s:]+):\s+(.*)$"#).unwrap();
         }
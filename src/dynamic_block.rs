@@ -0,0 +1,178 @@
+use parser::Parser;
+use regex::Regex;
+use std::fmt;
+use utils::StringUtils;
+
+/// A dynamic block is defined as:
+///
+/// ```text
+/// #+BEGIN: NAME PARAMETERS
+/// CONTENTS
+/// #+END:
+/// ```
+///
+/// The contents are generated from the parameters, see `Document::update_dynamic_blocks`.
+///
+/// See <https://orgmode.org/worg/dev/org-syntax.html#Dynamic_Blocks> and
+/// <https://orgmode.org/manual/Dynamic-Blocks.html>
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DynamicBlock {
+    pub name: String,
+    pub parameters: Option<String>,
+    /// The raw lines between the begin and end lines, each followed by a newline
+    pub contents: String,
+    #[serde(skip)]
+    indent: usize,
+    #[serde(skip)]
+    lowercase: bool,
+}
+
+impl DynamicBlock {
+    pub fn new(name: &str, parameters: Option<&str>) -> Self {
+        DynamicBlock {
+            name: name.to_string(),
+            parameters: parameters.map(|parameters| parameters.to_string()),
+            contents: String::new(),
+            indent: 0,
+            lowercase: false,
+        }
+    }
+
+    pub fn parse(line: &str, parser: &mut Parser) -> Option<DynamicBlock> {
+        lazy_static! {
+            static ref REGEX: Regex = Regex::new(r#"(?xi)
+                ^
+                \s*
+                \#\+(?P<begin>BEGIN):
+                \s+
+                (?P<name>\S+)
+                (\s+(?P<parameters>.*?))?
+                \s*
+                $
+            "#).unwrap();
+        }
+
+        let captures = REGEX.captures(line)?;
+
+        // Without an end line, the begin line is read as a keyword instead, so that updating the
+        // block can't replace the rest of the document
+        let is_end = |line: &str| line.trim().eq_ignore_ascii_case("#+END:");
+        if !parser.has_end_before_headline(is_end) {
+            org_warning!(Unterminated, "Expected `#+END:` before the next headline");
+            return None;
+        }
+
+        let mut contents = String::new();
+        while let Some(line) = parser.next() {
+            if is_end(&line) {
+                break;
+            }
+            contents += &line;
+            contents += "\n";
+        }
+
+        Some(DynamicBlock {
+            name: captures["name"].to_string(),
+            parameters: captures.name("parameters").map(|c| c.as_str().to_string()),
+            contents,
+            indent: line.indent(),
+            lowercase: &captures["begin"] == "begin",
+        })
+    }
+
+    /// The names of the parameters, such as `:maxlevel`, in the order they're written
+    pub fn parameter_names(&self) -> Vec<String> {
+        let parameters = self.parameters.as_ref().map(|parameters| split_parameters(parameters));
+        parameters
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|parameter| parameter.starts_with(':'))
+            .collect()
+    }
+
+    /// The value of a parameter such as `:maxlevel 2`, without the quotes around a value like
+    /// `:tstart "<2018-10-01>"`. A parameter without a value has an empty value.
+    pub fn parameter(&self, key: &str) -> Option<String> {
+        let parameters = split_parameters(self.parameters.as_ref()?);
+        let index = parameters.iter().position(|parameter| parameter.eq_ignore_ascii_case(key))?;
+
+        match parameters.get(index + 1) {
+            Some(value) if !value.starts_with(':') => Some(value.to_string()),
+            _ => Some(String::new()),
+        }
+    }
+}
+
+/// Splits parameters on whitespace, keeping quoted values together
+fn split_parameters(parameters: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut in_quotes = false;
+
+    for c in parameters.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !part.is_empty() {
+                    parts.push(part.clone());
+                    part.clear();
+                }
+            }
+            c => part.push(c),
+        }
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+    parts
+}
+
+impl fmt::Display for DynamicBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let indent = " ".repeat(self.indent);
+        let (begin, end) = if self.lowercase {
+            ("#+begin:", "#+end:")
+        } else {
+            ("#+BEGIN:", "#+END:")
+        };
+
+        write!(f, "{}{} {}", indent, begin, self.name)?;
+        if let Some(ref parameters) = self.parameters {
+            write!(f, " {}", parameters)?;
+        }
+        write!(f, "\n{}", self.contents)?;
+        write!(f, "{}{}", indent, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Option<DynamicBlock> {
+        let mut parser = Parser::from_string(source);
+        let line = parser.next().unwrap();
+        DynamicBlock::parse(&line, &mut parser)
+    }
+
+    #[test]
+    fn test_parse_dynamic_block() {
+        let source = "#+BEGIN: clocktable :maxlevel 2 :tstart \"<2018-10-01 Mon>\" :link\n\
+                      | Old | table |\n\
+                      #+END:";
+        let block = parse(source).unwrap();
+
+        assert_eq!(block.name, "clocktable");
+        assert_eq!(block.parameter(":maxlevel"), Some("2".to_string()));
+        assert_eq!(block.parameter(":tstart"), Some("<2018-10-01 Mon>".to_string()));
+        assert_eq!(block.parameter(":link"), Some(String::new()));
+        assert_eq!(block.parameter(":scope"), None);
+        assert_eq!(block.parameter_names(), vec![":maxlevel", ":tstart", ":link"]);
+        assert_eq!(block.contents, "| Old | table |\n");
+        assert_eq!(block.to_string(), source);
+
+        assert_eq!(parse("  #+begin: columnview\n  #+end:").unwrap().to_string(), "  #+begin: columnview\n  #+end:");
+        assert!(parse("#+BEGIN_SRC rust").is_none());
+        assert!(parse("#+BEGIN:").is_none());
+    }
+}
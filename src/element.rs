@@ -1,7 +1,14 @@
+use block::Block;
 use drawer::Drawer;
+use dynamic_block::DynamicBlock;
+use footnote::FootnoteDefinition;
 use itertools::Itertools;
+use keyword::{AffiliatedKeywords, Keyword};
 use list::List;
+use logbook::Logbook;
+use object::Object;
 use parser::Parser;
+use settings::{Settings, DEFAULT_SETTINGS};
 use std::fmt;
 use table::Table;
 use text::Text;
@@ -10,53 +17,123 @@ use text::Text;
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Element {
     Drawer(Drawer),
+    Logbook(Logbook),
     Paragraph(Text),
     Comment { text: String },
     FixedWidthArea { text: String },
-    // Block(Block)
+    Block(Block),
+    DynamicBlock(DynamicBlock),
+    Keyword(Keyword),
     HorizontalRule,
     Table(Table),
     List(List),
+    FootnoteDefinition(FootnoteDefinition),
 }
 
 impl Element {
     pub fn parse(line: &str, parser: &mut Parser) -> Option<Element> {
-        if let Some(text) = parse_area_prefixed(line, parser, "#") {
+        if let Some(definition) = FootnoteDefinition::parse(line, parser) {
+            Some(Element::FootnoteDefinition(definition))
+        } else if let Some(block) = Block::parse(line, parser) {
+            Some(Element::Block(block))
+        } else if let Some(block) = DynamicBlock::parse(line, parser) {
+            Some(Element::DynamicBlock(block))
+        } else if let Some(keyword) = Keyword::parse(line) {
+            Some(Element::Keyword(keyword))
+        } else if let Some(text) = parse_area_prefixed(line, parser, "#") {
             Some(Element::Comment { text })
         } else if let Some(text) = parse_area_prefixed(line, parser, ":") {
             Some(Element::FixedWidthArea { text })
-        // } else if let Some(block) = Block::parse(line, parser)? {
-        //     Some(Element::Block(block))
         } else if is_horizontal_rule(line) {
             Some(Element::HorizontalRule)
         } else if let Some(table) = Table::parse(line, parser) {
             Some(Element::Table(table))
-        } else if let Some(list) = List::parse(line, parser) {
-            Some(Element::List(list))
         } else {
-            None
+            List::parse(line, parser).map(Element::List)
         }
     }
 
     pub fn new_paragraph(text: &str) -> Self {
-        Element::Paragraph(Text::new(text))
+        // Split on newlines rather than using `lines()`, so that a single blank line is kept
+        let mut paragraph = Text::default();
+        for line in text.split('\n') {
+            paragraph.add_line(line);
+        }
+        Element::Paragraph(paragraph)
+    }
+
+    /// The affiliated keywords of elements that can have them, such as `#+NAME` on a table
+    pub fn affiliated(&self) -> Option<&AffiliatedKeywords> {
+        match self {
+            Element::Paragraph(paragraph) => Some(&paragraph.affiliated),
+            Element::Block(block) => Some(&block.affiliated),
+            Element::Table(table) => Some(&table.affiliated),
+            Element::List(list) => Some(&list.affiliated),
+            _ => None,
+        }
+    }
+
+    pub fn affiliated_mut(&mut self) -> Option<&mut AffiliatedKeywords> {
+        match self {
+            Element::Paragraph(paragraph) => Some(&mut paragraph.affiliated),
+            Element::Block(block) => Some(&mut block.affiliated),
+            Element::Table(table) => Some(&mut table.affiliated),
+            Element::List(list) => Some(&mut list.affiliated),
+            _ => None,
+        }
+    }
+
+    /// All objects in the element, including nested objects and those in nested elements
+    pub fn objects(&self) -> Vec<&Object> {
+        match self {
+            Element::Paragraph(text) => descendants(text.objects()),
+            Element::FootnoteDefinition(definition) => definition
+                .paragraphs
+                .iter()
+                .flat_map(|paragraph| descendants(paragraph.objects()))
+                .collect(),
+            Element::List(list) => list
+                .items()
+                .iter()
+                .flat_map(|item| {
+                    descendants(item.text().objects())
+                        .into_iter()
+                        .chain(item.elements().iter().flat_map(|element| element.objects()))
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Formats the element, filling paragraphs to the fill column
+    pub fn format(&self, settings: &Settings) -> String {
+        match self {
+            Element::Drawer(drawer) => drawer.to_string(),
+            Element::Logbook(logbook) => logbook.to_string(),
+            Element::Paragraph(paragraph) => paragraph.format(0, settings),
+            Element::Comment { text } => prefixed(text, "#"),
+            Element::FixedWidthArea { text } => prefixed(text, ":"),
+            Element::Block(block) => block.to_string(),
+            Element::DynamicBlock(block) => block.to_string(),
+            Element::Keyword(keyword) => keyword.to_string(),
+            Element::HorizontalRule => "-".repeat(5),
+            Element::Table(table) => table.to_string(),
+            Element::List(list) => list.format(settings),
+            Element::FootnoteDefinition(definition) => definition.format(settings),
+        }
     }
 }
 
 impl fmt::Display for Element {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Element::Drawer(drawer) => write!(f, "{}", drawer),
-            Element::Paragraph(paragraph) => write!(f, "{}", paragraph),
-            Element::Comment { text } => write!(f, "{}", prefixed(text, "#")),
-            Element::FixedWidthArea { text } => write!(f, "{}", prefixed(text, ":")),
-            Element::HorizontalRule => write!(f, "{}", "-".repeat(5)),
-            Element::Table(table) => write!(f, "{}", table),
-            Element::List(list) => write!(f, "{}", list),
-        }
+        write!(f, "{}", self.format(&DEFAULT_SETTINGS))
     }
 }
 
+pub(crate) fn descendants(objects: &[Object]) -> Vec<&Object> {
+    objects.iter().flat_map(|object| object.descendants()).collect()
+}
+
 fn prefixed(text: &str, prefix: &str) -> String {
     text.split('\n')
         .map(|line| format!("{} {}", prefix, line))
@@ -0,0 +1,218 @@
+use headline::is_headline;
+use object::Object;
+use parser::Parser;
+use regex::Regex;
+use settings::{Settings, DEFAULT_SETTINGS};
+use std::fmt;
+use text::Text;
+use utils::captures_at_start;
+
+/// A footnote definition is defined as `[fn:LABEL] CONTENTS` at the start of a line. It ends at
+/// the next footnote definition, the next headline or two consecutive blank lines, so a single
+/// blank line separates the paragraphs of the definition.
+///
+/// See <https://orgmode.org/worg/dev/org-syntax.html#Footnote_Definitions>
+#[derive(Serialize, Deserialize)]
+pub struct FootnoteDefinition {
+    pub label: String,
+    pub paragraphs: Vec<Text>,
+}
+
+impl FootnoteDefinition {
+    pub fn parse(line: &str, parser: &mut Parser) -> Option<FootnoteDefinition> {
+        let (label, contents) = parse_definition_start(line)?;
+        let mut paragraphs = vec![Text::new(contents)];
+
+        while let Some(line) = parser.peek().map(|line| line.to_string()) {
+            if line.trim().is_empty() {
+                // A blank line only belongs to the definition if another paragraph follows it
+                match parser.peek_nth(1) {
+                    Some(next) if !ends_definition(next) && !next.trim().is_empty() => {}
+                    _ => break,
+                }
+                parser.next();
+                paragraphs.last_mut().unwrap().add_line("");
+                paragraphs.push(Text::default());
+            } else if ends_definition(&line) {
+                break;
+            } else {
+                parser.next();
+                paragraphs.last_mut().unwrap().add_line(&line);
+            }
+        }
+
+        Some(FootnoteDefinition {
+            label: label.to_string(),
+            paragraphs,
+        })
+    }
+
+    /// The text of the first paragraph
+    pub fn text(&self) -> &Text {
+        &self.paragraphs[0]
+    }
+
+    pub fn format(&self, settings: &Settings) -> String {
+        let mut definition = format!("[fn:{}]", self.label);
+        for (index, paragraph) in self.paragraphs.iter().enumerate() {
+            if index > 0 {
+                definition += &format!("\n{}", paragraph.format(0, settings));
+            } else if !paragraph.as_str().is_empty() {
+                definition += &format!(" {}", paragraph.format(0, settings));
+            }
+        }
+        definition
+    }
+}
+
+impl fmt::Display for FootnoteDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(&DEFAULT_SETTINGS))
+    }
+}
+
+fn parse_definition_start(line: &str) -> Option<(&str, &str)> {
+    lazy_static! {
+        static ref REGEX: Regex = Regex::new(r#"^\[fn:([\w-]+)\]\s*(.*)$"#).unwrap();
+    }
+
+    REGEX.captures(line).map(|captures| {
+        (captures.get(1).unwrap().as_str(), captures.get(2).unwrap().as_str())
+    })
+}
+
+fn ends_definition(line: &str) -> bool {
+    is_headline(line) || parse_definition_start(line).is_some()
+}
+
+/// A footnote reference is defined as `[fn:LABEL]`, `[fn:LABEL:DEFINITION]` or
+/// `[fn::DEFINITION]`, where the latter two are inline footnotes
+///
+/// See <https://orgmode.org/worg/dev/org-syntax.html#Footnote_References>
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FootnoteReference {
+    pub label: Option<String>,
+    pub definition: Option<Vec<Object>>,
+}
+
+impl FootnoteReference {
+    /// Parses a footnote reference at the start of the text, returning the reference and its
+    /// length in bytes
+    pub(crate) fn parse(text: &str) -> Option<(FootnoteReference, usize)> {
+        lazy_static! {
+            static ref REGEX: Regex = Regex::new(r#"^\[fn:([\w-]*)(:|\])"#).unwrap();
+        }
+
+        let captures = captures_at_start(&REGEX, text)?;
+        let label = captures.get(1).unwrap().as_str();
+        let label = if label.is_empty() { None } else { Some(label.to_string()) };
+
+        if &captures[2] == "]" {
+            return label.map(|label| {
+                (FootnoteReference { label: Some(label), definition: None }, captures[0].len())
+            });
+        }
+
+        // Inline definitions may contain balanced square brackets, such as links
+        let start = captures[0].len();
+        let mut depth = 0;
+        for (index, c) in text[start..].char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' if depth > 0 => depth -= 1,
+                ']' => {
+                    let definition = Object::parse(&text[start..start + index]);
+                    return Some((
+                        FootnoteReference { label, definition: Some(definition) },
+                        start + index + 1,
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    pub fn is_inline(&self) -> bool {
+        self.definition.is_some()
+    }
+}
+
+impl fmt::Display for FootnoteReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[fn:{}", self.label.as_deref().unwrap_or(""))?;
+        if let Some(ref definition) = self.definition {
+            write!(f, ":")?;
+            for object in definition {
+                write!(f, "{}", object)?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_reference(text: &str) -> Option<FootnoteReference> {
+        FootnoteReference::parse(text).map(|(reference, _)| reference)
+    }
+
+    #[test]
+    fn test_parse_references() {
+        assert_eq!(parse_reference("[fn:1] and more"), Some(FootnoteReference {
+            label: Some("1".to_string()),
+            definition: None,
+        }));
+        assert_eq!(parse_reference("[fn:note:An *inline* note]").unwrap().definition.unwrap().len(), 3);
+        assert_eq!(parse_reference("[fn::See [[https://orgmode.org]]]"), Some(FootnoteReference {
+            label: None,
+            definition: Some(Object::parse("See [[https://orgmode.org]]")),
+        }));
+        assert_eq!(parse_reference("[fn:]"), None);
+        assert_eq!(parse_reference("[fn::unterminated"), None);
+    }
+
+    #[test]
+    fn test_parse_and_display_references() {
+        let references = ["[fn:1]", "[fn:name:Inline /definition/]", "[fn::Anonymous [[*Link]]]"];
+
+        for reference in references.iter() {
+            assert_eq!(&parse_reference(reference).unwrap().to_string(), reference);
+        }
+    }
+
+    #[test]
+    fn test_parse_definition() {
+        let mut parser = Parser::from_string(
+            "[fn:1] The first line\nthe second line\n\nA second paragraph\n\n\nNot part of it",
+        );
+        let line = parser.next().unwrap();
+        let definition = FootnoteDefinition::parse(&line, &mut parser).unwrap();
+
+        assert_eq!(definition.label, "1");
+        assert_eq!(definition.paragraphs.len(), 2);
+        assert_eq!(definition.text().as_str(), "The first line the second line");
+        assert_eq!(
+            definition.to_string(),
+            "[fn:1] The first line the second line\n\nA second paragraph"
+        );
+        assert_eq!(parser.next(), Some("".to_string()));
+        assert!(FootnoteDefinition::parse(" [fn:1] Indented", &mut parser).is_none());
+    }
+
+    #[test]
+    fn test_definition_ends_at_next_definition_or_headline() {
+        let sources = ["[fn:1] First\n\n[fn:2] Second", "[fn:1] First\n\n* Heading", "[fn:1] First\n\n*\tHeading"];
+        for source in sources.iter() {
+            let mut parser = Parser::from_string(source);
+            let line = parser.next().unwrap();
+            let definition = FootnoteDefinition::parse(&line, &mut parser).unwrap();
+
+            assert_eq!(definition.to_string(), "[fn:1] First");
+            assert_eq!(parser.next(), Some("".to_string()));
+        }
+    }
+}
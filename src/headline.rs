@@ -1,6 +1,7 @@
+use object::Object;
 use regex::Regex;
+use settings::{Settings, DEFAULT_SETTINGS};
 use std::fmt;
-use ::LINE_LENGTH;
 
 /// A headline is defined as `STARS KEYWORD PRIORITY TITLE TAGS`
 ///
@@ -9,29 +10,31 @@ use ::LINE_LENGTH;
 pub struct Headline {
     pub indent: u16,
     pub keyword: Option<String>,
+    pub is_done: bool,
     pub priority: Option<String>,
     pub is_commented: bool,
     pub title: String,
+    pub title_objects: Vec<Object>,
     pub tags: Vec<String>
 }
 
 impl Headline {
-    pub(super)fn parse(line: &str, keywords: &[String]) -> Option<Self> {
+    pub(super)fn parse(line: &str, settings: &Settings) -> Option<Self> {
         if is_headline(line) {
-            let (indent, text) = if let Some(index) = line.find(' ') {
-                line.split_at(index)
-            } else {
-                (line, "")
-            };
-            assert_eq!(indent, "*".repeat(indent.len()));
-            let indent = indent.len() as u16;
+            let keywords = &settings.todo_keywords;
+            let stars = line.chars().take_while(|c| *c == '*').count();
+            let (indent, text) = line.split_at(stars);
+            let indent = indent.len().min(u16::MAX as usize) as u16;
             let text = text.trim();
 
-            let keyword = keywords.iter()
-                .find(|keyword| text.starts_with(&((*keyword).clone() + " ")))
+            let keyword = keywords.keywords()
+                .find(|keyword| text == *keyword || text.starts_with(&(keyword.to_string() + " ")))
                 .map(|keyword| keyword.to_string());
+            let is_done = keyword.as_ref()
+                .map(|keyword| keywords.is_done(keyword))
+                .unwrap_or(false);
             let text = if let Some(ref keyword) = keyword {
-                text[keyword.len() + 1..].trim()
+                text[keyword.len()..].trim()
             } else {
                 text
             };
@@ -47,13 +50,29 @@ impl Headline {
                     (\s+(?P<tags>:([a-zA-Z0-9_@\#%]*:)+))?
                     $
                 "#).unwrap();
+                static ref NO_PRIORITY_REGEX: Regex = Regex::new(r#"(?x)
+                    ^
+                    (?P<comment>COMMENT(\s|$))?
+                    (?P<title>.*?)
+                    \s*
+                    (\s+(?P<tags>:([a-zA-Z0-9_@\#%]*:)+))?
+                    $
+                "#).unwrap();
             }
 
-            let captures = REGEX.captures(text).unwrap();
+            let mut captures = REGEX.captures(text)?;
+
+            // A priority cookie outside of the priority range is part of the title instead
+            if let Some(priority) = captures.name("priority").and_then(|c| c.as_str().chars().next()) {
+                if !settings.is_valid_priority(priority) {
+                    captures = NO_PRIORITY_REGEX.captures(text)?;
+                }
+            }
 
             let priority = captures.name("priority").map(|c| c.as_str().to_string());
             let is_commented = captures.name("comment").is_some();
             let title = captures.name("title").unwrap().as_str().to_string();
+            let title_objects = Object::parse(&title);
             let tags = if let Some(tags) = captures.name("tags").map(|c| c.as_str()) {
                 tags[1..tags.len() - 1].split(':')
                     .filter(|tag| !tag.is_empty())
@@ -66,54 +85,73 @@ impl Headline {
             Some(Headline {
                 indent,
                 keyword,
+                is_done,
                 priority,
                 is_commented,
                 title,
+                title_objects,
                 tags
             })
         } else {
             None
         }
     }
-}
 
-impl fmt::Display for Headline {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut width = self.indent as usize;
-        write!(f, "{}", "*".repeat(self.indent as usize))?;
+    /// Creates a top-level headline with a title and nothing else
+    pub fn new(title: &str) -> Self {
+        let mut headline = Headline {
+            indent: 1,
+            ..Headline::default()
+        };
+        headline.set_title(title);
+        headline
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+        self.title_objects = Object::parse(title);
+    }
+
+    /// Formats the headline with its tags aligned to the tag column
+    pub fn format(&self, settings: &Settings) -> String {
+        let mut line = "*".repeat(self.indent as usize);
 
         if let Some(ref keyword) = self.keyword {
-            width += keyword.len() + 1;
-            write!(f, " {}", keyword)?;
+            line += &format!(" {}", keyword);
         }
 
         if let Some(ref priority) = self.priority {
-            width += priority.len() + 4;
-            write!(f, " [#{}]", priority)?;
+            line += &format!(" [#{}]", priority);
         }
 
         if self.is_commented {
-            width += 8;
-            write!(f, " COMMENT")?;
+            line += " COMMENT";
         }
 
         if !self.title.is_empty() {
-            width += self.title.len() + 1;
-            write!(f, " {}", self.title)?;
+            line += &format!(" {}", self.title);
         }
 
         if !self.tags.is_empty() {
             let tags = format!(":{}:", self.tags.join(":"));
-            let padding = LINE_LENGTH - width - tags.len();
-            write!(f, "{}{}", " ".repeat(padding), tags)?;
+            // Titles that are too long to fit the tags in still get a space before the tags
+            let padding = settings.tag_column.saturating_sub(line.len() + tags.len()).max(1);
+            line += &format!("{}{}", " ".repeat(padding), tags);
         }
 
-        Ok(())
+        line
     }
 }
 
+impl fmt::Display for Headline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(&DEFAULT_SETTINGS))
+    }
+}
 
-fn is_headline(line: &str) -> bool {
+/// Whether a line starts a headline, which is one or more stars followed by whitespace or the end
+/// of the line
+pub(crate) fn is_headline(line: &str) -> bool {
     lazy_static! {
         static ref REGEX: Regex = Regex::new("^\\*+(\\s|$)").unwrap();
     }
@@ -125,6 +163,17 @@ fn is_headline(line: &str) -> bool {
 mod tests {
     use super::*;
 
+    use todo_keywords::TodoKeywords;
+
+    fn keywords() -> Settings {
+        let mut keywords = TodoKeywords::new();
+        keywords.add_sequence(TodoKeywords::parse_line("#+TODO: TODO DOING DONE").unwrap());
+        Settings {
+            todo_keywords: keywords,
+            ..Settings::default()
+        }
+    }
+
     #[test]
     fn test_is_headline() {
         assert!(is_headline("* My header"));
@@ -137,46 +186,64 @@ mod tests {
 
     #[test]
     fn test_parse_basic_headline() {
-        assert_eq!(Headline::parse("* My header", &["TODO".to_string(), "DOING".to_string(), "DONE".to_string()]),
+        assert_eq!(Headline::parse("* My header", &keywords()),
                    Some(Headline {
                        indent: 1,
                        keyword: None,
+                       is_done: false,
                        priority: None,
                        is_commented: false,
                        title: "My header".to_string(),
+                       title_objects: vec![Object::Text { text: "My header".to_string() }],
                        tags: vec![]
                    }));
     }
 
     #[test]
     fn test_parse_full_headline() {
-        assert_eq!(Headline::parse("** DOING [#C] Comment about my header :TAG1_%::@TAG2::", &["TODO".to_string(), "DOING".to_string(), "DONE".to_string()]),
+        assert_eq!(Headline::parse("** DOING [#C] Comment about my header :TAG1_%::@TAG2::", &keywords()),
                    Some(Headline {
                        indent: 2,
                        keyword: Some("DOING".to_string()),
+                       is_done: false,
                        priority: Some("C".to_string()),
                        is_commented: false,
                        title: "Comment about my header".to_string(),
+                       title_objects: vec![Object::Text { text: "Comment about my header".to_string() }],
                        tags: vec!["TAG1_%".to_string(), "@TAG2".to_string()]
                    }));
     }
 
     #[test]
     fn test_parse_commented_headline() {
-        assert_eq!(Headline::parse("**** TODO [#A] COMMENT Title :tag:a2%:", &["TODO".to_string(), "DOING".to_string(), "DONE".to_string()]),
+        assert_eq!(Headline::parse("**** TODO [#A] COMMENT Title :tag:a2%:", &keywords()),
                    Some(Headline {
                        indent: 4,
                        keyword: Some("TODO".to_string()),
+                       is_done: false,
                        priority: Some("A".to_string()),
                        is_commented: true,
                        title: "Title".to_string(),
+                       title_objects: vec![Object::Text { text: "Title".to_string() }],
                        tags: vec!["tag".to_string(), "a2%".to_string()]
                    }));
     }
 
+    #[test]
+    fn test_parse_done_headline() {
+        let headline = Headline::parse("* DONE Finished", &keywords()).unwrap();
+        assert_eq!(headline.keyword, Some("DONE".to_string()));
+        assert!(headline.is_done);
+
+        let headline = Headline::parse("* DOING", &keywords()).unwrap();
+        assert_eq!(headline.keyword, Some("DOING".to_string()));
+        assert_eq!(headline.title, "");
+        assert!(!headline.is_done);
+    }
+
     #[test]
     fn test_parse_and_display_headlines() {
-        let keywords = ["TODO".to_string(), "DOING".to_string(), "DONE".to_string()];
+        let keywords = keywords();
         let headlines = [
             "***",
             "* My header",
@@ -184,8 +251,47 @@ mod tests {
             "**** TODO [#A] COMMENT Title                                           :tag:a2%:"
         ];
 
-        for headline in headlines.into_iter() {
+        for headline in headlines.iter() {
             assert_eq!(&Headline::parse(headline, &keywords).unwrap().to_string(), headline);
         }
     }
+
+    #[test]
+    fn test_parse_tab_after_stars() {
+        let headline = Headline::parse("**\tTODO Tabbed headline", &keywords()).unwrap();
+
+        assert_eq!(headline.indent, 2);
+        assert_eq!(headline.keyword, Some("TODO".to_string()));
+        assert_eq!(headline.title, "Tabbed headline");
+    }
+
+    #[test]
+    fn test_display_long_title_with_tags() {
+        let title = "A very long title ".repeat(6);
+        let headline = Headline::parse(&format!("* {} :tag:", title), &keywords()).unwrap();
+
+        assert_eq!(headline.to_string(), format!("* {} :tag:", title.trim()));
+    }
+
+    #[test]
+    fn test_priority_range() {
+        let mut settings = keywords();
+
+        let headline = Headline::parse("* TODO [#D] Out of range", &settings).unwrap();
+        assert_eq!(headline.priority, None);
+        assert_eq!(headline.title, "[#D] Out of range");
+
+        settings.lowest_priority = 'E';
+        let headline = Headline::parse("* TODO [#D] In range", &settings).unwrap();
+        assert_eq!(headline.priority, Some("D".to_string()));
+        assert_eq!(headline.title, "In range");
+    }
+
+    #[test]
+    fn test_format_tag_column() {
+        let settings = Settings { tag_column: 20, ..keywords() };
+        let headline = Headline::parse("* Title :tag:", &settings).unwrap();
+
+        assert_eq!(headline.format(&settings), "* Title        :tag:");
+    }
 }
@@ -0,0 +1,167 @@
+use regex::Regex;
+use std::fmt;
+use utils::StringUtils;
+
+const AFFILIATED_KEYWORDS: &[&str] = &[
+    "CAPTION", "DATA", "HEADER", "HEADERS", "LABEL", "NAME", "PLOT", "RESNAME", "RESULT",
+    "RESULTS", "SOURCE", "SRCNAME", "TBLNAME",
+];
+
+/// A keyword is defined as `#+KEY: VALUE`, or `#+KEY[OPTIONAL]: VALUE` for some affiliated
+/// keywords such as `#+CAPTION`
+///
+/// See <https://orgmode.org/worg/dev/org-syntax.html#Keywords> and
+/// <https://orgmode.org/worg/dev/org-syntax.html#Affiliated_Keywords>
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keyword {
+    pub key: String,
+    pub optional: Option<String>,
+    pub value: String,
+    #[serde(skip)]
+    indent: usize,
+}
+
+impl Keyword {
+    pub fn new(key: &str, value: &str) -> Self {
+        Keyword {
+            key: key.to_string(),
+            optional: None,
+            value: value.to_string(),
+            indent: 0,
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Keyword> {
+        lazy_static! {
+            static ref REGEX: Regex = Regex::new(r#"(?x)
+                ^
+                \s*
+                \#\+(?P<key>[^\s:\[\]]+)
+                (\[(?P<optional>[^\]]*)\])?
+                :
+                \s*
+                (?P<value>.*?)
+                \s*
+                $
+            "#).unwrap();
+        }
+
+        let captures = REGEX.captures(line)?;
+
+        Some(Keyword {
+            key: captures["key"].to_string(),
+            optional: captures.name("optional").map(|c| c.as_str().to_string()),
+            value: captures.name("value").map(|c| c.as_str()).unwrap_or("").to_string(),
+            indent: line.indent(),
+        })
+    }
+
+    pub fn is(&self, key: &str) -> bool {
+        self.key.eq_ignore_ascii_case(key)
+    }
+
+    /// Whether the keyword belongs to the element that follows it, like `#+NAME` or `#+ATTR_HTML`
+    pub fn is_affiliated(&self) -> bool {
+        let key = self.key.to_uppercase();
+        key.starts_with("ATTR_") || AFFILIATED_KEYWORDS.contains(&key.as_str())
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}#+{}", " ".repeat(self.indent), self.key)?;
+        if let Some(ref optional) = self.optional {
+            write!(f, "[{}]", optional)?;
+        }
+        write!(f, ":")?;
+        if !self.value.is_empty() {
+            write!(f, " {}", self.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The affiliated keywords attached to an element
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AffiliatedKeywords {
+    pub keywords: Vec<Keyword>,
+}
+
+impl AffiliatedKeywords {
+    pub fn is_empty(&self) -> bool {
+        self.keywords.is_empty()
+    }
+
+    pub fn push(&mut self, keyword: Keyword) {
+        self.keywords.push(keyword);
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.keywords
+            .iter()
+            .find(|keyword| keyword.is(key))
+            .map(|keyword| keyword.value.as_str())
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.get("NAME")
+    }
+
+    pub fn caption(&self) -> Option<&str> {
+        self.get("CAPTION")
+    }
+
+    /// The attributes for an export backend, such as `html` for `#+ATTR_HTML`
+    pub fn attributes(&self, backend: &str) -> Option<&str> {
+        self.get(&format!("ATTR_{}", backend))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Keyword> {
+        self.keywords.iter()
+    }
+}
+
+impl fmt::Display for AffiliatedKeywords {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for keyword in self.keywords.iter() {
+            writeln!(f, "{}", keyword)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keywords() {
+        assert_eq!(Keyword::parse("#+TITLE: My document"), Some(Keyword::new("TITLE", "My document")));
+        assert_eq!(Keyword::parse("  #+author:"), Some(Keyword { indent: 2, ..Keyword::new("author", "") }));
+        assert_eq!(Keyword::parse("#+CAPTION[Short]: A longer caption"), Some(Keyword {
+            key: "CAPTION".to_string(),
+            optional: Some("Short".to_string()),
+            value: "A longer caption".to_string(),
+            indent: 0,
+        }));
+        assert_eq!(Keyword::parse("#+BEGIN_SRC rust"), None);
+        assert_eq!(Keyword::parse("# A comment: with a colon"), None);
+        assert_eq!(Keyword::parse("#+NAME:value"), Some(Keyword::new("NAME", "value")));
+    }
+
+    #[test]
+    fn test_is_affiliated() {
+        assert!(Keyword::new("NAME", "table").is_affiliated());
+        assert!(Keyword::new("attr_html", ":width 100%").is_affiliated());
+        assert!(!Keyword::new("TITLE", "My document").is_affiliated());
+    }
+
+    #[test]
+    fn test_parse_and_display_keywords() {
+        let keywords = ["#+FILETAGS: :work:home:", "#+CAPTION[Short]: Long", "#+EMPTY:", "  #+NAME: tbl"];
+
+        for keyword in keywords.iter() {
+            assert_eq!(&Keyword::parse(keyword).unwrap().to_string(), keyword);
+        }
+    }
+}
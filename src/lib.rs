@@ -6,26 +6,41 @@ extern crate regex;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
-extern crate textwrap;
+extern crate uuid;
 
 #[macro_use]
 mod parser;
 
 mod agenda;
+mod block;
+mod clockcheck;
+mod clocktable;
+mod diagnostic;
 mod document;
 mod drawer;
+mod dynamic_block;
 mod element;
+mod footnote;
 mod headline;
+mod keyword;
 mod library;
+mod link;
 mod list;
 mod logbook;
 mod node;
+mod object;
 mod planning;
+mod properties;
+mod query;
 mod section;
+mod settings;
+mod source;
 mod table;
 mod text;
+mod timesheet;
 mod timestamp;
 mod timestamps;
+mod todo_keywords;
 mod tree;
 mod utils;
 
@@ -33,19 +48,38 @@ use std::io::Error as IoError;
 use std::path::Path;
 
 pub use agenda::{Agenda, AgendaEntry, AgendaEntryKind, AgendaRange};
-pub use document::{Document, DocumentId};
+pub use block::{Block, BlockKind};
+pub use clockcheck::{check_clocks, ClockIssue, ClockLocation, ClockProblem};
+pub use clocktable::{
+    split_by_day, ClockBlock, ClockGroup, ClockGrouping, ClockReport, ClockReportOptions, ClockRow,
+    ClockScope,
+};
+pub use diagnostic::{Diagnostic, DiagnosticCode, Severity};
+pub use document::{Document, DocumentId, Subtree};
 pub use drawer::Drawer;
+pub use dynamic_block::DynamicBlock;
 pub use element::Element;
+pub use footnote::{FootnoteDefinition, FootnoteReference};
 pub use headline::Headline;
-pub use library::Library;
-pub use list::List;
-pub use logbook::Logbook;
+pub use keyword::{AffiliatedKeywords, Keyword};
+pub use library::{Library, RefileTarget};
+pub use link::{Link, LinkKind, LinkTarget};
+pub use list::{List, ListItem};
+pub use logbook::{ClockEntry, Logbook, LogbookItem};
 pub use node::{Node, NodeId};
+pub use object::Object;
 pub use parser::Parser;
 pub use planning::Planning;
+pub use properties::Properties;
+pub use query::{Query, QueryError};
 pub use section::Section;
+pub use settings::{PropertyInheritance, Settings};
+pub use source::{Source, Span};
 pub use table::Table;
-pub use timestamp::{today, format_duration, Date, Duration, Time, Timestamp};
+pub use text::Text;
+pub use timesheet::{Timesheet, TimesheetRow};
+pub use timestamp::{today, format_duration, Date, DateTime, Duration, Time, Timestamp};
+pub use todo_keywords::{TodoKeywords, TodoSequence, TodoSequenceKind};
 
 pub const LINE_LENGTH: usize = 80;
 
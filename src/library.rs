@@ -1,35 +1,57 @@
+use diagnostic::Diagnostic;
 use document::{Document, DocumentId};
 use std::collections::HashMap;
 use std::path::Path;
 use std::fs;
 use std::fmt;
-use timestamp::{Date, today};
+use timestamp::{now, today, Date, DateTime, Duration};
 use agenda::{Agenda, AgendaRange};
+use clockcheck::{self, ClockIssue};
+use clocktable::{ClockReport, ClockReportOptions, ClockScope};
 use std::io::Error as IoError;
 use std::ops::{Index, IndexMut};
 use std::ffi::OsStr;
-use node::Node;
+use node::{Node, NodeId};
+use query::Query;
+use settings::Settings;
 
 #[derive(Serialize)]
 pub struct Library {
     #[serde(skip)]
     next_id: DocumentId,
+    #[serde(skip)]
+    settings: Settings,
+    /// The nodes with each `:ID:` or `:CUSTOM_ID:` value, see `reindex`
+    #[serde(skip)]
+    ids: HashMap<String, Vec<(DocumentId, NodeId)>>,
     documents: HashMap<DocumentId, Document>
 }
 
 impl Library {
     pub fn new() -> Self {
+        Library::with_settings(Settings::default())
+    }
+
+    /// Creates a library that opens documents with the given settings
+    pub fn with_settings(settings: Settings) -> Self {
         Library {
             next_id: 0,
+            settings,
+            ids: HashMap::new(),
             documents: HashMap::new()
         }
     }
 
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
     pub fn add(&mut self, mut document: Document) -> (DocumentId, &Document) {
         let id = self.next_id;
         self.next_id += 1;
         document.set_id(id);
         self.documents.insert(id, document);
+        self.index_document(id);
         (id, &self.documents[&id])
     }
 
@@ -48,7 +70,7 @@ impl Library {
     }
 
     pub fn open_file(&mut self, path: &Path) -> Result<(DocumentId, &Document), IoError> {
-        Ok(self.add(Document::open_file(path)?))
+        Ok(self.add(Document::open_file_with_settings(path, &self.settings)?))
     }
 
     pub fn agenda(&self, range: AgendaRange, start_date: Date) -> Agenda {
@@ -63,6 +85,17 @@ impl Library {
         self.agenda(AgendaRange::Week, today())
     }
 
+    /// A report of the time clocked in the library, relative to today
+    pub fn clock_report(&self, scope: &ClockScope, options: &ClockReportOptions) -> ClockReport {
+        ClockReport::new(self, scope, options, today())
+    }
+
+    /// Problems with the clocks in every document, such as overlapping clocks or clocks that
+    /// ran for longer than `max_duration`, see `check_clocks`
+    pub fn check_clocks(&self, max_duration: Duration) -> Vec<ClockIssue> {
+        clockcheck::check_clocks(self, max_duration, &now())
+    }
+
     pub fn document(&self, id: DocumentId) -> Option<&Document> {
         self.documents.get(&id)
     }
@@ -75,10 +108,203 @@ impl Library {
         self.documents.values()
     }
 
+    /// The problems found while parsing each document
+    pub fn diagnostics(&self) -> impl Iterator<Item=&Diagnostic> {
+        self.documents()
+            .flat_map(|document| document.diagnostics().iter())
+    }
+
     pub fn nodes_clocked_to_today(&self) -> impl Iterator<Item = &Node> {
         self.documents()
             .flat_map(|document| document.nodes_clocked_to_today())
     }
+
+    /// The node with a running clock in any document, along with the time it was started. If
+    /// several clocks are running, this is the one that was started last, which is the one that
+    /// `clock_out` returns.
+    pub fn running_clock(&self) -> Option<(&Node, DateTime)> {
+        self.documents()
+            .filter_map(|document| document.running_clock())
+            .max_by_key(|&(node, start)| (start, node.document_id))
+    }
+
+    /// Starts the clock on a node, stopping any clock that is running in another document first.
+    /// Returns false if the node doesn't exist.
+    pub fn clock_in(&mut self, document_id: DocumentId, node_id: NodeId, now: &DateTime) -> bool {
+        if self.document(document_id).and_then(|document| document.node(node_id)).is_none() {
+            return false;
+        }
+        self.clock_out(now);
+        self.document_mut(document_id).unwrap().clock_in(node_id, now)
+    }
+
+    /// Stops every running clock in the library, returning the document and node of the clock
+    /// that was started last
+    pub fn clock_out(&mut self, now: &DateTime) -> Option<(DocumentId, NodeId)> {
+        let mut stopped = Vec::new();
+        for (&document_id, document) in self.documents.iter_mut() {
+            while let Some((node_id, start)) = document.running_clock().map(|(node, start)| (node.id, start)) {
+                if document.clock_out(now) != Some(node_id) {
+                    break;
+                }
+                stopped.push((start, document_id, node_id));
+            }
+        }
+        stopped
+            .into_iter()
+            .max_by_key(|&(start, document_id, _)| (start, document_id))
+            .map(|(_, document_id, node_id)| (document_id, node_id))
+    }
+
+    /// The nodes in every document that match a tags/property query
+    pub fn select<'a>(&'a self, query: &'a Query) -> impl Iterator<Item = &'a Node> + 'a {
+        self.documents().flat_map(move |document| document.select(query))
+    }
+
+    /// Moves a node along with its children under a target node, or to the top level of the
+    /// target document for `None`, returning the new id of the node. Nodes that are moved to
+    /// another document get new ids.
+    pub fn refile(
+        &mut self,
+        document_id: DocumentId,
+        node_id: NodeId,
+        target_document_id: DocumentId,
+        target_id: Option<NodeId>,
+    ) -> Option<NodeId> {
+        if document_id == target_document_id {
+            let document = self.document_mut(document_id)?;
+            return if document.move_subtree(node_id, target_id) { Some(node_id) } else { None };
+        }
+
+        let target = self.document(target_document_id)?;
+        if let Some(target_id) = target_id {
+            target.node(target_id)?;
+        }
+
+        let subtree = self.document_mut(document_id)?.take_subtree(node_id)?;
+        let new_id = self.document_mut(target_document_id)?.insert_subtree(target_id, subtree);
+        self.reindex();
+        new_id
+    }
+
+    /// Finds a node by the value of its `:ID:` or `:CUSTOM_ID:` property
+    pub fn find_by_id(&self, id: &str) -> Option<&Node> {
+        self.nodes_with_id(id).into_iter().next()
+    }
+
+    fn nodes_with_id(&self, id: &str) -> Vec<&Node> {
+        self.ids.get(id)
+            .map(|locations| locations.as_slice())
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|&(document_id, node_id)| self.document(document_id)?.node(node_id))
+            // Skip nodes that have changed since the index was built
+            .filter(|node| has_id(node, id))
+            .collect()
+    }
+
+    /// The `:ID:` property of a node, adding a new one if the node doesn't have one yet
+    pub fn get_or_create_id(&mut self, document_id: DocumentId, node_id: NodeId) -> Option<String> {
+        let node = self.document_mut(document_id)?.node_mut(node_id)?;
+        let id = node.get_or_create_id().to_string();
+        let locations = self.ids.entry(id.clone()).or_default();
+        if !locations.contains(&(document_id, node_id)) {
+            locations.push((document_id, node_id));
+        }
+        Some(id)
+    }
+
+    /// The ids that belong to more than one node, along with those nodes
+    pub fn duplicate_ids(&self) -> Vec<(&str, Vec<&Node>)> {
+        let mut duplicates: Vec<(&str, Vec<&Node>)> = self.ids.keys()
+            .map(|id| (id.as_str(), self.nodes_with_id(id)))
+            .filter(|(_, nodes)| nodes.len() > 1)
+            .collect();
+        duplicates.sort_by_key(|(id, _)| *id);
+        duplicates
+    }
+
+    /// Rebuilds the index used by `find_by_id`, which is needed after ids are changed directly
+    /// through `document_mut`
+    pub fn reindex(&mut self) {
+        self.ids.clear();
+        let mut document_ids: Vec<DocumentId> = self.documents.keys().copied().collect();
+        document_ids.sort();
+        for document_id in document_ids {
+            self.index_document(document_id);
+        }
+    }
+
+    fn index_document(&mut self, document_id: DocumentId) {
+        let document = &self.documents[&document_id];
+        for node in document.all_nodes() {
+            let mut values: Vec<&str> = node.property("ID").into_iter()
+                .chain(node.property("CUSTOM_ID"))
+                .collect();
+            values.dedup();
+
+            for value in values {
+                self.ids.entry(value.to_string()).or_default().push((document_id, node.id));
+            }
+        }
+    }
+
+    /// The places that nodes can be refiled to, which are the top level of each document and
+    /// every node up to `max_depth` levels deep
+    pub fn refile_targets(&self, max_depth: usize) -> Vec<RefileTarget> {
+        let mut documents: Vec<&Document> = self.documents().collect();
+        documents.sort_by_key(|document| document.id);
+
+        let mut targets = Vec::new();
+        for document in documents {
+            let name = document.name();
+
+            targets.push(RefileTarget {
+                document_id: document.id,
+                node_id: None,
+                path: vec![name.clone()],
+            });
+
+            let nodes = document.pre_order().filter(|(depth, _)| *depth <= max_depth);
+            for (_, node) in nodes {
+                let mut path = vec![name.clone()];
+                path.extend(document.outline_path(node.id).into_iter().map(String::from));
+                targets.push(RefileTarget {
+                    document_id: document.id,
+                    node_id: Some(node.id),
+                    path,
+                });
+            }
+        }
+
+        targets
+    }
+}
+
+fn has_id(node: &Node, id: &str) -> bool {
+    node.property("ID") == Some(id) || node.property("CUSTOM_ID") == Some(id)
+}
+
+/// A place that a node can be refiled to, see `Library::refile_targets`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RefileTarget {
+    pub document_id: DocumentId,
+    /// The node to refile under, or `None` for the top level of the document
+    pub node_id: Option<NodeId>,
+    /// The name of the document followed by the outline path of the node
+    pub path: Vec<String>,
+}
+
+impl fmt::Display for RefileTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.path.join("/"))
+    }
+}
+
+impl Default for Library {
+    fn default() -> Self {
+        Library::new()
+    }
 }
 
 impl Index<DocumentId> for Library {
@@ -102,3 +328,166 @@ impl fmt::Debug for Library {
            .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refile() {
+        let mut library = Library::new();
+        let (inbox_id, _) = library.add(Document::from_string(
+            "#+TITLE: Inbox\n\
+             * TODO Call the bank\n\
+             ** Note\n\
+             * TODO Buy milk",
+        ));
+        let (projects_id, _) = library.add(Document::from_string(
+            "#+TITLE: Projects\n\
+             * Finances\n\
+             ** Taxes\n\
+             * Errands",
+        ));
+        let find = |library: &Library, document_id: DocumentId, title: &str| {
+            library[document_id].all_nodes().find(|node| node.title() == title).unwrap().id
+        };
+
+        let call_id = find(&library, inbox_id, "Call the bank");
+        let finances_id = find(&library, projects_id, "Finances");
+        let new_id = library.refile(inbox_id, call_id, projects_id, Some(finances_id)).unwrap();
+
+        assert_eq!(library[inbox_id].to_string(), "#+TITLE: Inbox\n* TODO Buy milk");
+        assert_eq!(
+            library[projects_id].to_string(),
+            "#+TITLE: Projects\n* Finances\n** Taxes\n** TODO Call the bank\n*** Note\n* Errands"
+        );
+        for node in library[projects_id].descendants(finances_id) {
+            assert_eq!(node.document_id, projects_id);
+            assert_eq!(library[projects_id].node(node.id).unwrap().title(), node.title());
+        }
+        assert_eq!(library[projects_id].outline_path(new_id), vec!["Finances", "Call the bank"]);
+
+        let errands_id = find(&library, projects_id, "Errands");
+        assert_eq!(library.refile(projects_id, finances_id, projects_id, Some(new_id)), None);
+        assert_eq!(
+            library.refile(projects_id, new_id, projects_id, Some(errands_id)),
+            Some(new_id)
+        );
+        assert_eq!(library.refile(inbox_id, call_id, projects_id, None), None);
+
+        let targets: Vec<String> = library
+            .refile_targets(1)
+            .iter()
+            .map(|target| target.to_string())
+            .collect();
+        assert_eq!(
+            targets,
+            vec!["Inbox", "Inbox/Buy milk", "Projects", "Projects/Finances", "Projects/Errands"]
+        );
+        assert_eq!(library.refile_targets(3).len(), 8);
+    }
+
+    #[test]
+    fn test_find_by_id() {
+        let mut library = Library::new();
+        let (first_id, _) = library.add(Document::from_string(
+            "* Meeting notes\n\
+             :PROPERTIES:\n\
+             :ID:       4a5f6a2e-1b3c-4d5e-8f90-123456789abc\n\
+             :END:\n\
+             * Reading list\n\
+             :PROPERTIES:\n\
+             :CUSTOM_ID: reading\n\
+             :END:\n\
+             * No id",
+        ));
+        library.add(Document::from_string(
+            "* Copied meeting notes\n\
+             :PROPERTIES:\n\
+             :ID:       4a5f6a2e-1b3c-4d5e-8f90-123456789abc\n\
+             :END:",
+        ));
+
+        let node = library.find_by_id("reading").unwrap();
+        assert_eq!(node.title(), "Reading list");
+        assert_eq!(node.document_id, first_id);
+        assert_eq!(
+            library.find_by_id("4a5f6a2e-1b3c-4d5e-8f90-123456789abc").unwrap().title(),
+            "Meeting notes"
+        );
+        assert!(library.find_by_id("missing").is_none());
+
+        let duplicates = library.duplicate_ids();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].0, "4a5f6a2e-1b3c-4d5e-8f90-123456789abc");
+        assert_eq!(
+            duplicates[0].1.iter().map(|node| node.title()).collect::<Vec<_>>(),
+            vec!["Meeting notes", "Copied meeting notes"]
+        );
+
+        let node_id = library[first_id].all_nodes().find(|node| node.title() == "No id").unwrap().id;
+        let id = library.get_or_create_id(first_id, node_id).unwrap();
+        assert_eq!(id.len(), 36);
+        assert_eq!(library.get_or_create_id(first_id, node_id).unwrap(), id);
+        assert_eq!(library.find_by_id(&id).unwrap().title(), "No id");
+        assert!(library[first_id]
+            .to_string()
+            .ends_with(&format!("* No id\n:PROPERTIES:\n:ID: {}\n:END:", id)));
+    }
+
+    #[test]
+    fn test_running_clock() {
+        let mut library = Library::new();
+        let (work_id, _) = library.add(Document::from_string("* Write report"));
+        let (home_id, _) = library.add(Document::from_string(
+            "* Water plants\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 09:00]\n\
+             :END:",
+        ));
+        let report_id = library[work_id].root_ids().next().unwrap();
+        let now = Date::from_ymd(2018, 10, 27).and_hms(9, 15, 0);
+
+        let (node, start) = library.running_clock().unwrap();
+        assert_eq!((node.document_id, node.title()), (home_id, "Water plants"));
+        assert_eq!(start, Date::from_ymd(2018, 10, 27).and_hms(9, 0, 0));
+
+        assert!(library.clock_in(work_id, report_id, &now));
+        assert_eq!(library.running_clock().unwrap().0.title(), "Write report");
+        assert!(library[home_id].to_string().contains("=>  0:15"));
+        assert_eq!(library.clock_out(&now), Some((work_id, report_id)));
+        assert!(library.running_clock().is_none());
+    }
+
+    #[test]
+    fn test_clock_out_stops_every_clock() {
+        let mut library = Library::new();
+        let (work_id, _) = library.add(Document::from_string(
+            "* Early\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 08:00]\n\
+             :END:\n\
+             * Late\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 10:00]\n\
+             :END:",
+        ));
+        library.add(Document::from_string(
+            "* Middle\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 09:00]\n\
+             :END:",
+        ));
+        let late_id = library[work_id].all_nodes().find(|node| node.title() == "Late").unwrap().id;
+        let now = Date::from_ymd(2018, 10, 27).and_hms(11, 0, 0);
+
+        let (running, start) = library.running_clock().unwrap();
+        assert_eq!((running.document_id, running.id), (work_id, late_id));
+        assert_eq!(start, Date::from_ymd(2018, 10, 27).and_hms(10, 0, 0));
+        assert_eq!(library[work_id].running_clock().unwrap().0.id, late_id);
+
+        assert_eq!(library.clock_out(&now), Some((work_id, late_id)));
+        assert!(library.running_clock().is_none());
+        assert_eq!(library.clock_out(&now), None);
+    }
+}
@@ -0,0 +1,246 @@
+use object::Object;
+use regex::Regex;
+use std::fmt;
+use utils::captures_at_start;
+
+/// Link types that are recognized in angle and plain links, and as typed targets of regular links
+const LINK_TYPES: &[&str] = &[
+    "attachment", "bbdb", "docview", "doi", "elisp", "file", "file+emacs", "file+sys", "ftp",
+    "gnus", "help", "http", "https", "id", "info", "irc", "mailto", "mhe", "news", "rmail",
+    "shell", "tel",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// `[[target][description]]` or `[[target]]`
+    Regular,
+    /// `<protocol:path>`
+    Angle,
+    /// `protocol:path`
+    Plain,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LinkTarget {
+    /// `file:projects.org::*Heading`, `./projects.org` or `/home/user/notes.org`
+    File { path: String, search: Option<String> },
+    /// `id:4c5e5b3c-...`
+    Id { id: String },
+    /// `https://orgmode.org`
+    Url { url: String },
+    /// `#custom-id`
+    CustomId { id: String },
+    /// `*Heading`
+    Heading { title: String },
+    /// `(coderef)`
+    Coderef { reference: String },
+    /// Any other text, which is matched against targets, names and headlines
+    Fuzzy { text: String },
+    /// Any other link type, such as `mailto:user@example.com`
+    Other { protocol: String, path: String },
+}
+
+impl LinkTarget {
+    pub fn parse(path: &str) -> LinkTarget {
+        if let Some((protocol, rest)) = split_protocol(path) {
+            match protocol {
+                "file" | "file+sys" | "file+emacs" => file_target(rest),
+                "id" => LinkTarget::Id { id: rest.to_string() },
+                "http" | "https" => LinkTarget::Url { url: path.to_string() },
+                protocol => LinkTarget::Other {
+                    protocol: protocol.to_string(),
+                    path: rest.to_string(),
+                },
+            }
+        } else if path.starts_with('/') || path.starts_with("./") || path.starts_with("../")
+            || path.starts_with("~/")
+        {
+            file_target(path)
+        } else if let Some(id) = path.strip_prefix('#') {
+            LinkTarget::CustomId { id: id.to_string() }
+        } else if let Some(title) = path.strip_prefix('*') {
+            LinkTarget::Heading { title: title.trim().to_string() }
+        } else if path.starts_with('(') && path.ends_with(')') && path.len() > 2 {
+            LinkTarget::Coderef { reference: path[1..path.len() - 1].to_string() }
+        } else {
+            LinkTarget::Fuzzy { text: path.to_string() }
+        }
+    }
+}
+
+fn file_target(path: &str) -> LinkTarget {
+    let mut split = path.splitn(2, "::");
+    LinkTarget::File {
+        path: split.next().unwrap_or("").to_string(),
+        search: split.next().map(|search| search.to_string()),
+    }
+}
+
+fn split_protocol(path: &str) -> Option<(&str, &str)> {
+    let index = path.find(':')?;
+    let protocol = &path[..index];
+
+    if LINK_TYPES.contains(&protocol) {
+        Some((protocol, &path[index + 1..]))
+    } else {
+        None
+    }
+}
+
+/// See <https://orgmode.org/worg/dev/org-syntax.html#Links>
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    pub kind: LinkKind,
+    /// The raw target, as written in the document
+    pub path: String,
+    pub target: LinkTarget,
+    pub description: Option<Vec<Object>>,
+}
+
+impl Link {
+    pub fn new(path: &str, description: Option<&str>) -> Link {
+        Link {
+            kind: LinkKind::Regular,
+            path: path.to_string(),
+            target: LinkTarget::parse(path),
+            description: description.map(Object::parse),
+        }
+    }
+
+    /// Parses a link at the start of the text, returning the link and its length in bytes
+    pub(crate) fn parse(text: &str, after_word: bool) -> Option<(Link, usize)> {
+        lazy_static! {
+            static ref REGULAR_REGEX: Regex = Regex::new(r#"(?x)
+                ^
+                \[\[
+                (?P<path>[^\[\]]+)
+                \]
+                (\[(?P<description>[^\[\]]*(\[[^\[\]]*\][^\[\]]*)*)\])?
+                \]
+            "#).unwrap();
+            static ref ANGLE_REGEX: Regex = Regex::new(r#"(?x)
+                ^
+                <
+                (?P<protocol>[a-z][a-z0-9+]*):
+                (?P<path>[^<>\[\]\n]+)
+                >
+            "#).unwrap();
+            static ref PLAIN_REGEX: Regex = Regex::new(r#"(?x)
+                ^
+                (?P<protocol>[a-z][a-z0-9+]*):
+                (?P<path>[^\s()<>\[\]"]*[^\s()<>\[\]".,;:!?'])
+            "#).unwrap();
+        }
+
+        if let Some(captures) = captures_at_start(&REGULAR_REGEX, text) {
+            let path = captures.name("path").unwrap().as_str();
+            let description = captures.name("description").map(|c| c.as_str());
+            Some((Link::new(path, description), captures[0].len()))
+        } else if let Some(captures) = captures_at_start(&ANGLE_REGEX, text) {
+            if !LINK_TYPES.contains(&captures.name("protocol").unwrap().as_str()) {
+                return None;
+            }
+            let path = &captures[0][1..captures[0].len() - 1];
+            Some((Link {
+                kind: LinkKind::Angle,
+                ..Link::new(path, None)
+            }, captures[0].len()))
+        } else if let Some(captures) = captures_at_start(&PLAIN_REGEX, text) {
+            if after_word || !LINK_TYPES.contains(&captures.name("protocol").unwrap().as_str()) {
+                return None;
+            }
+            Some((Link {
+                kind: LinkKind::Plain,
+                ..Link::new(&captures[0], None)
+            }, captures[0].len()))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            LinkKind::Regular => {
+                write!(f, "[[{}]", self.path)?;
+                if let Some(ref description) = self.description {
+                    write!(f, "[")?;
+                    for object in description {
+                        write!(f, "{}", object)?;
+                    }
+                    write!(f, "]")?;
+                }
+                write!(f, "]")
+            }
+            LinkKind::Angle => write!(f, "<{}>", self.path),
+            LinkKind::Plain => write!(f, "{}", self.path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Option<Link> {
+        Link::parse(text, false).map(|(link, _)| link)
+    }
+
+    #[test]
+    fn test_parse_targets() {
+        assert_eq!(LinkTarget::parse("file:projects.org::*Inbox"), LinkTarget::File {
+            path: "projects.org".to_string(),
+            search: Some("*Inbox".to_string()),
+        });
+        assert_eq!(LinkTarget::parse("~/notes.org"), LinkTarget::File {
+            path: "~/notes.org".to_string(),
+            search: None,
+        });
+        assert_eq!(LinkTarget::parse("id:1234-abcd"), LinkTarget::Id { id: "1234-abcd".to_string() });
+        assert_eq!(LinkTarget::parse("https://orgmode.org"), LinkTarget::Url {
+            url: "https://orgmode.org".to_string(),
+        });
+        assert_eq!(LinkTarget::parse("#my-id"), LinkTarget::CustomId { id: "my-id".to_string() });
+        assert_eq!(LinkTarget::parse("*Some heading"), LinkTarget::Heading {
+            title: "Some heading".to_string(),
+        });
+        assert_eq!(LinkTarget::parse("(jump)"), LinkTarget::Coderef { reference: "jump".to_string() });
+        assert_eq!(LinkTarget::parse("mailto:me@example.com"), LinkTarget::Other {
+            protocol: "mailto".to_string(),
+            path: "me@example.com".to_string(),
+        });
+        assert_eq!(LinkTarget::parse("Some target"), LinkTarget::Fuzzy { text: "Some target".to_string() });
+    }
+
+    #[test]
+    fn test_parse_regular_link() {
+        let link = parse("[[https://orgmode.org][The *Org* website]] and more").unwrap();
+        assert_eq!(link.kind, LinkKind::Regular);
+        assert_eq!(link.path, "https://orgmode.org");
+        assert_eq!(link.description.as_ref().unwrap().len(), 3);
+        assert_eq!(link.to_string(), "[[https://orgmode.org][The *Org* website]]");
+
+        assert_eq!(parse("[[#custom-id]]").unwrap().to_string(), "[[#custom-id]]");
+    }
+
+    #[test]
+    fn test_parse_angle_and_plain_links() {
+        let link = parse("<mailto:me@example.com> text").unwrap();
+        assert_eq!(link.kind, LinkKind::Angle);
+        assert_eq!(link.to_string(), "<mailto:me@example.com>");
+
+        let link = parse("https://orgmode.org/manual/, a website").unwrap();
+        assert_eq!(link.kind, LinkKind::Plain);
+        assert_eq!(link.path, "https://orgmode.org/manual/");
+
+        let link = parse("https://orgmode.org.").unwrap();
+        assert_eq!(link.path, "https://orgmode.org");
+
+        assert_eq!(parse("<2018-10-27 Sat>"), None);
+        assert_eq!(parse("note: not a link"), None);
+        assert_eq!(Link::parse("https://orgmode.org", true), None);
+    }
+}
@@ -1,11 +1,12 @@
 use element::Element;
 use itertools::Itertools;
+use keyword::AffiliatedKeywords;
 use parser::Parser;
 use regex::Regex;
+use settings::{Settings, DEFAULT_SETTINGS};
 use std::fmt;
 use text::Text;
 use utils::StringUtils;
-use std::cmp::min;
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -59,6 +60,8 @@ pub struct List {
     #[serde(rename = "type")]
     kind: ListKind,
     items: Vec<ListItem>,
+    #[serde(default, skip_serializing_if = "AffiliatedKeywords::is_empty")]
+    pub affiliated: AffiliatedKeywords,
 }
 
 impl List {
@@ -72,6 +75,7 @@ impl List {
             let mut list = List {
                 kind: list_kind,
                 items: vec![item],
+                affiliated: AffiliatedKeywords::default(),
             };
             let mut was_empty_line = false;
 
@@ -102,7 +106,7 @@ impl List {
                     if let Some(element) = Element::parse(&line, parser) {
                         list.items[item_count - 1].elements.push(element);
                     } else {
-                        list.items[item_count - 1].add_line(&line[min(line.indent(), indent + 1)..]);
+                        list.items[item_count - 1].add_line(line.trim_indent(indent + 1));
                     }
                 }
             }
@@ -112,16 +116,27 @@ impl List {
             None
         }
     }
-}
 
-impl fmt::Display for List {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    pub fn items(&self) -> &[ListItem] {
+        &self.items
+    }
+
+    /// Formats the list, filling the text of each item to the fill column
+    pub fn format(&self, settings: &Settings) -> String {
+        let mut output = String::new();
+        self.write(&mut output, settings).unwrap();
+        output
+    }
+
+    fn write(&self, f: &mut dyn fmt::Write, settings: &Settings) -> fmt::Result {
         let mut counter = Counter::start(self.kind);
         let mut is_first = true;
 
+        write!(f, "{}", self.affiliated)?;
+
         for item in self.items.iter() {
             if !is_first {
-                write!(f, "\n")?;
+                writeln!(f)?;
             } else {
                 is_first = false;
             }
@@ -155,27 +170,33 @@ impl fmt::Display for List {
                 }
             }
 
-            write!(f, " {}", item.text.format(3))?;
+            write!(f, " {}", item.text.format(3, settings))?;
 
             if let Some(ref definition) = item.definition {
                 write!(f, " :: {}", definition)?;
             }
 
             if !item.elements.is_empty() {
-                let mut elements = item.elements.iter()
-                    .map(|element| {
-                        if let Element::List(list) = element {
-                            list.to_string().lines().map(|line| &line[1..]).join("\n")
-                        } else {
-                            element.to_string()
-                        }
-                    })
-                    .join("\n");
                 let indent = match self.kind {
                     ListKind::Unordered | ListKind::Definition => 3,
                     ListKind::OrderedNumber | ListKind::OrderedLetter => 4,
                 };
-                write!(f, "\n{}", elements.add_indent(indent))?;
+                let elements = item.elements.iter()
+                    .map(|element| match element {
+                        Element::List(list) => {
+                            list.format(settings)
+                                .lines()
+                                .map(|line| line.strip_prefix(' ').unwrap_or(line))
+                                .join("\n")
+                                .add_indent(indent)
+                        }
+                        // Blocks keep their original indentation, since their contents are verbatim
+                        Element::Block(block) => block.to_string(),
+                        Element::DynamicBlock(block) => block.to_string(),
+                        element => element.format(settings).add_indent(indent),
+                    })
+                    .join("\n");
+                write!(f, "\n{}", elements)?;
             }
 
             counter.increment();
@@ -185,6 +206,12 @@ impl fmt::Display for List {
     }
 }
 
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &DEFAULT_SETTINGS)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ListItem {
     counter: Option<Counter>,
@@ -244,14 +271,13 @@ impl ListItem {
                 if let Ok(number) = counter_set.parse() {
                     Counter::Number(number)
                 } else {
-                    Counter::Letter(counter_set.chars().next().unwrap())
+                    Counter::Letter(counter_set.chars().next().unwrap_or('a'))
                 }
             });
             let checkbox = checkbox.map(|checkbox| match checkbox {
                 "x" | "X" => Checkbox::Checked,
-                " " => Checkbox::Unchecked,
                 "-" => Checkbox::Partial,
-                checkbox => panic!("Unsupported checkbox symbol: {}", checkbox),
+                _ => Checkbox::Unchecked,
             });
 
             Some(ParsedListItem {
@@ -270,8 +296,20 @@ impl ListItem {
         }
     }
 
+    pub fn text(&self) -> &Text {
+        &self.text
+    }
+
+    pub fn definition(&self) -> Option<&str> {
+        self.definition.as_deref()
+    }
+
+    pub fn elements(&self) -> &[Element] {
+        &self.elements
+    }
+
     fn add_line(&mut self, line: &str) {
-        if self.elements.len() > 0 {
+        if !self.elements.is_empty() {
             if let Some(Element::Paragraph(paragraph)) = self.elements.last_mut() {
                 if !paragraph.at_end() {
                     paragraph.add_line(line);
@@ -1,65 +1,545 @@
-use drawer::Drawer;
-use timestamp::{Duration, Timestamp, today, TIMESTAMP_REGEX};
+use regex::Regex;
+use std::fmt;
+use timestamp::{
+    self, parse_duration, today, DateTime, Duration, Timestamp, TimestampKind, TIMESTAMP_REGEX,
+};
 
+/// A log drawer, usually `:LOGBOOK:`, with the most recent items first
+///
+/// See <https://orgmode.org/manual/Tracking-TODO-state-changes.html>
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Logbook {
-    entries: Vec<ClockEntry>,
+    /// The name of the drawer
+    pub name: String,
+    pub items: Vec<LogbookItem>,
 }
 
 impl Logbook {
-    pub fn from(drawer: Option<&Drawer>) -> Logbook {
-        let entries = if let Some(drawer) = drawer {
-            drawer
-                .contents
-                .iter()
-                .filter_map(|line| ClockEntry::parse(line))
-                .collect()
-        } else {
-            Vec::new()
-        };
+    pub fn new(name: &str) -> Self {
+        Logbook {
+            name: name.to_string(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Parses the lines inside a log drawer. Lines that aren't recognized are kept as they were
+    /// written.
+    pub fn parse(name: &str, lines: &[String]) -> Self {
+        Logbook::parse_at(name, lines, None)
+    }
+
+    /// Parses the lines inside a log drawer, where the first line is at the given line number of
+    /// the file
+    pub(crate) fn parse_at(name: &str, lines: &[String], first_line: Option<u32>) -> Self {
+        let mut items: Vec<LogbookItem> = Vec::new();
+        let mut lines = lines.iter().zip(0..).peekable();
+
+        while let Some((line, index)) = lines.next() {
+            let item = if let Some(mut clock) = ClockEntry::parse(line) {
+                clock.line = first_line.map(|first_line| first_line + index);
+                LogbookItem::Clock(clock)
+            } else if let Some(mut item) = LogbookItem::parse_heading(line) {
+                // The note is indented below the heading, which ends with `\\`. Its lines are
+                // kept with their indentation, since they may contain lists or other markup.
+                while let Some((next, _)) = lines.peek() {
+                    if !next.starts_with(char::is_whitespace) || is_item_start(next) {
+                        break;
+                    }
+                    item.note_mut().unwrap().push(next.to_string());
+                    lines.next();
+                }
+                item
+            } else {
+                LogbookItem::Other {
+                    text: line.to_string(),
+                }
+            };
+            items.push(item);
+        }
+
+        Logbook {
+            name: name.to_string(),
+            items,
+        }
+    }
+
+    /// Adds an item to the top of the logbook, like Org does
+    pub fn add(&mut self, item: LogbookItem) {
+        self.items.insert(0, item);
+    }
+
+    pub fn clocks(&self) -> impl Iterator<Item = &ClockEntry> {
+        self.items.iter().filter_map(|item| match item {
+            LogbookItem::Clock(clock) => Some(clock),
+            _ => None,
+        })
+    }
 
-        Logbook { entries }
+    /// The clock that was started but not stopped yet, if any
+    pub fn running_clock(&self) -> Option<&ClockEntry> {
+        self.clocks().find(|clock| clock.is_running())
+    }
+
+    pub fn running_clock_mut(&mut self) -> Option<&mut ClockEntry> {
+        self.items.iter_mut().filter_map(|item| match item {
+            LogbookItem::Clock(clock) if clock.is_running() => Some(clock),
+            _ => None,
+        }).next()
     }
 
     pub fn time_spent(&self) -> Duration {
-        self.entries
-            .iter()
-            .map(|entry| entry.time_spent())
+        self.clocks()
+            .map(|clock| clock.time_spent())
             .fold(Duration::zero(), |total, duration| total + duration)
     }
 
     pub fn time_spent_today(&self) -> Duration {
         let today = today();
-        self.entries
-            .iter()
-            .filter(|entry| entry.timestamp == today)
-            .map(|entry| entry.time_spent())
+        self.clocks()
+            .filter(|clock| clock.timestamp == today)
+            .map(|clock| clock.time_spent())
             .fold(Duration::zero(), |total, duration| total + duration)
     }
 
     pub fn was_clocked_to_today(&self) -> bool {
         let today = today();
-        self.entries.iter().any(|entry| entry.timestamp == today)
+        self.clocks().any(|clock| clock.timestamp == today)
+    }
+}
+
+impl fmt::Display for Logbook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, ":{}:", self.name)?;
+        for item in self.items.iter() {
+            writeln!(f, "{}", item)?;
+        }
+        write!(f, ":END:")
     }
 }
 
+/// An item in a logbook, using the headings from `org-log-note-headings`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LogbookItem {
+    Clock(ClockEntry),
+    /// `- State "DONE"       from "TODO"       [2018-10-27 Sat 20:09]`
+    StateChange {
+        to: Option<String>,
+        from: Option<String>,
+        timestamp: Timestamp,
+        note: Vec<String>,
+    },
+    /// `- Note taken on [2018-10-27 Sat 20:09] \\`
+    Note { timestamp: Timestamp, note: Vec<String> },
+    /// `- CLOSING NOTE [2018-10-27 Sat 20:09] \\`
+    ClosingNote { timestamp: Timestamp, note: Vec<String> },
+    /// `- Rescheduled from "[2018-10-20 Sat]" on [2018-10-27 Sat 20:09]`
+    Rescheduled {
+        from: Timestamp,
+        timestamp: Timestamp,
+        note: Vec<String>,
+    },
+    /// `- Not scheduled, was "[2018-10-20 Sat]" on [2018-10-27 Sat 20:09]`
+    Unscheduled {
+        was: Timestamp,
+        timestamp: Timestamp,
+        note: Vec<String>,
+    },
+    /// `- New deadline from "[2018-10-20 Sat]" on [2018-10-27 Sat 20:09]`
+    NewDeadline {
+        from: Timestamp,
+        timestamp: Timestamp,
+        note: Vec<String>,
+    },
+    /// `- Removed deadline, was "[2018-10-20 Sat]" on [2018-10-27 Sat 20:09]`
+    RemovedDeadline {
+        was: Timestamp,
+        timestamp: Timestamp,
+        note: Vec<String>,
+    },
+    /// `- Refiled on [2018-10-27 Sat 20:09]`
+    Refiled { timestamp: Timestamp, note: Vec<String> },
+    /// A line that isn't a clock entry or log note
+    Other { text: String },
+}
+
+impl LogbookItem {
+    /// A state change from one TODO keyword to another at the given time, without a note
+    pub fn state_change(to: Option<&str>, from: Option<&str>, time: &DateTime) -> Self {
+        LogbookItem::StateChange {
+            to: to.map(|keyword| keyword.to_string()),
+            from: from.map(|keyword| keyword.to_string()),
+            timestamp: Timestamp::from_datetime(time, TimestampKind::Inactive),
+            note: Vec::new(),
+        }
+    }
+
+    /// The time the item was logged, or the start of a clock
+    pub fn timestamp(&self) -> Option<&Timestamp> {
+        match self {
+            LogbookItem::Clock(clock) => Some(&clock.timestamp),
+            LogbookItem::StateChange { timestamp, .. }
+            | LogbookItem::Note { timestamp, .. }
+            | LogbookItem::ClosingNote { timestamp, .. }
+            | LogbookItem::Rescheduled { timestamp, .. }
+            | LogbookItem::Unscheduled { timestamp, .. }
+            | LogbookItem::NewDeadline { timestamp, .. }
+            | LogbookItem::RemovedDeadline { timestamp, .. }
+            | LogbookItem::Refiled { timestamp, .. } => Some(timestamp),
+            LogbookItem::Other { .. } => None,
+        }
+    }
+
+    /// The lines of the note below the heading of a log entry, as they were written, including
+    /// their indentation
+    pub fn note(&self) -> Option<&[String]> {
+        match self {
+            LogbookItem::StateChange { note, .. }
+            | LogbookItem::Note { note, .. }
+            | LogbookItem::ClosingNote { note, .. }
+            | LogbookItem::Rescheduled { note, .. }
+            | LogbookItem::Unscheduled { note, .. }
+            | LogbookItem::NewDeadline { note, .. }
+            | LogbookItem::RemovedDeadline { note, .. }
+            | LogbookItem::Refiled { note, .. } => Some(note),
+            LogbookItem::Clock(_) | LogbookItem::Other { .. } => None,
+        }
+    }
+
+    pub fn note_mut(&mut self) -> Option<&mut Vec<String>> {
+        match self {
+            LogbookItem::StateChange { note, .. }
+            | LogbookItem::Note { note, .. }
+            | LogbookItem::ClosingNote { note, .. }
+            | LogbookItem::Rescheduled { note, .. }
+            | LogbookItem::Unscheduled { note, .. }
+            | LogbookItem::NewDeadline { note, .. }
+            | LogbookItem::RemovedDeadline { note, .. }
+            | LogbookItem::Refiled { note, .. } => Some(note),
+            LogbookItem::Clock(_) | LogbookItem::Other { .. } => None,
+        }
+    }
+
+    /// Parses the first line of a log entry, without its note
+    fn parse_heading(line: &str) -> Option<LogbookItem> {
+        lazy_static! {
+            static ref HEADING_REGEX: Regex =
+                Regex::new(r#"^\s*-\s+(?P<heading>.*?)\s*(\\\\)?\s*$"#).unwrap();
+            static ref STATE_REGEX: Regex = Regex::new(r#"(?x)
+                ^State
+                \s+ (?:"(?P<to>[^"]*)"\s+)?
+                from
+                \s+ (?:"(?P<from>[^"]*)"\s+)?
+                (?P<timestamp>\[[^\]]*\])$
+            "#).unwrap();
+            static ref OTHER_REGEX: Regex = Regex::new(r#"(?x)
+                ^(?P<kind>
+                    CLOSING\ NOTE |
+                    Note\ taken\ on |
+                    Rescheduled\ from |
+                    Not\ scheduled,\ was |
+                    New\ deadline\ from |
+                    Removed\ deadline,\ was |
+                    Refiled\ on
+                )
+                \s+
+                (?:"(?P<old>[^"]*)"\s+on\s+)?
+                (?P<timestamp>\[[^\]]*\])$
+            "#).unwrap();
+        }
+
+        let captures = HEADING_REGEX.captures(line)?;
+        let heading = captures.name("heading").unwrap().as_str();
+        let note = Vec::new();
+
+        let item = if let Some(captures) = STATE_REGEX.captures(heading) {
+            let keyword = |name| {
+                captures.name(name).map(|c| c.as_str().to_string()).filter(|k| !k.is_empty())
+            };
+            LogbookItem::StateChange {
+                to: keyword("to"),
+                from: keyword("from"),
+                timestamp: parse_inactive(&captures["timestamp"])?,
+                note,
+            }
+        } else {
+            let captures = OTHER_REGEX.captures(heading)?;
+            let timestamp = parse_inactive(&captures["timestamp"])?;
+            let old = captures.name("old").map(|c| c.as_str());
+
+            match (&captures["kind"], old) {
+                ("CLOSING NOTE", None) => LogbookItem::ClosingNote { timestamp, note },
+                ("Note taken on", None) => LogbookItem::Note { timestamp, note },
+                ("Refiled on", None) => LogbookItem::Refiled { timestamp, note },
+                ("Rescheduled from", Some(old)) => LogbookItem::Rescheduled {
+                    from: Timestamp::parse(old)?,
+                    timestamp,
+                    note,
+                },
+                ("Not scheduled, was", Some(old)) => LogbookItem::Unscheduled {
+                    was: Timestamp::parse(old)?,
+                    timestamp,
+                    note,
+                },
+                ("New deadline from", Some(old)) => LogbookItem::NewDeadline {
+                    from: Timestamp::parse(old)?,
+                    timestamp,
+                    note,
+                },
+                ("Removed deadline, was", Some(old)) => LogbookItem::RemovedDeadline {
+                    was: Timestamp::parse(old)?,
+                    timestamp,
+                    note,
+                },
+                _ => return None,
+            }
+        };
+
+        Some(item)
+    }
+
+    fn heading(&self) -> String {
+        let quote = |keyword: &Option<String>| {
+            keyword.as_ref().map(|keyword| format!("\"{}\"", keyword)).unwrap_or_default()
+        };
+        match self {
+            LogbookItem::StateChange { to, from, timestamp, .. } => {
+                format!("State {:<12} from {:<12} {}", quote(to), quote(from), timestamp)
+            }
+            LogbookItem::Note { timestamp, .. } => format!("Note taken on {}", timestamp),
+            LogbookItem::ClosingNote { timestamp, .. } => format!("CLOSING NOTE {}", timestamp),
+            LogbookItem::Rescheduled { from, timestamp, .. } => {
+                format!("Rescheduled from \"{}\" on {}", from, timestamp)
+            }
+            LogbookItem::Unscheduled { was, timestamp, .. } => {
+                format!("Not scheduled, was \"{}\" on {}", was, timestamp)
+            }
+            LogbookItem::NewDeadline { from, timestamp, .. } => {
+                format!("New deadline from \"{}\" on {}", from, timestamp)
+            }
+            LogbookItem::RemovedDeadline { was, timestamp, .. } => {
+                format!("Removed deadline, was \"{}\" on {}", was, timestamp)
+            }
+            LogbookItem::Refiled { timestamp, .. } => format!("Refiled on {}", timestamp),
+            LogbookItem::Clock(_) | LogbookItem::Other { .. } => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for LogbookItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogbookItem::Clock(clock) => write!(f, "{}", clock),
+            LogbookItem::Other { text } => write!(f, "{}", text),
+            _ => {
+                let note = self.note().unwrap();
+                write!(f, "- {}", self.heading())?;
+                if !note.is_empty() {
+                    write!(f, " \\\\")?;
+                }
+                for line in note.iter() {
+                    write!(f, "\n{}", line)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A `CLOCK:` line, such as `CLOCK: [2018-10-27 Sat 09:00]--[2018-10-27 Sat 10:23] =>  1:23`
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ClockEntry {
-    timestamp: Timestamp,
+    pub timestamp: Timestamp,
+    /// The duration after `=>`, as it was recorded when the clock was stopped
+    #[serde(with = "timestamp::optional_minutes")]
+    pub duration: Option<Duration>,
+    /// The 1-based line number of the entry in the file it was parsed from
+    #[serde(skip)]
+    pub line: Option<u32>,
 }
 
 impl ClockEntry {
+    /// A clock that was started at the given time and is still running
+    pub fn new(start: &DateTime) -> Self {
+        ClockEntry {
+            timestamp: Timestamp::from_datetime(start, TimestampKind::Inactive),
+            duration: None,
+            line: None,
+        }
+    }
+
+    /// Whether the clock has no end yet, as in `CLOCK: [2018-10-27 Sat 09:00]`
+    pub fn is_running(&self) -> bool {
+        self.timestamp.end_date.is_none() && self.timestamp.end_time.is_none()
+    }
+
+    pub fn start(&self) -> Option<DateTime> {
+        self.timestamp.time.map(|time| self.timestamp.date.and_time(time))
+    }
+
+    /// The time the clock was stopped, if it isn't running
+    pub fn end(&self) -> Option<DateTime> {
+        let time = self.timestamp.end_time?;
+        Some(self.timestamp.end_date.unwrap_or(self.timestamp.date).and_time(time))
+    }
+
+    /// The start and end of the clock, cut off at the start and end of a period that may be open
+    /// on either side. Returns `None` if the clock is running or isn't in the period.
+    pub fn clipped(&self, start: Option<DateTime>, end: Option<DateTime>) -> Option<(DateTime, DateTime)> {
+        let clock_start = self.start()?;
+        let clock_end = self.end()?;
+        let clock_start = start.map_or(clock_start, |start| clock_start.max(start));
+        let clock_end = end.map_or(clock_end, |end| clock_end.min(end));
+
+        if clock_start < clock_end {
+            Some((clock_start, clock_end))
+        } else {
+            None
+        }
+    }
+
+    /// Stops the clock at the given time and records the duration
+    pub fn stop(&mut self, end: &DateTime) {
+        let end = Timestamp::from_datetime(end, TimestampKind::Inactive);
+        self.timestamp.end_date = Some(end.date);
+        self.timestamp.end_time = end.time;
+        self.duration = Some(self.timestamp.duration());
+    }
+
     pub fn time_spent(&self) -> Duration {
         self.timestamp.duration()
     }
 
     fn parse(line: &str) -> Option<ClockEntry> {
-        if line.starts_with("CLOCK:") {
-            if let Some(captures) = TIMESTAMP_REGEX.captures(line) {
-                Timestamp::parse(&captures[0]).map(|timestamp| ClockEntry { timestamp })
-            } else {
-                None
+        lazy_static! {
+            static ref REGEX: Regex = Regex::new(r#"(?x)
+                ^\s*CLOCK:\s*
+                (?P<timestamp>\S.*?)
+                (\s*=>\s*(?P<negative>-)?(?P<duration>\d+:\d+))?
+                \s*$
+            "#).unwrap();
+        }
+
+        let captures = REGEX.captures(line)?;
+        let text = &captures["timestamp"];
+        if TIMESTAMP_REGEX.find(text).map(|m| m.as_str()) != Some(text) {
+            return None;
+        }
+        let timestamp = Timestamp::parse(text)?;
+        // Org records a negative duration if the clock was stopped before it was started
+        let duration = match captures.name("duration") {
+            Some(duration) if captures.name("negative").is_some() => {
+                Some(-parse_duration(duration.as_str())?)
             }
-        } else {
-            None
+            Some(duration) => Some(parse_duration(duration.as_str())?),
+            None => None,
+        };
+
+        Some(ClockEntry {
+            timestamp,
+            duration,
+            line: None,
+        })
+    }
+}
+
+impl fmt::Display for ClockEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CLOCK: {}", self.timestamp)?;
+        if let Some(duration) = self.duration {
+            let minutes = duration.num_minutes();
+            let sign = if minutes < 0 { "-" } else { "" };
+            let hours = format!("{}{}", sign, minutes.abs() / 60);
+            write!(f, " => {:>2}:{:02}", hours, minutes.abs() % 60)?;
         }
+        Ok(())
+    }
+}
+
+fn is_item_start(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("- ") || line.starts_with("CLOCK:")
+}
+
+fn parse_inactive(text: &str) -> Option<Timestamp> {
+    Timestamp::parse(text).filter(|timestamp| !timestamp.is_active())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let lines = vec![
+            "CLOCK: [2018-10-27 Sat 09:00]",
+            "- State \"DONE\"       from \"TODO\"       [2018-10-27 Sat 20:09] \\\\",
+            "  Finally finished",
+            "  after two days:",
+            "    indented further",
+            "- State \"TODO\"       from              [2018-10-25 Thu 08:00]",
+            "- Note taken on [2018-10-26 Fri 12:00] \\\\",
+            "  Waiting on Jane",
+            "- CLOSING NOTE [2018-10-26 Fri 12:05]",
+            "- Rescheduled from \"[2018-10-20 Sat]\" on [2018-10-24 Wed 10:00]",
+            "- Not scheduled, was \"[2018-10-21 Sun]\" on [2018-10-24 Wed 10:01]",
+            "- New deadline from \"[2018-10-30 Tue]\" on [2018-10-24 Wed 10:02]",
+            "- Removed deadline, was \"[2018-10-31 Wed]\" on [2018-10-24 Wed 10:03]",
+            "- Refiled on [2018-10-24 Wed 10:04]",
+            "CLOCK: [2018-10-26 Fri 09:00]--[2018-10-26 Fri 19:30] => 10:30",
+            "Some other line",
+        ];
+        let lines: Vec<String> = lines.into_iter().map(String::from).collect();
+        let logbook = Logbook::parse("LOGBOOK", &lines);
+
+        assert_eq!(logbook.to_string(), format!(":LOGBOOK:\n{}\n:END:", lines.join("\n")));
+        assert_eq!(logbook.items.len(), 12);
+        assert!(logbook.running_clock().is_some());
+        assert_eq!(logbook.time_spent(), Duration::minutes(630));
+
+        match logbook.items[1] {
+            LogbookItem::StateChange { ref to, ref from, ref note, .. } => {
+                assert_eq!(to.as_ref().map(|s| s.as_str()), Some("DONE"));
+                assert_eq!(from.as_ref().map(|s| s.as_str()), Some("TODO"));
+                assert_eq!(note, &vec!["  Finally finished", "  after two days:", "    indented further"]);
+            }
+            _ => panic!("Expected a state change"),
+        }
+        match logbook.items[2] {
+            LogbookItem::StateChange { ref from, .. } => assert_eq!(from, &None),
+            _ => panic!("Expected a state change"),
+        }
+    }
+
+    #[test]
+    fn test_clock() {
+        let start = ::timestamp::Date::from_ymd(2018, 10, 27).and_hms(9, 0, 0);
+        let mut clock = ClockEntry::new(&start);
+        assert_eq!(clock.to_string(), "CLOCK: [2018-10-27 Sat 09:00]");
+
+        clock.stop(&start.date().and_hms(10, 23, 59));
+        assert_eq!(
+            clock.to_string(),
+            "CLOCK: [2018-10-27 Sat 09:00]--[2018-10-27 Sat 10:23] =>  1:23"
+        );
+        assert!(ClockEntry::parse("CLOCK: [2018-10-27 Sat 09:00] => oops").is_none());
+
+        let source = "CLOCK: [2018-10-27 Sat 10:00]--[2018-10-27 Sat 09:30] => -0:30";
+        let clock = ClockEntry::parse(source).unwrap();
+        assert_eq!(clock.duration, Some(Duration::minutes(-30)));
+        assert_eq!(clock.time_spent(), Duration::minutes(-30));
+        assert_eq!(clock.to_string(), source);
+    }
+
+    #[test]
+    fn test_clipped_clock() {
+        let at = |hour| ::timestamp::Date::from_ymd(2018, 10, 27).and_hms(hour, 0, 0);
+        let clock = ClockEntry::parse("CLOCK: [2018-10-27 Sat 09:00]--[2018-10-27 Sat 12:00] =>  3:00").unwrap();
+
+        assert_eq!(clock.clipped(None, None), Some((at(9), at(12))));
+        assert_eq!(clock.clipped(Some(at(10)), None), Some((at(10), at(12))));
+        assert_eq!(clock.clipped(None, Some(at(11))), Some((at(9), at(11))));
+        assert_eq!(clock.clipped(Some(at(12)), None), None);
+        assert_eq!(ClockEntry::new(&at(9)).clipped(None, None), None);
     }
 }
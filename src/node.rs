@@ -1,13 +1,16 @@
 use drawer::Drawer;
-use element::Element;
+use element::{self, Element};
 use headline::Headline;
 use logbook::Logbook;
+use object::Object;
 use planning::Planning;
-use regex::Regex;
+use properties::Properties;
 use section::Section;
-use std::collections::HashMap;
+use settings::{Settings, DEFAULT_SETTINGS};
+use source::{self, Source};
 use std::fmt;
-use timestamp::{Date, Duration, Timestamp};
+use timestamp::{parse_duration, Date, Duration, Timestamp, TimestampKind};
+use uuid::Uuid;
 use document::DocumentId;
 
 pub type NodeId = usize;
@@ -20,11 +23,13 @@ pub struct Node {
     pub indent: u16,
     #[serde(flatten)]
     pub headline: Headline,
-    pub properties: HashMap<String, String>,
+    pub properties: Properties,
     pub section: Section,
     pub scheduled_for: Option<Timestamp>,
     pub deadline: Option<Timestamp>,
     pub closed_at: Option<Timestamp>,
+    #[serde(skip)]
+    source: Option<Source>,
 }
 
 impl Node {
@@ -36,19 +41,104 @@ impl Node {
         }
     }
 
+    /// Sets the level of the node, which is the number of stars in its headline
+    pub fn set_indent(&mut self, indent: u16) {
+        self.indent = indent;
+        self.headline.indent = indent;
+    }
+
     pub fn title(&self) -> &str {
         &self.headline.title
     }
 
+    pub fn is_done(&self) -> bool {
+        self.headline.is_done
+    }
+
+    /// All objects in the headline title and section
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        element::descendants(&self.headline.title_objects)
+            .into_iter()
+            .chain(self.section.objects())
+    }
+
+    /// The original text of the headline, planning info and properties, for documents parsed in
+    /// lossless mode
+    pub fn source(&self) -> Option<&Source> {
+        self.source.as_ref()
+    }
+
+    pub(crate) fn add_source(&mut self, source: Source) {
+        match self.source {
+            Some(ref mut existing) => existing.extend(source),
+            None => self.source = Some(source),
+        }
+    }
+
+    pub(crate) fn finish_sources(&mut self, settings: &Settings) {
+        let head = self.format_head(settings);
+        if let Some(ref mut source) = self.source {
+            source.set_formatted(&head);
+        }
+        self.section.finish_sources(settings);
+    }
+
+    pub fn format(&self, settings: &Settings) -> String {
+        let head = self.format_head(settings);
+        if self.section.is_empty() {
+            head
+        } else {
+            format!("{}\n{}", head, self.section.format(settings))
+        }
+    }
+
+    /// Writes the node using the original text of each region that hasn't changed
+    pub(crate) fn write_lossless(&self, output: &mut String, settings: &Settings) {
+        let head = self.format_head(settings);
+
+        match self.source {
+            Some(ref source) if source.matches(&head) => source::push_region(output, &source.text),
+            _ => source::push_region(output, &(head + "\n")),
+        }
+        self.section.write_lossless(output, settings);
+    }
+
+    /// The headline, planning info and properties drawer
+    fn format_head(&self, settings: &Settings) -> String {
+        let mut head = self.headline.format(settings);
+        if let Some(planning) = self.planning() {
+            head += &format!("\n{}", planning);
+        }
+        if let Some(drawer) = self.properties_drawer() {
+            head += &format!("\n{}", drawer);
+        }
+        head
+    }
+
     pub fn add_line(&mut self, line: &str) {
         self.section.add_line(line)
     }
 
+    /// Updates the timestamps of the section after the planning info has changed
+    pub(crate) fn update_planning_timestamps(&mut self) {
+        let timestamps = &mut self.section.timestamps.timestamps;
+        timestamps.retain(|timestamp| match timestamp.kind {
+            TimestampKind::Scheduled | TimestampKind::Deadline | TimestampKind::Closed => false,
+            TimestampKind::Active | TimestampKind::Inactive => true,
+        });
+        timestamps.extend(
+            vec![&self.deadline, &self.scheduled_for, &self.closed_at]
+                .into_iter()
+                .filter_map(|timestamp| timestamp.clone()),
+        );
+        timestamps.sort();
+    }
+
     pub fn set_planning(&mut self, planning: Planning, line: &str) {
         if self.has_planning() {
-            org_warning!("Planning info already set");
+            org_warning!(Duplicate, "Planning info already set");
         } else if !self.section.is_empty() || !self.properties.is_empty() {
-            org_warning!("Planning info must come immediately after the headline");
+            org_warning!(Misplaced, "Planning info must come immediately after the headline");
             self.add_line(line);
         } else {
             self.deadline = planning.deadline;
@@ -103,6 +193,15 @@ impl Node {
         self.section.timestamps_for_date(date)
     }
 
+    /// The timestamps on a date, where scheduled items and deadlines only repeat after `today`
+    pub fn timestamps_for_date_at<'a>(
+        &'a self,
+        date: &'a Date,
+        today: Date,
+    ) -> impl Iterator<Item = Timestamp> + 'a {
+        self.section.timestamps_for_date_at(date, today)
+    }
+
     pub fn is_past_scheduled(&self) -> bool {
         self.scheduled_for
             .as_ref()
@@ -117,8 +216,34 @@ impl Node {
             .unwrap_or(false)
     }
 
+    /// The value of a property of the node itself, ignoring `NAME+` additions and inheritance.
+    /// See `Document::node_property` for the full value.
     pub fn property(&self, name: &str) -> Option<&str> {
-        self.properties.get(name).map(|prop| prop.as_str())
+        self.properties.get(name)
+    }
+
+    /// Sets a property in the property drawer, replacing any existing value
+    pub fn set_property(&mut self, name: &str, value: &str) {
+        self.properties.set(name, value);
+    }
+
+    /// Removes a property from the property drawer, returning its previous value
+    pub fn delete_property(&mut self, name: &str) -> Option<String> {
+        self.properties.remove(name)
+    }
+
+    /// The value of the `:ID:` property, which is a durable handle for the node
+    pub fn id_property(&self) -> Option<&str> {
+        self.property("ID")
+    }
+
+    /// The value of the `:ID:` property, generating a new UUID and adding it to the property
+    /// drawer if the node doesn't have one yet
+    pub fn get_or_create_id(&mut self) -> &str {
+        if !self.properties.contains("ID") {
+            self.properties.set("ID", &Uuid::new_v4().to_string());
+        }
+        self.properties.get("ID").unwrap()
     }
 
     pub fn has_tag(&self, tag: &str) -> bool {
@@ -130,23 +255,7 @@ impl Node {
     }
 
     pub fn effort(&self) -> Option<Duration> {
-        lazy_static! {
-            static ref REGEX: Regex = Regex::new(
-                r#"^(?P<hours>\d+):(?P<minutes>\d+)$"#
-            ).unwrap();
-        }
-
-        if let Some(captures) = self
-            .property("Effort")
-            .and_then(|effort| REGEX.captures(effort))
-        {
-            let hours: i64 = captures.name("hours").unwrap().as_str().parse().unwrap();
-            let minutes: i64 = captures.name("minutes").unwrap().as_str().parse().unwrap();
-
-            Some(Duration::hours(hours) + Duration::minutes(minutes))
-        } else {
-            None
-        }
+        self.property("Effort").and_then(parse_duration)
     }
 
     pub fn drawer(&self, name: &str) -> Option<&Drawer> {
@@ -159,9 +268,46 @@ impl Node {
             }).next()
     }
 
-    // TODO: Avoid reparsing the logbook from the drawer each time it is accessed
-    pub fn logbook(&self) -> Logbook {
-        Logbook::from(self.drawer("LOGBOOK"))
+    pub fn drawer_mut(&mut self, name: &str) -> Option<&mut Drawer> {
+        self.section
+            .elements
+            .iter_mut()
+            .filter_map(|element| match element {
+                Element::Drawer(drawer) if drawer.name == name => Some(drawer),
+                _ => None,
+            }).next()
+    }
+
+    /// The logbook of the node, which is empty if the node doesn't have one
+    pub fn logbook(&self) -> &Logbook {
+        lazy_static! {
+            static ref EMPTY: Logbook = Logbook::new(&DEFAULT_SETTINGS.log_drawer);
+        }
+        self.section
+            .elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Logbook(logbook) => Some(logbook),
+                _ => None,
+            }).next()
+            .unwrap_or(&EMPTY)
+    }
+
+    /// The logbook of the node, adding an empty log drawer with the given name before the rest of
+    /// the section if the node doesn't have one yet
+    pub fn logbook_mut(&mut self, drawer_name: &str) -> &mut Logbook {
+        let index = self.section.elements.iter().position(|element| {
+            matches!(element, Element::Logbook(_))
+        });
+        let index = index.unwrap_or_else(|| {
+            self.section.elements.insert(0, Element::Logbook(Logbook::new(drawer_name)));
+            0
+        });
+
+        match self.section.elements[index] {
+            Element::Logbook(ref mut logbook) => logbook,
+            _ => unreachable!(),
+        }
     }
 
     pub fn time_spent_today(&self) -> Duration {
@@ -175,15 +321,6 @@ impl Node {
 
 impl fmt::Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.headline)?;
-        if let Some(planning) = self.planning() {
-            write!(f, "\n{}", planning)?;
-        }
-        if let Some(drawer) = self.properties_drawer() {
-            write!(f, "\n{}", drawer)?;
-        }
-        write!(f, "\n{}", self.section)?;
-
-        Ok(())
+        write!(f, "{}", self.format(&DEFAULT_SETTINGS))
     }
 }
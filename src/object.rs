@@ -0,0 +1,298 @@
+use footnote::FootnoteReference;
+use link::Link;
+use std::fmt;
+
+/// An inline object inside a paragraph, headline title or list item
+///
+/// See <https://orgmode.org/worg/dev/org-syntax.html#Objects>
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Object {
+    Text { text: String },
+    Bold { objects: Vec<Object> },
+    Italic { objects: Vec<Object> },
+    Underline { objects: Vec<Object> },
+    StrikeThrough { objects: Vec<Object> },
+    Verbatim { text: String },
+    Code { text: String },
+    Link(Link),
+    FootnoteReference(FootnoteReference),
+}
+
+impl Object {
+    /// Parses a string into a list of objects. Anything that isn't markup becomes plain text.
+    pub fn parse(text: &str) -> Vec<Object> {
+        Object::parse_lines(text, &[])
+    }
+
+    /// Parses text that was joined from several lines, where `line_starts` are the offsets of the
+    /// lines after the first. Like in Org, emphasis can span at most two lines, see
+    /// `org-emphasis-regexp-components`.
+    pub(crate) fn parse_lines(text: &str, line_starts: &[usize]) -> Vec<Object> {
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let mut objects = Vec::new();
+        let mut plain_start = 0;
+        let mut index = 0;
+
+        while index < chars.len() {
+            let start = chars[index].0;
+            let after_word = index > 0 && chars[index - 1].1.is_alphanumeric();
+            let limit = emphasis_limit(text, line_starts, start);
+
+            let object = FootnoteReference::parse(&text[start..])
+                .map(|(reference, length)| (Object::FootnoteReference(reference), length))
+                .or_else(|| {
+                    Link::parse(&text[start..], after_word)
+                        .map(|(link, length)| (Object::Link(link), length))
+                });
+
+            if let Some((object, length)) = object {
+                if plain_start < start {
+                    objects.push(Object::new_text(&text[plain_start..start]));
+                }
+                objects.push(object);
+
+                plain_start = start + length;
+                while index < chars.len() && chars[index].0 < plain_start {
+                    index += 1;
+                }
+            } else if let Some(end) = find_emphasis(&chars, index, limit) {
+                let body_start = chars[index + 1].0;
+                let body_end = chars[end].0;
+                let next = chars.get(end + 1).map(|(i, _)| *i).unwrap_or(text.len());
+
+                if plain_start < start {
+                    objects.push(Object::new_text(&text[plain_start..start]));
+                }
+                objects.push(Object::new_emphasis(chars[index].1, &text[body_start..body_end]));
+
+                plain_start = next;
+                index = end + 1;
+            } else {
+                index += 1;
+            }
+        }
+
+        if plain_start < text.len() {
+            objects.push(Object::new_text(&text[plain_start..]));
+        }
+
+        objects
+    }
+
+    fn new_text(text: &str) -> Object {
+        Object::Text {
+            text: text.to_string(),
+        }
+    }
+
+    fn new_emphasis(marker: char, body: &str) -> Object {
+        match marker {
+            '*' => Object::Bold { objects: Object::parse(body) },
+            '/' => Object::Italic { objects: Object::parse(body) },
+            '_' => Object::Underline { objects: Object::parse(body) },
+            '+' => Object::StrikeThrough { objects: Object::parse(body) },
+            '=' => Object::Verbatim { text: body.to_string() },
+            _ => Object::Code { text: body.to_string() },
+        }
+    }
+
+    /// The nested objects, for objects that can contain other objects
+    pub fn objects(&self) -> &[Object] {
+        match self {
+            Object::Bold { objects }
+            | Object::Italic { objects }
+            | Object::Underline { objects }
+            | Object::StrikeThrough { objects } => objects,
+            Object::Link(link) => link.description.as_deref().unwrap_or(&[]),
+            Object::FootnoteReference(reference) => reference.definition.as_deref().unwrap_or(&[]),
+            _ => &[],
+        }
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(self, Object::Text { .. })
+    }
+
+    /// Returns this object followed by all of its nested objects, depth first
+    pub fn descendants(&self) -> Vec<&Object> {
+        let mut objects = vec![self];
+        for object in self.objects() {
+            objects.extend(object.descendants());
+        }
+        objects
+    }
+
+    /// The text without any markup
+    pub fn plain_text(&self) -> String {
+        match self {
+            Object::Text { text } | Object::Verbatim { text } | Object::Code { text } => {
+                text.clone()
+            }
+            Object::Link(link) if link.description.is_none() => link.path.clone(),
+            Object::FootnoteReference(_) => String::new(),
+            object => object.objects().iter().map(|object| object.plain_text()).collect(),
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let marker = match self {
+            Object::Text { text } => return write!(f, "{}", text),
+            Object::Verbatim { text } => return write!(f, "={}=", text),
+            Object::Code { text } => return write!(f, "~{}~", text),
+            Object::Link(link) => return write!(f, "{}", link),
+            Object::FootnoteReference(reference) => return write!(f, "{}", reference),
+            Object::Bold { .. } => "*",
+            Object::Italic { .. } => "/",
+            Object::Underline { .. } => "_",
+            Object::StrikeThrough { .. } => "+",
+        };
+
+        write!(f, "{}", marker)?;
+        for object in self.objects() {
+            write!(f, "{}", object)?;
+        }
+        write!(f, "{}", marker)
+    }
+}
+
+fn is_marker(c: char) -> bool {
+    "*/_+=~".contains(c)
+}
+
+fn is_pre(c: char) -> bool {
+    c.is_whitespace() || "-({'\"".contains(c)
+}
+
+fn is_post(c: char) -> bool {
+    c.is_whitespace() || "-.,;:!?')}[\"".contains(c)
+}
+
+fn is_border(c: char) -> bool {
+    !c.is_whitespace() && !",'\"".contains(c)
+}
+
+/// The offset before which an emphasis object starting at `start` has to end, which is the start
+/// of the line after the next one
+fn emphasis_limit(text: &str, line_starts: &[usize], start: usize) -> usize {
+    let line = line_starts.partition_point(|&line_start| line_start <= start);
+    line_starts.get(line + 1).cloned().unwrap_or(text.len())
+}
+
+/// Returns the index of the closing marker if an emphasis object starts at `start` and ends
+/// before the offset `limit`.
+///
+/// See <https://orgmode.org/worg/dev/org-syntax.html#Emphasis_Markers>
+fn find_emphasis(chars: &[(usize, char)], start: usize, limit: usize) -> Option<usize> {
+    let marker = chars[start].1;
+
+    if !is_marker(marker) || (start > 0 && !is_pre(chars[start - 1].1)) {
+        return None;
+    }
+
+    if !chars.get(start + 1).map(|(_, c)| is_border(*c)).unwrap_or(false) {
+        return None;
+    }
+
+    (start + 2..chars.len()).take_while(|&end| chars[end].0 < limit).find(|&end| {
+        chars[end].1 == marker
+            && is_border(chars[end - 1].1)
+            && chars.get(end + 1).map(|(_, c)| is_post(*c)).unwrap_or(true)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Object {
+        Object::Text { text: text.to_string() }
+    }
+
+    #[test]
+    fn test_parse_plain_text() {
+        assert_eq!(Object::parse("Just some text"), vec![text("Just some text")]);
+        assert_eq!(Object::parse("2*3*4 and a/b/c"), vec![text("2*3*4 and a/b/c")]);
+        assert_eq!(Object::parse("* not bold*"), vec![text("* not bold*")]);
+        assert_eq!(Object::parse(""), vec![]);
+    }
+
+    #[test]
+    fn test_parse_emphasis() {
+        assert_eq!(
+            Object::parse("Some *bold /and italic/* text, =code= and ~more~."),
+            vec![
+                text("Some "),
+                Object::Bold {
+                    objects: vec![
+                        text("bold "),
+                        Object::Italic { objects: vec![text("and italic")] },
+                    ],
+                },
+                text(" text, "),
+                Object::Verbatim { text: "code".to_string() },
+                text(" and "),
+                Object::Code { text: "more".to_string() },
+                text("."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verbatim_is_not_parsed() {
+        assert_eq!(
+            Object::parse("(=*not bold*=)"),
+            vec![
+                text("("),
+                Object::Verbatim { text: "*not bold*".to_string() },
+                text(")"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_links() {
+        let objects = Object::parse("See [[file:notes.org][my /notes/]] or https://orgmode.org_");
+        assert_eq!(objects.len(), 4);
+        assert_eq!(objects[0], text("See "));
+        assert!(match objects[1] {
+            Object::Link(ref link) => link.path == "file:notes.org",
+            _ => false,
+        });
+        assert_eq!(objects[2], text(" or "));
+        assert!(match objects[3] {
+            Object::Link(ref link) => link.path == "https://orgmode.org_",
+            _ => false,
+        });
+        assert_eq!(objects[1].descendants().len(), 4);
+        assert_eq!(objects[1].plain_text(), "my notes");
+        assert_eq!(objects[3].plain_text(), "https://orgmode.org_");
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let texts = [
+            "_underlined_ and +struck through+",
+            "A *bold* word in a sentence",
+            "Nested */italic and bold/* and unicode *ünï*",
+            "*unterminated bold",
+            "A footnote[fn:1] and an inline one[fn::with *bold*].",
+            "*[[https://orgmode.org][Org]]* <https://orgmode.org> mailto:me@example.com.",
+        ];
+
+        for text in texts.iter() {
+            let objects = Object::parse(text);
+            let display: String = objects.iter().map(|object| object.to_string()).collect();
+            assert_eq!(&display, text);
+        }
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let objects = Object::parse("*bold* and =code=");
+        let plain: String = objects.iter().map(|object| object.plain_text()).collect();
+        assert_eq!(plain, "bold and code");
+    }
+}
@@ -1,42 +1,120 @@
+use diagnostic;
+use headline::is_headline;
 use std::io::{self, BufReader, BufRead, Error as IoError};
 use std::fs::File;
-use std::iter::Peekable;
+use std::collections::VecDeque;
 
+/// Reports a warning as a `Diagnostic`, such as
+/// `org_warning!(Unterminated, "Expected `{}` before end of file", end)`
 macro_rules! org_warning {
-    ($($arg:tt)*) => {
-        let warning = format!($($arg)*);
-        println!("WARNING: {}", warning);
+    ($code:ident, $($arg:tt)*) => {
+        $crate::diagnostic::report(
+            $crate::diagnostic::Severity::Warning,
+            $crate::diagnostic::DiagnosticCode::$code,
+            format!($($arg)*),
+        );
     };
 }
 
+/// Reads lines from a file, keeping the line terminators so the original text can be recovered
+struct RawLines<R> {
+    reader: R
+}
+
+impl<R: BufRead> Iterator for RawLines<R> {
+    type Item = Result<String, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(line)),
+            Err(error) => Some(Err(error))
+        }
+    }
+}
+
+/// Removes the `\n` or `\r\n` at the end of a raw line
+fn strip_line_terminator(line: &str) -> &str {
+    match line.strip_suffix('\n') {
+        Some(line) => line.strip_suffix('\r').unwrap_or(line),
+        None => line
+    }
+}
+
 pub struct Parser<'a> {
-    lines: Peekable<Box<Iterator<Item=Result<String, io::Error>> + 'a>>,
+    /// The raw lines, including their line terminators
+    lines: Box<dyn Iterator<Item=Result<String, io::Error>> + 'a>,
+    /// Lines that were looked at ahead of time, but not read yet
+    ahead: VecDeque<Result<String, io::Error>>,
     current_line: u32,
+    offset: usize,
+    raw: Option<String>,
     pub io_error: Option<IoError>
 }
 
 impl<'a> Parser<'a> {
     pub fn from_string(source: &'a str) -> Self {
-        Parser::new(Box::new(source.lines().map(|line| Ok(line.to_string()))))
+        let lines = source.split_inclusive('\n').map(|line| Ok(line.to_string()));
+        Parser::from_raw_lines(Box::new(lines))
     }
 
     pub fn from_file(file: File) -> Self {
-        Parser::new(Box::new(BufReader::new(file).lines()))
+        Parser::from_raw_lines(Box::new(RawLines { reader: BufReader::new(file) }))
     }
 
-    pub fn new(iter: Box<Iterator<Item=Result<String, io::Error>> + 'a>) -> Self {
+    /// Creates a parser from lines without line terminators. Each line is assumed to end with
+    /// `\n` in the original text.
+    pub fn new(iter: Box<dyn Iterator<Item=Result<String, io::Error>> + 'a>) -> Self {
+        Parser::from_raw_lines(Box::new(iter.map(|line| line.map(|line| line + "\n"))))
+    }
+
+    fn from_raw_lines(iter: Box<dyn Iterator<Item=Result<String, io::Error>> + 'a>) -> Self {
         Parser {
-            lines: iter.peekable(),
-            current_line: 1,
+            lines: iter,
+            ahead: VecDeque::new(),
+            current_line: 0,
+            offset: 0,
+            raw: None,
             io_error: None
         }
     }
 
+    /// The 1-based number of the line that was read last
+    pub fn current_line(&self) -> u32 {
+        self.current_line
+    }
+
+    /// The number of bytes read so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Starts keeping the raw text of every line read, to be collected with `take_raw`
+    pub(crate) fn record_raw(&mut self) {
+        self.raw = Some(String::new());
+    }
+
+    /// Returns the raw text read since the last call, including line terminators
+    pub(crate) fn take_raw(&mut self) -> String {
+        self.raw.as_mut().map(|raw| raw.split_off(0)).unwrap_or_default()
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<String> {
-        match self.lines.next() {
+        let line = match self.ahead.pop_front() {
+            Some(line) => Some(line),
+            None => self.lines.next()
+        };
+        match line {
             Some(Ok(line)) => {
                 self.current_line += 1;
-                Some(line)
+                diagnostic::set_current_line(self.current_line);
+                self.offset += line.len();
+                if let Some(ref mut raw) = self.raw {
+                    raw.push_str(&line);
+                }
+                Some(strip_line_terminator(&line).to_string())
             },
             Some(Err(error)) => {
                 self.io_error = Some(error);
@@ -47,11 +125,35 @@ impl<'a> Parser<'a> {
     }
 
     pub fn peek(&mut self) -> Option<&str> {
-        match self.lines.peek() {
-            Some(Ok(line)) => Some(line.as_str()),
-            Some(Err(_)) => None,
-            None => None
+        self.peek_nth(0)
+    }
+
+    /// Looks at the line `n` lines after the next one, without reading it
+    pub(crate) fn peek_nth(&mut self, n: usize) -> Option<&str> {
+        while self.ahead.len() <= n {
+            let line = self.lines.next()?;
+            self.ahead.push_back(line);
+        }
+        match self.ahead[n] {
+            Ok(ref line) => Some(strip_line_terminator(line)),
+            Err(_) => None
+        }
+    }
+
+    /// Whether one of the following lines is the end line of a block or drawer, before the next
+    /// headline or the end of the file. Headlines can't be inside of blocks, so an unterminated
+    /// block shouldn't swallow the rest of the document.
+    pub(crate) fn has_end_before_headline(&mut self, is_end: impl Fn(&str) -> bool) -> bool {
+        let mut n = 0;
+        while let Some(line) = self.peek_nth(n) {
+            if is_end(line) {
+                return true;
+            } else if is_headline(line) {
+                return false;
+            }
+            n += 1;
         }
+        false
     }
 
     pub fn take_until(&mut self, end_line: &str) -> Vec<String> {
@@ -65,7 +167,7 @@ impl<'a> Parser<'a> {
             }
         }
 
-        org_warning!("Expected `{}` before end of file", end_line);
+        org_warning!(Unterminated, "Expected `{}` before end of file", end_line);
         lines
     }
 }
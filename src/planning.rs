@@ -41,34 +41,32 @@ impl Planning {
             for captures in ONE_REGEX.captures_iter(line) {
                 let keyword = captures.name("keyword").unwrap().as_str();
                 let timestamp = captures.name("timestamp").unwrap().as_str();
-                let mut timestamp = match Timestamp::parse(timestamp) {
-                    Some(timestamp) => timestamp,
-                    None => continue
-                };
+                // A line with an invalid timestamp is kept as text rather than dropped
+                let mut timestamp = Timestamp::parse(timestamp)?;
 
                 match keyword {
                     "DEADLINE" => {
                         if planning.deadline.is_some() {
-                            org_warning!("deadline is already set");
+                            org_warning!(Duplicate, "Deadline is already set");
                         }
                         timestamp.kind = TimestampKind::Deadline;
                         planning.deadline = Some(timestamp);
                     },
                     "SCHEDULED" => {
                         if planning.scheduled.is_some() {
-                            org_warning!("scheduled is already set");
+                            org_warning!(Duplicate, "Scheduled is already set");
                         }
                         timestamp.kind = TimestampKind::Scheduled;
                         planning.scheduled = Some(timestamp);
                     },
                     "CLOSED" => {
                         if planning.closed.is_some() {
-                            org_warning!("closed is already set");
+                            org_warning!(Duplicate, "Closed is already set");
                         }
                         timestamp.kind = TimestampKind::Closed;
                         planning.closed = Some(timestamp);
                     },
-                    _ => panic!("Unexpected keyword: {}", keyword)
+                    _ => {}
                 }
             }
 
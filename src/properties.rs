@@ -0,0 +1,184 @@
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::FromIterator;
+
+/// The properties of a node or document, in the order they were written
+///
+/// Names are matched ignoring case, like in Org. A `NAME+` property adds to the value of `NAME`,
+/// see <https://orgmode.org/manual/Property-Syntax.html>.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Properties {
+    entries: Vec<(String, String)>,
+}
+
+impl Properties {
+    pub fn new() -> Self {
+        Properties::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Every entry as it was written, including `NAME+` entries
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// The value of `NAME`, ignoring any `NAME+` entries
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(entry, _)| entry.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// The value of `NAME` followed by the value of every `NAME+` entry, separated by spaces
+    pub fn value(&self, name: &str) -> Option<String> {
+        let additions = self.additions(name);
+        match (self.get(name), additions.is_empty()) {
+            (Some(value), true) => Some(value.to_string()),
+            (Some(value), false) => Some(format!("{} {}", value, additions.join(" "))),
+            (None, false) => Some(additions.join(" ")),
+            (None, true) => None,
+        }
+    }
+
+    /// The values of the `NAME+` entries that add to `NAME`
+    pub fn additions(&self, name: &str) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|(entry, _)| is_addition(entry, name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Sets the value of a property, replacing the existing value and any `NAME+` entries
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.entries.retain(|(entry, _)| !is_addition(entry, name));
+
+        match self.entries.iter_mut().find(|(entry, _)| entry.eq_ignore_ascii_case(name)) {
+            Some(entry) => *entry = (name.to_string(), value.to_string()),
+            None => self.entries.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    /// Removes a property along with any `NAME+` entries, returning its previous value
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let value = self.value(name);
+        self.entries.retain(|(entry, _)| {
+            !entry.eq_ignore_ascii_case(name) && !is_addition(entry, name)
+        });
+        value
+    }
+
+    /// Adds an entry as it was written, without replacing existing entries
+    pub fn push(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_string(), value.to_string()));
+    }
+}
+
+fn is_addition(entry: &str, name: &str) -> bool {
+    entry.len() == name.len() + 1
+        && entry.ends_with('+')
+        && entry[..name.len()].eq_ignore_ascii_case(name)
+}
+
+impl FromIterator<(String, String)> for Properties {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Properties {
+            entries: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<(String, String)> for Properties {
+    fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+        self.entries.extend(iter)
+    }
+}
+
+impl IntoIterator for Properties {
+    type Item = (String, String);
+    type IntoIter = ::std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+// Properties are serialized as a map, like the `HashMap` they replaced, but keep their order
+impl Serialize for Properties {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (name, value) in self.entries.iter() {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Properties {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PropertiesVisitor;
+
+        impl<'de> Visitor<'de> for PropertiesVisitor {
+            type Value = Properties;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of property names to values")
+            }
+
+            fn visit_map<M: MapAccess<'de>>(self, mut access: M) -> Result<Properties, M::Error> {
+                let mut properties = Properties::new();
+                while let Some((name, value)) = access.next_entry::<String, String>()? {
+                    properties.push(&name, &value);
+                }
+                Ok(properties)
+            }
+        }
+
+        deserializer.deserialize_map(PropertiesVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_properties() {
+        let mut properties = Properties::new();
+        properties.push("Effort", "1:00");
+        properties.push("VAR", "x=1");
+        properties.push("var+", "y=2");
+        properties.push("VAR+", "z=3");
+
+        assert_eq!(properties.get("effort"), Some("1:00"));
+        assert_eq!(properties.get("VAR"), Some("x=1"));
+        assert_eq!(properties.value("Var"), Some("x=1 y=2 z=3".to_string()));
+        assert_eq!(properties.value("missing"), None);
+
+        properties.set("EFFORT", "2:00");
+        properties.set("VAR", "w=0");
+        properties.set("Owner", "Jane");
+        assert_eq!(
+            properties.iter().collect::<Vec<_>>(),
+            vec![("EFFORT", "2:00"), ("VAR", "w=0"), ("Owner", "Jane")]
+        );
+
+        assert_eq!(properties.remove("effort"), Some("2:00".to_string()));
+        assert_eq!(properties.remove("effort"), None);
+        assert_eq!(properties.len(), 2);
+    }
+}
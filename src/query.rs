@@ -0,0 +1,503 @@
+use chrono::{Duration, Local, NaiveDateTime};
+use document::Document;
+use node::Node;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::fmt;
+use timestamp::{parse_duration, today, Time, Timestamp};
+
+/// A compiled tags/property match, such as `+work-boss+PRIORITY="A"|home/NEXT`
+///
+/// See <https://orgmode.org/manual/Matching-tags-and-properties.html>
+#[derive(Debug)]
+pub struct Query {
+    /// Alternatives separated by `|`, each of which matches if all of its terms match
+    alternatives: Vec<Vec<Term>>,
+    todo: Option<TodoMatch>,
+}
+
+/// The part of a query after `/`, which matches against the TODO keyword
+#[derive(Debug)]
+struct TodoMatch {
+    /// With `/!`, only headlines with a TODO keyword that isn't done match
+    only_todo: bool,
+    alternatives: Vec<Vec<Term>>,
+}
+
+#[derive(Debug)]
+struct Term {
+    negated: bool,
+    kind: TermKind,
+}
+
+#[derive(Debug)]
+enum TermKind {
+    Tag(String),
+    TagRegex(Regex),
+    Property {
+        name: String,
+        op: Op,
+        value: Value,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug)]
+enum Value {
+    Number(f64),
+    String(String),
+    Regex(Regex),
+    Time(TimeValue),
+}
+
+#[derive(Debug)]
+enum TimeValue {
+    Absolute(NaiveDateTime),
+    Now,
+    /// Midnight at the start of today, offset by a number of days
+    Today(i64),
+}
+
+/// An invalid query, along with the byte offset where the problem was found
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryError {
+    pub message: String,
+    pub offset: usize,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Query, QueryError> {
+        let mut parser = QueryParser { query, offset: 0 };
+
+        let alternatives = parser.parse_alternatives(false)?;
+        let todo = if parser.eat('/') {
+            let only_todo = parser.eat('!');
+            let alternatives = parser.parse_alternatives(true)?;
+            Some(TodoMatch { only_todo, alternatives })
+        } else {
+            None
+        };
+
+        match parser.peek() {
+            Some(c) => Err(parser.error(&format!("Unexpected `{}`", c))),
+            None => Ok(Query { alternatives, todo }),
+        }
+    }
+
+    /// Whether a node of the document matches the query
+    pub fn matches(&self, document: &Document, node: &Node) -> bool {
+        let tags = document.node_tags(node.id);
+        let matches_tags = self.alternatives.is_empty()
+            || self.alternatives.iter().any(|terms| {
+                terms.iter().all(|term| term.matches(document, node, &tags))
+            });
+
+        matches_tags && self.todo.as_ref().is_none_or(|todo| todo.matches(node))
+    }
+}
+
+impl TodoMatch {
+    fn matches(&self, node: &Node) -> bool {
+        let keyword = match node.headline.keyword {
+            Some(ref keyword) => keyword.as_str(),
+            None => return false,
+        };
+
+        if self.only_todo && node.is_done() {
+            return false;
+        }
+
+        self.alternatives.is_empty()
+            || self.alternatives.iter().any(|terms| {
+                terms.iter().all(|term| {
+                    let matches = match term.kind {
+                        TermKind::Tag(ref name) => name == keyword,
+                        TermKind::TagRegex(ref regex) => regex.is_match(keyword),
+                        TermKind::Property { .. } => false,
+                    };
+                    matches != term.negated
+                })
+            })
+    }
+}
+
+impl Term {
+    fn matches(&self, document: &Document, node: &Node, tags: &[&str]) -> bool {
+        let matches = match self.kind {
+            TermKind::Tag(ref name) => tags.contains(&name.as_str()),
+            TermKind::TagRegex(ref regex) => tags.iter().any(|tag| regex.is_match(tag)),
+            TermKind::Property { ref name, op, ref value } => {
+                // A missing property is compared as an empty value, like in Emacs
+                let property = property_value(document, node, name).unwrap_or_default();
+                compare(&property, op, value)
+            }
+        };
+
+        matches != self.negated
+    }
+}
+
+/// The value of a property, including special properties such as `TODO` or `SCHEDULED`
+fn property_value(document: &Document, node: &Node, name: &str) -> Option<String> {
+    match name.to_uppercase().as_str() {
+        "ITEM" => Some(node.title().to_string()),
+        "TODO" => node.headline.keyword.clone(),
+        "LEVEL" => Some(node.indent.to_string()),
+        "PRIORITY" => document.node_priority(node.id).map(|priority| priority.to_string()),
+        "CATEGORY" => document.node_category(node.id).map(|category| category.to_string()),
+        "SCHEDULED" => node.scheduled_for.as_ref().map(|timestamp| timestamp.to_string()),
+        "DEADLINE" => node.deadline.as_ref().map(|timestamp| timestamp.to_string()),
+        "CLOSED" => node.closed_at.as_ref().map(|timestamp| timestamp.to_string()),
+        _ => document.node_property(node.id, name),
+    }
+}
+
+fn compare(property: &str, op: Op, value: &Value) -> bool {
+    let ordering = match value {
+        Value::Regex(regex) => {
+            return match op {
+                Op::Equal => regex.is_match(property),
+                Op::NotEqual => !regex.is_match(property),
+                _ => false,
+            }
+        }
+        Value::Number(number) => match property.trim() {
+            // An empty value counts as zero, like `string-to-number` in Emacs
+            "" => 0.0.partial_cmp(number),
+            property => property.parse::<f64>().ok().and_then(|property| property.partial_cmp(number)),
+        },
+        Value::String(string) => match (parse_duration(property), parse_duration(string)) {
+            // Durations such as efforts are compared by length rather than as text
+            (Some(property), Some(duration)) => Some(property.cmp(&duration)),
+            _ => Some(property.cmp(string.as_str())),
+        },
+        Value::Time(time) => Timestamp::parse(property.trim())
+            .map(|timestamp| {
+                let time_of_day = timestamp.time.unwrap_or_else(|| Time::from_hms(0, 0, 0));
+                timestamp.date.and_time(time_of_day).cmp(&time.resolve())
+            }),
+    };
+
+    match ordering {
+        Some(ordering) => match op {
+            Op::Equal => ordering == Ordering::Equal,
+            Op::NotEqual => ordering != Ordering::Equal,
+            Op::Less => ordering == Ordering::Less,
+            Op::LessOrEqual => ordering != Ordering::Greater,
+            Op::Greater => ordering == Ordering::Greater,
+            Op::GreaterOrEqual => ordering != Ordering::Less,
+        },
+        None => false,
+    }
+}
+
+impl TimeValue {
+    fn parse(value: &str) -> Option<TimeValue> {
+        lazy_static! {
+            static ref RELATIVE_REGEX: Regex =
+                Regex::new(r#"^<(?P<amount>[+-]\d+)(?P<unit>[dw])>$"#).unwrap();
+        }
+
+        match value {
+            "<now>" => Some(TimeValue::Now),
+            "<today>" => Some(TimeValue::Today(0)),
+            "<tomorrow>" => Some(TimeValue::Today(1)),
+            "<yesterday>" => Some(TimeValue::Today(-1)),
+            _ => {
+                if let Some(captures) = RELATIVE_REGEX.captures(value) {
+                    let amount: i64 = captures["amount"].parse().ok()?;
+                    let days = if &captures["unit"] == "w" {
+                        amount.checked_mul(7)?
+                    } else {
+                        amount
+                    };
+                    // Stay well within the range of dates
+                    if days.abs() > 1_000_000 {
+                        return None;
+                    }
+                    return Some(TimeValue::Today(days));
+                }
+
+                let timestamp = Timestamp::parse(value)?;
+                let time = timestamp.time.unwrap_or_else(|| Time::from_hms(0, 0, 0));
+                Some(TimeValue::Absolute(timestamp.date.and_time(time)))
+            }
+        }
+    }
+
+    fn resolve(&self) -> NaiveDateTime {
+        match self {
+            TimeValue::Absolute(time) => *time,
+            TimeValue::Now => Local::now().naive_local(),
+            TimeValue::Today(days) => (today() + Duration::days(*days)).and_hms(0, 0, 0),
+        }
+    }
+}
+
+struct QueryParser<'a> {
+    query: &'a str,
+    offset: usize,
+}
+
+impl<'a> QueryParser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.query[self.offset..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.offset += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: &str) -> QueryError {
+        QueryError {
+            message: message.to_string(),
+            offset: self.offset,
+        }
+    }
+
+    /// Parses terms separated by `|`, up to the `/` that starts the TODO match
+    fn parse_alternatives(&mut self, is_todo: bool) -> Result<Vec<Vec<Term>>, QueryError> {
+        let mut alternatives = Vec::new();
+        let mut terms = Vec::new();
+
+        loop {
+            match self.peek() {
+                None | Some('/') => break,
+                Some('|') => {
+                    self.offset += 1;
+                    alternatives.push(terms);
+                    terms = Vec::new();
+                }
+                Some('&') | Some('+') => self.offset += 1,
+                Some(c) if c.is_whitespace() => self.offset += c.len_utf8(),
+                Some(_) => terms.push(self.parse_term(is_todo)?),
+            }
+        }
+
+        if !terms.is_empty() || !alternatives.is_empty() {
+            alternatives.push(terms);
+        }
+        Ok(alternatives)
+    }
+
+    fn parse_term(&mut self, is_todo: bool) -> Result<Term, QueryError> {
+        let negated = self.eat('-');
+
+        if self.peek() == Some('{') {
+            let regex = self.parse_regex()?;
+            return Ok(Term { negated, kind: TermKind::TagRegex(regex) });
+        }
+
+        let name = self.parse_name();
+        if name.is_empty() {
+            return Err(match self.peek() {
+                Some(c) => self.error(&format!("Unexpected `{}`", c)),
+                None => self.error("Expected a tag or property"),
+            });
+        }
+
+        let kind = match self.parse_op() {
+            Some(_) if is_todo => {
+                return Err(self.error("Properties can't be matched against the TODO keyword"))
+            }
+            Some(op) => TermKind::Property { name, op, value: self.parse_value()? },
+            None => TermKind::Tag(name),
+        };
+
+        Ok(Term { negated, kind })
+    }
+
+    fn parse_name(&mut self) -> String {
+        let name: String = self.query[self.offset..]
+            .chars()
+            .take_while(|&c| c.is_alphanumeric() || "_@#%".contains(c))
+            .collect();
+        self.offset += name.len();
+        name
+    }
+
+    fn parse_op(&mut self) -> Option<Op> {
+        let ops = [
+            ("<=", Op::LessOrEqual),
+            (">=", Op::GreaterOrEqual),
+            ("<>", Op::NotEqual),
+            ("!=", Op::NotEqual),
+            ("==", Op::Equal),
+            ("=", Op::Equal),
+            ("<", Op::Less),
+            (">", Op::Greater),
+        ];
+
+        for &(text, op) in ops.iter() {
+            if self.query[self.offset..].starts_with(text) {
+                self.offset += text.len();
+                return Some(op);
+            }
+        }
+
+        None
+    }
+
+    fn parse_value(&mut self) -> Result<Value, QueryError> {
+        match self.peek() {
+            Some('"') => {
+                let start = self.offset;
+                self.offset += 1;
+                let end = match self.query[self.offset..].find('"') {
+                    Some(end) => self.offset + end,
+                    None => {
+                        self.offset = start;
+                        return Err(self.error("Unterminated string"));
+                    }
+                };
+                let string = &self.query[self.offset..end];
+                self.offset = end + 1;
+
+                if string.starts_with('<') && string.ends_with('>') {
+                    match TimeValue::parse(string) {
+                        Some(time) => Ok(Value::Time(time)),
+                        None => {
+                            self.offset = start;
+                            Err(self.error(&format!("Invalid time `{}`", string)))
+                        }
+                    }
+                } else {
+                    Ok(Value::String(string.to_string()))
+                }
+            }
+            Some('{') => self.parse_regex().map(Value::Regex),
+            _ => {
+                let text: String = self.query[self.offset..]
+                    .chars()
+                    .enumerate()
+                    .take_while(|&(index, c)| {
+                        c.is_ascii_digit() || c == '.' || (index == 0 && c == '-')
+                    })
+                    .map(|(_, c)| c)
+                    .collect();
+
+                match text.parse() {
+                    Ok(number) => {
+                        self.offset += text.len();
+                        Ok(Value::Number(number))
+                    }
+                    Err(_) => Err(self.error("Expected a string, number or regular expression")),
+                }
+            }
+        }
+    }
+
+    fn parse_regex(&mut self) -> Result<Regex, QueryError> {
+        let start = self.offset;
+        self.offset += 1;
+        let end = match self.query[self.offset..].find('}') {
+            Some(end) => self.offset + end,
+            None => {
+                self.offset = start;
+                return Err(self.error("Unterminated regular expression"));
+            }
+        };
+        let regex = Regex::new(&self.query[self.offset..end]).map_err(|error| {
+            QueryError {
+                message: format!("Invalid regular expression: {}", error),
+                offset: start,
+            }
+        })?;
+        self.offset = end + 1;
+        Ok(regex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use settings::Settings;
+    use todo_keywords::TodoKeywords;
+
+    const DOCUMENT: &str = "* Work :work:\n\
+                            ** TODO [#A] Write report :boss:\n\
+                            SCHEDULED: <2018-09-10 Mon>\n\
+                            ** NEXT Review pull requests\n\
+                            :PROPERTIES:\n\
+                            :Effort:   0:30\n\
+                            :END:\n\
+                            ** NEXT Plan the offsite\n\
+                            :PROPERTIES:\n\
+                            :Effort:   2:00\n\
+                            :END:\n\
+                            * Home :home:\n\
+                            ** NEXT Fix the sink\n\
+                            :PROPERTIES:\n\
+                            :Effort:   0:45\n\
+                            :END:\n\
+                            ** DONE Buy milk\n\
+                            CLOSED: [2018-09-09 Sun 18:00]";
+
+    fn select(query: &str) -> Vec<String> {
+        let mut todo_keywords = TodoKeywords::new();
+        todo_keywords.add_sequence(TodoKeywords::parse_line("#+TODO: TODO NEXT | DONE").unwrap());
+        let settings = Settings { todo_keywords, ..Settings::default() };
+        let document = Document::from_string_with_settings(DOCUMENT, &settings);
+        let query = Query::parse(query).unwrap();
+        document.select(&query).map(|node| node.title().to_string()).collect()
+    }
+
+    #[test]
+    fn test_select() {
+        assert_eq!(
+            select("+work-boss+PRIORITY=\"B\"|+home&TODO=\"NEXT\"&Effort<\"1:00\""),
+            vec!["Work", "Review pull requests", "Plan the offsite", "Fix the sink"]
+        );
+        assert_eq!(
+            select("work&Effort>=\"0:30\""),
+            vec!["Review pull requests", "Plan the offsite"]
+        );
+        assert_eq!(select("SCHEDULED<=\"<2018-09-10>\""), vec!["Write report"]);
+        assert_eq!(select("CLOSED>\"<2018-09-09 Sun 12:00>\""), vec!["Buy milk"]);
+        assert_eq!(select("LEVEL=1"), vec!["Work", "Home"]);
+        assert_eq!(select("ITEM={^Plan}"), vec!["Plan the offsite"]);
+        assert_eq!(select("{^bo}"), vec!["Write report"]);
+        assert_eq!(select("home/DONE"), vec!["Buy milk"]);
+        assert_eq!(select("/!-NEXT"), vec!["Write report"]);
+        assert_eq!(select("-work-home"), Vec::<String>::new());
+        assert_eq!(select("Effort<>\"0:30\"&LEVEL=2").len(), 4);
+        assert_eq!(
+            select("Effort<\"1:00\"&LEVEL=2"),
+            vec!["Write report", "Review pull requests", "Fix the sink", "Buy milk"]
+        );
+        assert_eq!(select("CLIENT=\"\"").len(), 7);
+        assert_eq!(select("CLIENT<1&LEVEL=2").len(), 5);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Query::parse("").is_ok());
+        assert!(Query::parse("+work-boss+PRIORITY=\"A\"|+home&TODO=\"NEXT\"/!").is_ok());
+        assert_eq!(Query::parse("work)").unwrap_err().offset, 4);
+        assert_eq!(Query::parse("Effort<\"1:00").unwrap_err().offset, 7);
+        assert_eq!(Query::parse("{[}").unwrap_err().offset, 0);
+        assert_eq!(Query::parse("LEVEL>two").unwrap_err().offset, 6);
+        assert_eq!(Query::parse("SCHEDULED<\"<someday>\"").unwrap_err().offset, 10);
+        assert!(Query::parse("work/TODO=\"NEXT\"").is_err());
+    }
+}
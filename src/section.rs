@@ -2,20 +2,27 @@ use drawer::Drawer;
 use element::Element;
 use std::fmt;
 use itertools::Itertools;
+use keyword::Keyword;
+use object::Object;
+use settings::{Settings, DEFAULT_SETTINGS};
+use source::{self, Source};
 use timestamp::{Timestamp, Date};
 use timestamps::Timestamps;
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct Section {
     pub elements: Vec<Element>,
-    pub(crate) timestamps: Timestamps
+    pub(crate) timestamps: Timestamps,
+    #[serde(skip)]
+    sources: Vec<Source>
 }
 
 impl Section {
     pub fn new() -> Self {
         Section {
             elements: Vec::new(),
-            timestamps: Timestamps::default()
+            timestamps: Timestamps::default(),
+            sources: Vec::new()
         }
     }
 
@@ -27,6 +34,32 @@ impl Section {
         self.elements.push(Element::Drawer(drawer))
     }
 
+    /// Adds an element to the end of the section. Any affiliated keywords directly before an
+    /// element that supports them, such as `#+NAME` or `#+CAPTION` before a table or paragraph,
+    /// are moved into that element.
+    pub fn add_element(&mut self, mut element: Element) {
+        if let Some(affiliated) = element.affiliated_mut() {
+            let count = self
+                .elements
+                .iter()
+                .rev()
+                .take_while(|element| match element {
+                    Element::Keyword(keyword) => keyword.is_affiliated(),
+                    _ => false,
+                })
+                .count();
+            let start = self.elements.len() - count;
+
+            for keyword in self.elements.drain(start..) {
+                if let Element::Keyword(keyword) = keyword {
+                    affiliated.push(keyword);
+                }
+            }
+        }
+
+        self.elements.push(element);
+    }
+
     pub fn add_line(&mut self, line: &str) {
         self.timestamps.parse_and_append(line);
 
@@ -37,7 +70,80 @@ impl Section {
             }
         }
 
-        self.elements.push(Element::new_paragraph(line));
+        // Affiliated keywords only belong to a paragraph that starts directly below them
+        if line.is_empty() {
+            self.elements.push(Element::new_paragraph(line));
+        } else {
+            self.add_element(Element::new_paragraph(line));
+        }
+    }
+
+    pub fn keywords(&self) -> impl Iterator<Item = &Keyword> {
+        self.elements.iter().filter_map(|element| match element {
+            Element::Keyword(keyword) => Some(keyword),
+            _ => None,
+        })
+    }
+
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.elements.iter().flat_map(|element| element.objects())
+    }
+
+    /// The original text of the elements, for documents parsed in lossless mode
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    /// Records the original text of the lines that were just added to the section
+    pub(crate) fn add_source(&mut self, source: Source) {
+        let count = self.elements.len();
+
+        // The lines either extended the last element, or were turned into a new element that
+        // took over the affiliated keywords before it, so the last regions are merged together
+        if count > 0 && self.sources.len() >= count {
+            let mut merged = self.sources.drain(count - 1..).collect::<Vec<_>>().into_iter();
+            let mut first = merged.next().unwrap();
+            for other in merged {
+                first.extend(other);
+            }
+            first.extend(source);
+            self.sources.push(first);
+        } else {
+            self.sources.push(source);
+        }
+    }
+
+    pub(crate) fn finish_sources(&mut self, settings: &Settings) {
+        if self.sources.len() != self.elements.len() {
+            org_warning!(Internal, "Unable to keep the original text of a section");
+            self.sources.clear();
+            return;
+        }
+
+        for (source, element) in self.sources.iter_mut().zip(self.elements.iter()) {
+            source.set_formatted(&element.format(settings));
+        }
+    }
+
+    /// Writes each element using its original text if it hasn't changed since it was parsed
+    pub(crate) fn write_lossless(&self, output: &mut String, settings: &Settings) {
+        let mut next = 0;
+
+        for element in self.elements.iter() {
+            let formatted = element.format(settings);
+
+            match self.sources[next..].iter().position(|source| source.matches(&formatted)) {
+                Some(index) => {
+                    source::push_region(output, &self.sources[next + index].text);
+                    next += index + 1;
+                }
+                None => source::push_region(output, &(formatted + "\n")),
+            }
+        }
+    }
+
+    pub fn format(&self, settings: &Settings) -> String {
+        self.elements.iter().map(|element| element.format(settings)).join("\n")
     }
 
     pub fn matches_date(&self, date: &Date) -> bool {
@@ -47,10 +153,14 @@ impl Section {
     pub fn timestamps_for_date<'a>(&'a self, date: &'a Date) -> impl Iterator<Item=Timestamp> + 'a {
         self.timestamps.timestamps_for_date(date)
     }
+
+    pub fn timestamps_for_date_at<'a>(&'a self, date: &'a Date, today: Date) -> impl Iterator<Item=Timestamp> + 'a {
+        self.timestamps.timestamps_for_date_at(date, today)
+    }
 }
 
 impl fmt::Display for Section {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.elements.iter().join("\n"))
+        write!(f, "{}", self.format(&DEFAULT_SETTINGS))
     }
 }
@@ -0,0 +1,161 @@
+use todo_keywords::TodoKeywords;
+use LINE_LENGTH;
+
+lazy_static! {
+    pub(crate) static ref DEFAULT_SETTINGS: Settings = Settings::default();
+}
+
+/// Options that control how documents are parsed and formatted
+///
+/// In-buffer settings, such as `#+TODO:` or `#+PRIORITIES:`, override these for the document
+/// they appear in.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub todo_keywords: TodoKeywords,
+    pub highest_priority: char,
+    pub lowest_priority: char,
+    /// The priority of headlines without a priority cookie
+    pub default_priority: char,
+    /// The column that tags are aligned to end at
+    pub tag_column: usize,
+    /// The column that paragraphs are filled to
+    pub fill_column: usize,
+    /// The name of the drawer that clock entries and state changes are logged into
+    pub log_drawer: String,
+    /// Whether a `CLOSED:` timestamp is added when a node is marked done, like `org-log-done`,
+    /// which is off by default as in Org and turned on with `#+STARTUP: logdone`
+    pub log_done: bool,
+    /// Whether marking a repeating node done is logged, like `org-log-repeat`
+    pub log_repeat: bool,
+    /// Whether every change of TODO keyword is logged, like `!` after each keyword in Org
+    pub log_state_changes: bool,
+    /// Which node properties are inherited from parent nodes
+    pub property_inheritance: PropertyInheritance,
+    /// Whether nodes inherit the tags of their parents and the `#+FILETAGS` of their document
+    pub tag_inheritance: bool,
+    /// Tags that are never inherited, like `org-tags-exclude-from-inheritance`
+    pub tags_exclude_from_inheritance: Vec<String>,
+    /// How many days before a deadline it is shown in the agenda, unless the deadline has its
+    /// own warning period, like `org-deadline-warning-days`
+    pub deadline_warning_days: u32,
+    /// Clocked time in reports is rounded down to a multiple of this many minutes
+    pub clock_rounding_minutes: u32,
+    /// Whether to keep the original text of the document, see `Document::open_file_lossless`
+    pub lossless: bool,
+}
+
+impl Settings {
+    /// Whether the priority is within the range from the highest to the lowest priority
+    pub fn is_valid_priority(&self, priority: char) -> bool {
+        priority >= self.highest_priority && priority <= self.lowest_priority
+    }
+
+    /// Applies an in-buffer `#+PRIORITIES: HIGHEST LOWEST DEFAULT` line, returning false if the
+    /// value is invalid
+    pub(crate) fn set_priorities(&mut self, value: &str) -> bool {
+        let priorities: Vec<char> = value
+            .split_whitespace()
+            .filter_map(|priority| {
+                let mut chars = priority.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(c),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        match priorities[..] {
+            [highest, lowest, default]
+                if highest <= lowest && default >= highest && default <= lowest =>
+            {
+                self.highest_priority = highest;
+                self.lowest_priority = lowest;
+                self.default_priority = default;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Applies the logging options of an in-buffer `#+STARTUP:` line, ignoring the others
+    pub(crate) fn apply_startup(&mut self, value: &str) {
+        for option in value.split_whitespace() {
+            match option {
+                "logdone" => self.log_done = true,
+                "nologdone" => self.log_done = false,
+                "logrepeat" => self.log_repeat = true,
+                "nologrepeat" => self.log_repeat = false,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Which properties are inherited, like `org-use-property-inheritance`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PropertyInheritance {
+    All,
+    Off,
+    /// Only the properties with these names are inherited
+    Only(Vec<String>),
+}
+
+impl PropertyInheritance {
+    pub fn inherits(&self, name: &str) -> bool {
+        match self {
+            PropertyInheritance::All => true,
+            PropertyInheritance::Off => false,
+            PropertyInheritance::Only(names) => {
+                names.iter().any(|inherited| inherited.eq_ignore_ascii_case(name))
+            }
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            todo_keywords: TodoKeywords::default(),
+            highest_priority: 'A',
+            lowest_priority: 'C',
+            default_priority: 'B',
+            tag_column: LINE_LENGTH,
+            fill_column: LINE_LENGTH,
+            log_drawer: "LOGBOOK".to_string(),
+            log_done: false,
+            log_repeat: true,
+            log_state_changes: false,
+            property_inheritance: PropertyInheritance::All,
+            tag_inheritance: true,
+            tags_exclude_from_inheritance: Vec::new(),
+            deadline_warning_days: 14,
+            clock_rounding_minutes: 15,
+            lossless: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_priorities() {
+        let mut settings = Settings::default();
+
+        assert!(settings.set_priorities("A E C"));
+        assert_eq!(
+            (settings.highest_priority, settings.lowest_priority, settings.default_priority),
+            ('A', 'E', 'C')
+        );
+        assert!(settings.is_valid_priority('D'));
+        assert!(!settings.is_valid_priority('F'));
+
+        assert!(!settings.set_priorities("C A B"));
+        assert!(!settings.set_priorities("A C"));
+        assert!(!settings.set_priorities("AB C B"));
+        assert_eq!(settings.lowest_priority, 'E');
+    }
+}
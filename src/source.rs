@@ -0,0 +1,60 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// A range of bytes in the original text of a document
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The original text of a region of a document, such as an element or the headline, planning
+/// info and properties of a node. A region is printed back from its original text for as long as
+/// the parsed value still formats the same way as it did when it was parsed.
+#[derive(Clone, Debug)]
+pub struct Source {
+    pub span: Span,
+    /// The original text, including whitespace and line terminators
+    pub text: String,
+    fingerprint: u64,
+}
+
+impl Source {
+    pub(crate) fn new(span: Span, text: String) -> Self {
+        Source {
+            span,
+            text,
+            fingerprint: 0,
+        }
+    }
+
+    /// Extends the region with text that directly follows it
+    pub(crate) fn extend(&mut self, other: Source) {
+        self.span.end = other.span.end;
+        self.text += &other.text;
+    }
+
+    /// Records how the parsed value formats, so that later changes to it can be detected
+    pub(crate) fn set_formatted(&mut self, formatted: &str) {
+        self.fingerprint = fingerprint(formatted);
+    }
+
+    pub(crate) fn matches(&self, formatted: &str) -> bool {
+        self.fingerprint == fingerprint(formatted)
+    }
+}
+
+fn fingerprint(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Appends a region to the output, starting it on a new line if the previous region didn't end
+/// with a line terminator
+pub(crate) fn push_region(output: &mut String, text: &str) {
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output.push_str(text);
+}
@@ -1,4 +1,5 @@
 use itertools::Itertools;
+use keyword::AffiliatedKeywords;
 use parser::Parser;
 use std::fmt;
 use utils::StringUtils;
@@ -7,17 +8,22 @@ lazy_static! {
     static ref DEFAULT_TABLE_CELL: TableCell = TableCell::default();
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct Table {
     rows: Vec<TableEntry>,
+    #[serde(default, skip_serializing_if = "AffiliatedKeywords::is_empty")]
+    pub affiliated: AffiliatedKeywords,
 }
 
 impl Table {
     pub fn parse(line: &str, parser: &mut Parser) -> Option<Table> {
         if let Some(row) = TableEntry::parse(line) {
-            let mut table = Table { rows: vec![row] };
+            let mut table = Table {
+                rows: vec![row],
+                affiliated: AffiliatedKeywords::default(),
+            };
 
-            while let Some(row) = parser.peek().and_then(|line| TableEntry::parse(line)) {
+            while let Some(row) = parser.peek().and_then(TableEntry::parse) {
                 parser.next();
                 table.rows.push(row);
             }
@@ -28,6 +34,20 @@ impl Table {
         }
     }
 
+    pub fn push_row<S: ToString>(&mut self, cells: &[S]) {
+        let cells = cells
+            .iter()
+            .map(|cell| TableCell {
+                text: cell.to_string(),
+            })
+            .collect();
+        self.rows.push(TableEntry::Row(TableRow { cells }));
+    }
+
+    pub fn push_rule(&mut self) {
+        self.rows.push(TableEntry::Rule);
+    }
+
     pub fn row_count(&self) -> usize {
         self.rows.len()
     }
@@ -85,7 +105,7 @@ impl fmt::Display for Table {
             .map(|row| row.format(&column_widths))
             .join("\n");
 
-        write!(f, "{}", rows)
+        write!(f, "{}{}", self.affiliated, rows)
     }
 }
 
@@ -1,15 +1,29 @@
 use itertools::Itertools;
+use keyword::AffiliatedKeywords;
+use object::Object;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::cell::OnceCell;
 use std::fmt;
-use textwrap::fill;
+use settings::{Settings, DEFAULT_SETTINGS};
 use utils::StringUtils;
-use LINE_LENGTH;
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct Text {
     text: String,
+    /// The offsets in `text` where each line after the first starts
+    #[serde(default)]
+    line_starts: Vec<usize>,
+    /// The objects are only parsed once they are needed, since a paragraph is built up line by
+    /// line
+    #[serde(skip)]
+    objects: OnceCell<Vec<Object>>,
     indent: usize,
     leading_blank_lines: usize,
     trailing_blank_lines: usize,
+    /// The affiliated keywords of a paragraph, such as `#+CAPTION` above an image link
+    #[serde(default)]
+    pub affiliated: AffiliatedKeywords,
 }
 
 impl Text {
@@ -28,40 +42,160 @@ impl Text {
             } else {
                 self.trailing_blank_lines += 1;
             }
+            return;
         } else if self.text.is_empty() {
             self.indent = line.indent();
             self.text = line.trim().to_string();
         } else {
             self.text += " ";
+            self.line_starts.push(self.text.len());
             self.text += line.trim();
         }
+
+        self.objects = OnceCell::new();
     }
 
     pub fn at_end(&self) -> bool {
         self.trailing_blank_lines >= 2
     }
 
-    pub fn format(&self, indent: usize) -> String {
-        let text = fill(self.text.trim(), LINE_LENGTH - (indent + self.indent));
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        self.objects.get_or_init(|| Object::parse_lines(&self.text, &self.line_starts))
+    }
+
+    /// Formats the text filled to the fill column, with every line after the first indented
+    pub fn format(&self, indent: usize, settings: &Settings) -> String {
+        let width = settings.fill_column.saturating_sub(indent + self.indent);
+        let text = fill(self.objects(), width);
         let line_prefix = String::from("\n") + &" ".repeat(indent + self.indent);
 
-        "\n".repeat(self.leading_blank_lines)
+        self.affiliated.to_string()
+            + &"\n".repeat(self.leading_blank_lines)
             + &" ".repeat(self.indent)
-            + &text.split("\n").join(&line_prefix)
+            + &text.split('\n').join(&line_prefix)
             + &"\n".repeat(self.trailing_blank_lines)
     }
 }
 
+// The objects are serialized along with the text, even if they haven't been parsed yet
+impl Serialize for Text {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Text", 7)?;
+        state.serialize_field("text", &self.text)?;
+        state.serialize_field("line_starts", &self.line_starts)?;
+        state.serialize_field("objects", self.objects())?;
+        state.serialize_field("indent", &self.indent)?;
+        state.serialize_field("leading_blank_lines", &self.leading_blank_lines)?;
+        state.serialize_field("trailing_blank_lines", &self.trailing_blank_lines)?;
+        if self.affiliated.is_empty() {
+            state.skip_field("affiliated")?;
+        } else {
+            state.serialize_field("affiliated", &self.affiliated)?;
+        }
+        state.end()
+    }
+}
+
 impl fmt::Display for Text {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.format(0))
+        write!(f, "{}", self.format(0, &DEFAULT_SETTINGS))
     }
 }
 
+/// Splits the objects into words, keeping markup together as a single word so that it is never
+/// broken across lines
+fn words(objects: &[Object]) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+
+    for object in objects {
+        match object {
+            Object::Text { text } => {
+                for c in text.chars() {
+                    if c.is_whitespace() {
+                        if !word.is_empty() {
+                            words.push(word);
+                            word = String::new();
+                        }
+                    } else {
+                        word.push(c);
+                    }
+                }
+            }
+            object => word += &object.to_string(),
+        }
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+/// Whether a word would change the meaning of a line if the line started with it, such as
+/// turning it into a headline or list item
+fn can_start_line(word: &str) -> bool {
+    let is_bullet = word == "-"
+        || word == "+"
+        || word.chars().all(|c| c == '*')
+        || ((word.ends_with('.') || word.ends_with(')'))
+            && word.len() > 1
+            && word[..word.len() - 1].chars().all(|c| c.is_ascii_digit()));
+
+    !is_bullet && !word.starts_with('#') && !word.starts_with('|')
+}
+
+fn fill(objects: &[Object], width: usize) -> String {
+    let mut text = String::new();
+    let mut line_width = 0;
+
+    for word in words(objects) {
+        let word_width = word.chars().count();
+
+        if line_width == 0 {
+            text += &word;
+            line_width = word_width;
+        } else if line_width + 1 + word_width <= width || !can_start_line(&word) {
+            text += " ";
+            text += &word;
+            line_width += 1 + word_width;
+        } else {
+            text += "\n";
+            text += &word;
+            line_width = word_width;
+        }
+    }
+
+    text
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_emphasis_spans_at_most_two_lines() {
+        assert!(!Text::new("Some *bold\ntext*").objects()[1].is_text());
+        assert_eq!(Text::new("Not *bold\nacross\nthree lines*").objects().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_long_paragraph() {
+        // Each line has unclosed markers, which used to be searched for until the end of the
+        // paragraph, after parsing the whole paragraph again for every line
+        let mut text = Text::default();
+        for _ in 0..5000 {
+            text.add_line("A line with *unclosed /markers =and ~a [[link]] [fn:1]");
+        }
+
+        assert_eq!(text.objects().iter().filter(|object| !object.is_text()).count(), 10000);
+    }
+
     #[test]
     fn test_format() {
         let text = Text::new(
@@ -69,9 +203,35 @@ mod tests {
              next line",
         );
         assert_eq!(
-            &text.format(0),
+            &text.format(0, &DEFAULT_SETTINGS),
             "  This is a paragraph that needs to be formatted nicely and should wrap to the\n  \
              next line"
         );
     }
+
+    #[test]
+    fn test_format_keeps_markup_together() {
+        let text = Text::new(
+            "This paragraph has some more markup near the end of the line, like =verbatim text= \
+             and *bold text*",
+        );
+        assert_eq!(
+            &text.format(0, &DEFAULT_SETTINGS),
+            "This paragraph has some more markup near the end of the line, like\n=verbatim text= \
+             and *bold text*"
+        );
+    }
+
+    #[test]
+    fn test_format_avoids_starting_lines_with_bullets() {
+        let text = Text::new(
+            "A long line of text that would otherwise wrap just before a bullet point so that\n\
+             - becomes a list item",
+        );
+        assert_eq!(
+            &text.format(0, &DEFAULT_SETTINGS),
+            "A long line of text that would otherwise wrap just before a bullet point so that -\n\
+             becomes a list item"
+        );
+    }
 }
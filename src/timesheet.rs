@@ -0,0 +1,164 @@
+use itertools::Itertools;
+use library::Library;
+use std::collections::BTreeMap;
+use timestamp::{Date, DateTime};
+
+/// The raw clock data of a library during a period of time, with a row for each `CLOCK:` line,
+/// such as for invoicing
+///
+/// Like in a `ClockReport`, clocks that are only partly in the period are cut off at its start
+/// and end, and clocks that are still running are left out.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Timesheet {
+    /// The properties to include in each row, such as `CLIENT`
+    pub properties: Vec<String>,
+    /// The clocks in the period, sorted by their start
+    pub rows: Vec<TimesheetRow>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TimesheetRow {
+    /// The day the clock was started
+    pub date: Date,
+    pub start: DateTime,
+    pub end: DateTime,
+    pub minutes: i64,
+    /// The path of the document, or its title if it wasn't opened from a file
+    pub file: String,
+    /// The titles of the node and its ancestors, outermost first
+    pub outline_path: Vec<String>,
+    pub category: Option<String>,
+    /// The tags of the node, including the tags it inherits
+    pub tags: Vec<String>,
+    /// The chosen properties that are set on the node or inherited by it
+    pub properties: BTreeMap<String, String>,
+}
+
+impl Timesheet {
+    /// The clocks between the start and the end, with the given properties of each clocked node.
+    /// Without a start or an end, the period is open on that side.
+    pub fn new(
+        library: &Library,
+        start: Option<DateTime>,
+        end: Option<DateTime>,
+        properties: &[&str],
+    ) -> Self {
+        let mut rows = Vec::new();
+
+        for document in library.documents() {
+            let file = match document.path {
+                Some(ref path) => path.display().to_string(),
+                None => document.name(),
+            };
+
+            for id in document.all_ids() {
+                for clock in document.node_logbook(id).clocks() {
+                    let (clock_start, clock_end) = match clock.clipped(start, end) {
+                        Some(clipped) => clipped,
+                        None => continue,
+                    };
+
+                    rows.push(TimesheetRow {
+                        date: clock_start.date(),
+                        start: clock_start,
+                        end: clock_end,
+                        minutes: clock_end.signed_duration_since(clock_start).num_minutes(),
+                        file: file.clone(),
+                        outline_path: document.outline_path(id).iter().map(|title| title.to_string()).collect(),
+                        category: document.node_category(id).map(|category| category.to_string()),
+                        tags: document.node_tags(id).iter().map(|tag| tag.to_string()).collect(),
+                        properties: properties
+                            .iter()
+                            .filter_map(|&name| Some((name.to_string(), document.node_property(id, name)?)))
+                            .collect(),
+                    });
+                }
+            }
+        }
+        rows.sort_by(|a, b| (a.start, &a.file, a.end).cmp(&(b.start, &b.file, b.end)));
+
+        Timesheet {
+            properties: properties.iter().map(|name| name.to_string()).collect(),
+            rows,
+        }
+    }
+
+    /// The rows as comma-separated values, with a header line and a column for each property.
+    /// The outline path is separated by `/` and the tags by `:`.
+    pub fn to_csv(&self) -> String {
+        let header = ["date", "start", "end", "minutes", "file", "outline_path", "category", "tags"]
+            .iter()
+            .map(|column| column.to_string())
+            .chain(self.properties.iter().cloned())
+            .map(|column| csv_field(&column))
+            .join(",");
+        let rows = self.rows.iter().map(|row| {
+            [
+                row.date.format("%Y-%m-%d").to_string(),
+                row.start.format("%Y-%m-%d %H:%M").to_string(),
+                row.end.format("%Y-%m-%d %H:%M").to_string(),
+                row.minutes.to_string(),
+                row.file.clone(),
+                row.outline_path.join("/"),
+                row.category.clone().unwrap_or_default(),
+                row.tags.join(":"),
+            ]
+            .iter()
+            .cloned()
+            .chain(self.properties.iter().map(|name| row.properties.get(name).cloned().unwrap_or_default()))
+            .map(|field| csv_field(&field))
+            .join(",")
+        });
+
+        Some(header).into_iter().chain(rows).map(|line| line + "\n").collect()
+    }
+}
+
+/// Quotes a field if it contains a comma, a quote or a line break, as described in RFC 4180
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use document::Document;
+
+    #[test]
+    fn test_timesheet() {
+        let mut library = Library::new();
+        library.add(Document::from_string(
+            "#+TITLE: Work\n\
+             #+FILETAGS: :work:\n\
+             * Website, redesign :PROJECT:\n\
+             :PROPERTIES:\n\
+             :CLIENT: Acme \"Corp\"\n\
+             :END:\n\
+             ** Design\n\
+             :LOGBOOK:\n\
+             CLOCK: [2018-10-27 Sat 09:00]--[2018-10-27 Sat 10:30] =>  1:30\n\
+             CLOCK: [2018-10-26 Fri 23:00]--[2018-10-27 Sat 01:00] =>  2:00\n\
+             CLOCK: [2018-10-20 Sat 09:00]--[2018-10-20 Sat 10:00] =>  1:00\n\
+             CLOCK: [2018-10-27 Sat 11:00]\n\
+             :END:",
+        ));
+        let start = Date::from_ymd(2018, 10, 27).and_hms(0, 0, 0);
+        let end = Date::from_ymd(2018, 10, 28).and_hms(0, 0, 0);
+
+        let timesheet = Timesheet::new(&library, Some(start), Some(end), &["CLIENT", "Effort"]);
+        assert_eq!(timesheet.rows.len(), 2);
+        assert_eq!(timesheet.rows[0].minutes, 60);
+        assert_eq!(timesheet.rows[1].outline_path, vec!["Website, redesign", "Design"]);
+        assert_eq!(timesheet.rows[1].tags, vec!["work", "PROJECT"]);
+        assert_eq!(
+            timesheet.to_csv(),
+            "date,start,end,minutes,file,outline_path,category,tags,CLIENT,Effort\n\
+             2018-10-27,2018-10-27 00:00,2018-10-27 01:00,60,Work,\"Website, redesign/Design\",,work:PROJECT,\"Acme \"\"Corp\"\"\",\n\
+             2018-10-27,2018-10-27 09:00,2018-10-27 10:30,90,Work,\"Website, redesign/Design\",,work:PROJECT,\"Acme \"\"Corp\"\"\",\n"
+        );
+    }
+}
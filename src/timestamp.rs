@@ -2,12 +2,13 @@ use chrono;
 use regex::Regex;
 use std::fmt;
 use std::cmp::Ordering;
-use chrono::Datelike;
+use chrono::{Datelike, Timelike};
 
 pub use chrono::Duration;
 
 pub type Date = chrono::NaiveDate;
 pub type Time = chrono::NaiveTime;
+pub type DateTime = chrono::NaiveDateTime;
 
 lazy_static! {
     pub(crate) static ref TIMESTAMP_REGEX: Regex = Regex::new(r#"(?x)
@@ -98,6 +99,23 @@ pub struct Repeater {
     pub unit: TimeUnit
 }
 
+impl Repeater {
+    /// Adds the interval of the repeater once. Months past the end of a shorter month are moved
+    /// back to its last day, so `2018-01-31 +1m` becomes `2018-02-28`.
+    fn shift(&self, datetime: &DateTime) -> Option<DateTime> {
+        let value = i64::from(self.value);
+        match self.unit {
+            TimeUnit::Hour => datetime.checked_add_signed(Duration::hours(value)),
+            TimeUnit::Day => datetime.checked_add_signed(Duration::days(value)),
+            TimeUnit::Week => datetime.checked_add_signed(Duration::weeks(value)),
+            TimeUnit::Month => add_months(&datetime.date(), value)
+                .map(|date| date.and_time(datetime.time())),
+            TimeUnit::Year => add_months(&datetime.date(), 12 * value)
+                .map(|date| date.and_time(datetime.time())),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum DelayMark {
     All,
@@ -111,6 +129,54 @@ pub struct Delay {
     pub unit: TimeUnit
 }
 
+impl TimeUnit {
+    fn symbol(&self) -> char {
+        match self {
+            TimeUnit::Hour => 'h',
+            TimeUnit::Day => 'd',
+            TimeUnit::Week => 'w',
+            TimeUnit::Month => 'm',
+            TimeUnit::Year => 'y'
+        }
+    }
+}
+
+impl Delay {
+    /// The number of days in the delay, such as the warning period of a deadline. Like
+    /// `org-get-wdays`, months are counted as 30.4 days and years as 365.25 days.
+    pub fn num_days(&self) -> i64 {
+        let value = i64::from(self.value);
+        match self.unit {
+            TimeUnit::Hour => value / 24,
+            TimeUnit::Day => value,
+            TimeUnit::Week => 7 * value,
+            TimeUnit::Month => 304 * value / 10,
+            TimeUnit::Year => 36525 * value / 100,
+        }
+    }
+}
+
+impl fmt::Display for Repeater {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mark = match self.mark {
+            RepeaterMark::Cumulate => "+",
+            RepeaterMark::CatchUp => "++",
+            RepeaterMark::Restart => ".+"
+        };
+        write!(f, "{}{}{}", mark, self.value, self.unit.symbol())
+    }
+}
+
+impl fmt::Display for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mark = match self.mark {
+            DelayMark::All => "-",
+            DelayMark::First => "--"
+        };
+        write!(f, "{}{}{}", mark, self.value, self.unit.symbol())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum TimestampKind {
     Scheduled,
@@ -131,8 +197,10 @@ pub enum TimestampKind {
 /// [DATE TIME-TIME REPEATER-OR-DELAY]                             (inactive range)
 impl Timestamp {
     pub fn parse(timestamp: &str) -> Option<Self> {
-        let (start, end) = if let Some(index) = timestamp.find("--") {
-            let (start, end) = timestamp.split_at(index);
+        // Look for `>--<` or `]--[`, since `--` can also start a delay, as in `<2018-09-15 --2d>`
+        let separator = timestamp.find(">--").or_else(|| timestamp.find("]--"));
+        let (start, end) = if let Some(index) = separator {
+            let (start, end) = timestamp.split_at(index + 1);
             (start, Some(&end[2..]))
         } else {
             (timestamp, None)
@@ -141,11 +209,11 @@ impl Timestamp {
         let end = if let Some(end) = end {
             let end = parse_timestamp(end)?;
             if start.repeater.is_some() || end.repeater.is_some() {
-                org_warning!("Multi-day repeating timestamps are not supported");
+                org_warning!(Unsupported, "Multi-day repeating timestamps are not supported");
                 start.repeater = None;
             }
             if end.delay.is_some() {
-                org_warning!("Ending timestamp should not have a delay");
+                org_warning!(InvalidTimestamp, "Ending timestamp should not have a delay");
             }
             Some(end)
         } else {
@@ -162,17 +230,79 @@ impl Timestamp {
         })
     }
 
+    /// A timestamp for a date and time to the minute, such as the `CLOSED:` time of a node
+    pub fn from_datetime(datetime: &DateTime, kind: TimestampKind) -> Self {
+        Timestamp {
+            date: datetime.date(),
+            end_date: None,
+            time: Some(Time::from_hms(datetime.hour(), datetime.minute(), 0)),
+            end_time: None,
+            kind,
+            repeater: None,
+            delay: None
+        }
+    }
+
+    /// Moves a repeating timestamp to its next occurrence after it was marked done at `now`,
+    /// returning false if it doesn't repeat
+    ///
+    /// Like in Org, `+1w` adds the interval once, `++1w` adds it until the timestamp is in the
+    /// future and `.+1w` adds it to today. See <https://orgmode.org/manual/Repeated-tasks.html>.
+    pub fn repeat(&mut self, now: &DateTime) -> bool {
+        let repeater = match self.repeater {
+            Some(ref repeater) if repeater.value > 0 => repeater.clone(),
+            _ => return false
+        };
+        let has_time = self.time.is_some();
+        let time = self.time.unwrap_or_else(|| Time::from_hms(0, 0, 0));
+        let is_future = |datetime: &DateTime| if has_time {
+            datetime > now
+        } else {
+            datetime.date() > now.date()
+        };
+
+        let next = match repeater.mark {
+            RepeaterMark::Cumulate => repeater.shift(&self.date.and_time(time)),
+            RepeaterMark::CatchUp => {
+                let mut next = repeater.shift(&self.date.and_time(time));
+                while let Some(datetime) = next {
+                    if is_future(&datetime) {
+                        break;
+                    }
+                    next = repeater.shift(&datetime);
+                }
+                next
+            },
+            RepeaterMark::Restart => repeater.shift(&now.date().and_time(time))
+        };
+
+        match next {
+            Some(next) => {
+                self.date = next.date();
+                if has_time {
+                    self.time = Some(next.time());
+                }
+                true
+            },
+            None => false
+        }
+    }
+
     pub fn matches(&self, date: &Date) -> bool {
         self.timestamp_for_date(date).is_some()
     }
 
     pub fn timestamp_for_date(&self, date: &Date) -> Option<Timestamp> {
+        self.timestamp_for_date_at(date, today())
+    }
+
+    /// The occurrence of the timestamp on a date, where scheduled items and deadlines only repeat
+    /// after the given day
+    pub fn timestamp_for_date_at(&self, date: &Date, today: Date) -> Option<Timestamp> {
         if !self.is_active() || date < &self.date {
             return None;
         }
 
-        let today = today();
-
         if date <= &today && (self.kind == TimestampKind::Scheduled || self.kind == TimestampKind::Deadline) {
             if self == date {
                 Some(self.clone())
@@ -185,24 +315,26 @@ impl Timestamp {
                     self == date
                 } else {
                     let duration = date.signed_duration_since(self.date).num_days() as u32;
+                    let months = 12 * (date.year() - self.date.year()) +
+                        (date.month() as i32 - self.date.month() as i32);
 
                     match repeater.unit {
                         TimeUnit::Year => {
                             self.date.month() == date.month() && self.date.day() == date.day() &&
-                                ((date.year() - self.date.year()) as u32 % repeater.value == 0)
+                                ((date.year() - self.date.year()) as u32).is_multiple_of(repeater.value)
                         },
                         TimeUnit::Month => {
                             self.date.day() == date.day() &&
-                                ((12 * (date.year() - self.date.year()) as u32 +
-                                  (date.month() - self.date.month()) as u32) % repeater.value == 0)
+                                (months as u32).is_multiple_of(repeater.value)
                         },
                         TimeUnit::Day => {
-                            duration % repeater.value == 0
+                            duration.is_multiple_of(repeater.value)
                         },
                         TimeUnit::Week => {
-                            duration % (7 * repeater.value) == 0
+                            duration.is_multiple_of(repeater.value.saturating_mul(7))
                         }
-                        TimeUnit::Hour => panic!("Hourly repeaters are not supported"),
+                        // An hourly repeater comes back every day
+                        TimeUnit::Hour => true,
                     }
                 }
             } else {
@@ -211,7 +343,7 @@ impl Timestamp {
 
             if on_today {
                 let mut timestamp = self.clone();
-                timestamp.date = date.clone();
+                timestamp.date = *date;
                 Some(timestamp)
             } else {
                 None
@@ -271,13 +403,20 @@ impl fmt::Display for Timestamp {
             }
         }
 
+        if let Some(ref repeater) = self.repeater {
+            write!(f, " {}", repeater)?;
+        }
+        if let Some(ref delay) = self.delay {
+            write!(f, " {}", delay)?;
+        }
+
         write!(f, "{}", end)?;
         if let Some(end_date) = self.end_date {
             write!(f, "--{}", start)?;
             write!(f, "{}", end_date.format("%Y-%m-%d %a"))?;
 
             if let Some(end_time) = self.end_time {
-                write!(f, "{}", end_time.format("%H:%M"))?;
+                write!(f, " {}", end_time.format("%H:%M"))?;
             }
             write!(f, "{}", end)?;
         }
@@ -319,49 +458,76 @@ fn parse_timestamp(timestamp: &str) -> Option<TimestampPart> {
     let captures = match DATE_REGEX.captures(timestamp) {
         Some(captures) => captures,
         None => {
-            org_warning!("Invalid date: {}", timestamp);
+            org_warning!(InvalidTimestamp, "Invalid date: {}", timestamp);
             return None;
         }
     };
     let is_active = captures.name("type").unwrap().as_str() == "<";
-    let year = captures.name("year").unwrap().as_str().parse().unwrap();
-    let month = captures.name("month").unwrap().as_str().parse().unwrap();
-    let day = captures.name("day").unwrap().as_str().parse().unwrap();
+    let year = captures.name("year").unwrap().as_str().parse();
+    let month = captures.name("month").unwrap().as_str().parse();
+    let day = captures.name("day").unwrap().as_str().parse();
 
-    let date = Date::from_ymd(year, month, day);
+    let date = match (year, month, day) {
+        (Ok(year), Ok(month), Ok(day)) => Date::from_ymd_opt(year, month, day),
+        _ => None
+    };
+    let date = match date {
+        Some(date) => date,
+        None => {
+            org_warning!(InvalidTimestamp, "Invalid date: {}", timestamp);
+            return None;
+        }
+    };
 
     let (time, end_time) = if let Some(captures) = TIME_REGEX.captures(timestamp) {
         let start_time = time(captures.name("hour").unwrap().as_str(),
                               captures.name("minute").unwrap().as_str(),
                               captures.name("pm").map(|c|c.as_str()));
-
-        let end_time = if let Some(end_hour) = captures.name("end_hour") {
-            Some(time(end_hour.as_str(),
-                      captures.name("end_minute").unwrap().as_str(),
-                      captures.name("end_pm").map(|c|c.as_str())))
-        } else {
-            None
+        let start_time = match start_time {
+            Some(start_time) => start_time,
+            None => {
+                org_warning!(InvalidTimestamp, "Invalid time: {}", timestamp);
+                return None;
+            }
         };
+
+        let end_time = captures.name("end_hour").and_then(|end_hour| {
+            let end_time = time(end_hour.as_str(),
+                                captures.name("end_minute").unwrap().as_str(),
+                                captures.name("end_pm").map(|c|c.as_str()));
+            if end_time.is_none() {
+                org_warning!(InvalidTimestamp, "Invalid end time: {}", timestamp);
+            }
+            end_time
+        });
         (Some(start_time), end_time)
     } else {
         (None, None)
     };
 
     let repeater = if let Some(captures) = REPEATER_REGEX.captures(timestamp) {
-        let mark = captures.name("mark").unwrap().as_str();
-        let mut value = captures.name("value").unwrap().as_str().parse().unwrap();
-        let unit = captures.name("unit").unwrap().as_str();
-
-        let mark = match mark {
-            "+" => RepeaterMark::Cumulate,
+        let mark = match captures.name("mark").unwrap().as_str() {
             "++" => RepeaterMark::CatchUp,
             ".+" => RepeaterMark::Restart,
-            _ => panic!("Unexpected repeater mark: {}", mark)
+            _ => RepeaterMark::Cumulate
         };
-        let mut unit = parse_unit(unit);
+        let value = captures.name("value").unwrap().as_str().parse();
+        let unit = parse_unit(captures.name("unit").unwrap().as_str());
 
+        match value {
+            Ok(value) => Some((mark, value, unit)),
+            Err(_) => {
+                org_warning!(InvalidTimestamp, "Invalid repeater: {}", timestamp);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let repeater = if let Some((mark, mut value, mut unit)) = repeater {
         if unit == TimeUnit::Hour {
-            org_warning!("Hourly repeaters are not supported");
+            org_warning!(Unsupported, "Hourly repeaters are not supported");
             unit = TimeUnit::Day;
             value = 1;
         }
@@ -376,22 +542,24 @@ fn parse_timestamp(timestamp: &str) -> Option<TimestampPart> {
     };
 
     let delay = if let Some(captures) = DELAY_REGEX.captures(timestamp) {
-        let mark = captures.name("mark").unwrap().as_str();
-        let value = captures.name("value").unwrap().as_str().parse().unwrap();
-        let unit = captures.name("unit").unwrap().as_str();
-
-        let mark = match mark {
-            "-" => DelayMark::All,
+        let mark = match captures.name("mark").unwrap().as_str() {
             "--" => DelayMark::First,
-            _ => panic!("Unexpected delay mark: {}", mark)
+            _ => DelayMark::All
         };
-        let unit = parse_unit(unit);
-
-        Some(Delay {
-            mark,
-            value,
-            unit
-        })
+        let value = captures.name("value").unwrap().as_str().parse();
+        let unit = parse_unit(captures.name("unit").unwrap().as_str());
+
+        match value {
+            Ok(value) => Some(Delay {
+                mark,
+                value,
+                unit
+            }),
+            Err(_) => {
+                org_warning!(InvalidTimestamp, "Invalid delay: {}", timestamp);
+                None
+            }
+        }
     } else {
         None
     };
@@ -409,17 +577,16 @@ fn parse_timestamp(timestamp: &str) -> Option<TimestampPart> {
 fn parse_unit(unit: &str) -> TimeUnit {
     match unit {
         "h" => TimeUnit::Hour,
-        "d" => TimeUnit::Day,
         "w" => TimeUnit::Week,
         "m" => TimeUnit::Month,
         "y" => TimeUnit::Year,
-        _ => panic!("Unexpected repeater/delay unit: {}", unit)
+        _ => TimeUnit::Day
     }
 }
 
-fn time(hour: &str, minute: &str, am_pm: Option<&str>) -> Time {
-    let mut hour = hour.parse().unwrap();
-    let minute = minute.parse().unwrap();
+fn time(hour: &str, minute: &str, am_pm: Option<&str>) -> Option<Time> {
+    let mut hour: u32 = hour.parse().ok()?;
+    let minute = minute.parse().ok()?;
 
     if let Some(am_pm) = am_pm {
         let pm = am_pm == "pm" || am_pm == "PM";
@@ -433,13 +600,69 @@ fn time(hour: &str, minute: &str, am_pm: Option<&str>) -> Time {
         }
     }
 
-    Time::from_hms(hour, minute, 0)
+    Time::from_hms_opt(hour, minute, 0)
 }
 
 pub fn today() -> Date {
     chrono::Local::today().naive_local()
 }
 
+pub fn now() -> DateTime {
+    chrono::Local::now().naive_local()
+}
+
+fn add_months(date: &Date, months: i64) -> Option<Date> {
+    let total = i64::from(date.year()) * 12 + i64::from(date.month0()) + months;
+    if total / 12 > i64::from(i32::MAX) {
+        return None;
+    }
+    let (year, month) = ((total / 12) as i32, (total % 12) as u32 + 1);
+
+    (1..=date.day())
+        .rev()
+        .filter_map(|day| Date::from_ymd_opt(year, month, day))
+        .next()
+}
+
+/// Parses a duration in the same `H:MM` format as `format_duration`, such as an effort estimate
+pub(crate) fn parse_duration(text: &str) -> Option<Duration> {
+    lazy_static! {
+        static ref REGEX: Regex = Regex::new(r#"^(?P<hours>\d+):(?P<minutes>\d+)$"#).unwrap();
+    }
+
+    let captures = REGEX.captures(text.trim())?;
+    let hours: i64 = captures.name("hours").unwrap().as_str().parse().ok()?;
+    let minutes: i64 = captures.name("minutes").unwrap().as_str().parse().ok()?;
+
+    // Stay well within the range of `Duration`, which panics on overflow
+    if hours > i64::from(u32::MAX) || minutes > i64::from(u32::MAX) {
+        return None;
+    }
+
+    Some(Duration::hours(hours) + Duration::minutes(minutes))
+}
+
+/// Serializes an optional duration as a number of minutes
+pub(crate) mod optional_minutes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use super::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => serializer.serialize_some(&duration.num_minutes()),
+            None => serializer.serialize_none()
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+        match Option::<i64>::deserialize(deserializer)? {
+            Some(minutes) if minutes.abs() > i64::MAX / 60_000 => Err(D::Error::custom("duration is out of range")),
+            minutes => Ok(minutes.map(Duration::minutes))
+        }
+    }
+}
+
 pub fn format_duration(duration: &Duration) -> String {
     format!("{}:{:02}", duration.num_hours(), duration.num_minutes() - 60 * duration.num_hours())
 }
@@ -477,4 +700,65 @@ mod tests {
         let timestamp = Timestamp::parse("[2018-09-15 Sat 05:00]").unwrap();
         assert_eq!(timestamp.time, Some(Time::from_hms(5, 0, 0)));
     }
+
+    #[test]
+    fn test_parse_invalid_timestamps() {
+        assert!(Timestamp::parse("<2018-02-31 Sat>").is_none());
+        assert!(Timestamp::parse("<2018-09-15 Sat 25:00>").is_none());
+        assert!(Timestamp::parse("<99999999999-09-15>").is_none());
+
+        let timestamp = Timestamp::parse("<2018-09-15 Sat 10:00-26:00>").unwrap();
+        assert_eq!(timestamp.end_time, None);
+
+        let timestamp = Timestamp::parse("<2018-09-15 Sat +99999999999d>").unwrap();
+        assert!(timestamp.repeater.is_none());
+    }
+
+    #[test]
+    fn test_parse_delay_and_range() {
+        let timestamp = Timestamp::parse("<2018-09-15 Sat --2d>").unwrap();
+        assert_eq!(timestamp.end_date, None);
+        assert!(timestamp.delay.is_some());
+
+        let timestamp = Timestamp::parse("<2018-09-15 Sat>--<2018-09-17 Mon>").unwrap();
+        assert_eq!(timestamp.end_date, Some(Date::from_ymd(2018, 9, 17)));
+    }
+
+    #[test]
+    fn test_repeaters() {
+        let mut timestamp = Timestamp::parse("<2018-11-15 Thu +1m>").unwrap();
+        assert!(timestamp.matches(&Date::from_ymd(2019, 2, 15)));
+        assert!(!timestamp.matches(&Date::from_ymd(2019, 2, 16)));
+
+        timestamp.repeater = Some(Repeater {
+            mark: RepeaterMark::Cumulate,
+            value: 1,
+            unit: TimeUnit::Hour,
+        });
+        assert!(timestamp.matches(&Date::from_ymd(2019, 2, 16)));
+    }
+
+    #[test]
+    fn test_repeat() {
+        let now = Date::from_ymd(2018, 10, 27).and_hms(20, 9, 0);
+        let repeat = |text: &str| {
+            let mut timestamp = Timestamp::parse(text).unwrap();
+            assert!(timestamp.repeat(&now));
+            timestamp.to_string()
+        };
+
+        assert_eq!(repeat("<2018-10-01 Mon +1w>"), "<2018-10-08 Mon +1w>");
+        assert_eq!(repeat("<2018-10-01 Mon 09:00 ++1w -1d>"), "<2018-10-29 Mon 09:00 ++1w -1d>");
+        assert_eq!(repeat("<2018-10-27 Sat 21:00 ++1d>"), "<2018-10-28 Sun 21:00 ++1d>");
+        assert_eq!(repeat("<2018-10-01 Mon .+2d>"), "<2018-10-29 Mon .+2d>");
+        assert_eq!(repeat("<2018-01-31 Wed +1m>"), "<2018-02-28 Wed +1m>");
+        assert_eq!(repeat("<2016-02-29 Mon +1y>"), "<2017-02-28 Tue +1y>");
+
+        let mut timestamp = Timestamp::parse("<2018-10-01 Mon>").unwrap();
+        assert!(!timestamp.repeat(&now));
+        assert_eq!(
+            Timestamp::from_datetime(&now, TimestampKind::Closed).to_string(),
+            "[2018-10-27 Sat 20:09]"
+        );
+    }
 }
@@ -29,13 +29,17 @@ impl Timestamps {
     pub fn timestamps_for_date<'a>(&'a self, date: &'a Date) -> impl Iterator<Item=Timestamp> + 'a {
         self.timestamps.iter().filter_map(move |timestamp| timestamp.timestamp_for_date(date))
     }
+
+    pub fn timestamps_for_date_at<'a>(&'a self, date: &'a Date, today: Date) -> impl Iterator<Item=Timestamp> + 'a {
+        self.timestamps.iter().filter_map(move |timestamp| timestamp.timestamp_for_date_at(date, today))
+    }
 }
 
 impl Add for Timestamps {
     type Output = Timestamps;
 
     fn add(mut self, rhs: Timestamps) -> Self::Output {
-        self.timestamps.extend(rhs.timestamps.into_iter());
+        self.timestamps.extend(rhs.timestamps);
         self.timestamps.sort();
 
         self
@@ -44,7 +48,7 @@ impl Add for Timestamps {
 
 impl AddAssign for Timestamps {
     fn add_assign(&mut self, rhs: Self) {
-        self.timestamps.extend(rhs.timestamps.into_iter());
+        self.timestamps.extend(rhs.timestamps);
         self.timestamps.sort();
     }
 }
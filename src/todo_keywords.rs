@@ -0,0 +1,186 @@
+use regex::Regex;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoSequenceKind {
+    Sequence,
+    Type,
+}
+
+/// A single sequence of TODO keywords, split into active and done states
+///
+/// See <https://orgmode.org/manual/Per_002dfile-keywords.html>
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoSequence {
+    pub kind: TodoSequenceKind,
+    pub todo: Vec<String>,
+    pub done: Vec<String>,
+}
+
+impl TodoSequence {
+    /// Parses the keywords of a sequence, such as `TODO(t) NEXT | DONE(d!) CANCELLED(c@)`.
+    /// Without a `|`, the last keyword is the only done state.
+    pub fn parse(kind: TodoSequenceKind, keywords: &str) -> Option<TodoSequence> {
+        let mut todo = Vec::new();
+        let mut done = Vec::new();
+        let mut has_separator = false;
+
+        for keyword in keywords.split_whitespace() {
+            if keyword == "|" {
+                has_separator = true;
+                continue;
+            }
+
+            // Strip fast access keys and logging settings, as in `WAIT(w@/!)`
+            let keyword = match keyword.find('(') {
+                Some(index) if index > 0 && keyword.ends_with(')') => &keyword[..index],
+                _ => keyword,
+            };
+
+            if has_separator {
+                done.push(keyword.to_string());
+            } else {
+                todo.push(keyword.to_string());
+            }
+        }
+
+        if !has_separator {
+            if let Some(keyword) = todo.pop() {
+                done.push(keyword);
+            }
+        }
+
+        if todo.is_empty() && done.is_empty() {
+            None
+        } else {
+            Some(TodoSequence { kind, todo, done })
+        }
+    }
+
+    pub fn keywords(&self) -> impl Iterator<Item = &str> {
+        self.todo.iter().chain(self.done.iter()).map(|keyword| keyword.as_str())
+    }
+
+    pub fn contains(&self, keyword: &str) -> bool {
+        self.keywords().any(|k| k == keyword)
+    }
+}
+
+/// The set of TODO keywords known to a document, made up of one or more sequences
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoKeywords {
+    pub sequences: Vec<TodoSequence>,
+}
+
+impl TodoKeywords {
+    pub fn new() -> Self {
+        TodoKeywords {
+            sequences: Vec::new(),
+        }
+    }
+
+    /// Parses an in-buffer `#+TODO:`, `#+SEQ_TODO:` or `#+TYP_TODO:` line into a sequence
+    pub fn parse_line(line: &str) -> Option<TodoSequence> {
+        lazy_static! {
+            static ref REGEX: Regex = Regex::new(r#"(?xi)
+                ^
+                \s*
+                \#\+(?P<kind>TODO|SEQ_TODO|TYP_TODO):
+                (?P<keywords>.*)
+                $
+            "#).unwrap();
+        }
+
+        let captures = REGEX.captures(line)?;
+        let kind = if captures["kind"].eq_ignore_ascii_case("TYP_TODO") {
+            TodoSequenceKind::Type
+        } else {
+            TodoSequenceKind::Sequence
+        };
+
+        TodoSequence::parse(kind, &captures["keywords"])
+    }
+
+    pub fn add_sequence(&mut self, sequence: TodoSequence) {
+        self.sequences.push(sequence);
+    }
+
+    pub fn keywords(&self) -> impl Iterator<Item = &str> {
+        self.sequences.iter().flat_map(|sequence| sequence.keywords())
+    }
+
+    pub fn sequence(&self, keyword: &str) -> Option<&TodoSequence> {
+        self.sequences.iter().find(|sequence| sequence.contains(keyword))
+    }
+
+    pub fn contains(&self, keyword: &str) -> bool {
+        self.sequence(keyword).is_some()
+    }
+
+    pub fn is_todo(&self, keyword: &str) -> bool {
+        self.sequences
+            .iter()
+            .any(|sequence| sequence.todo.iter().any(|k| k == keyword))
+    }
+
+    pub fn is_done(&self, keyword: &str) -> bool {
+        self.sequences
+            .iter()
+            .any(|sequence| sequence.done.iter().any(|k| k == keyword))
+    }
+}
+
+impl Default for TodoKeywords {
+    fn default() -> Self {
+        TodoKeywords {
+            sequences: vec![TodoSequence {
+                kind: TodoSequenceKind::Sequence,
+                todo: vec!["TODO".to_string(), "IN-PROGRESS".to_string()],
+                done: vec!["DONE".to_string()],
+            }],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            TodoKeywords::parse_line("#+TODO: TODO(t) NEXT(n) WAITING(w@/!) | DONE(d!) CANCELLED(c@)"),
+            Some(TodoSequence {
+                kind: TodoSequenceKind::Sequence,
+                todo: vec!["TODO".to_string(), "NEXT".to_string(), "WAITING".to_string()],
+                done: vec!["DONE".to_string(), "CANCELLED".to_string()],
+            })
+        );
+        assert_eq!(
+            TodoKeywords::parse_line("#+typ_todo: Fred Sara Lucy"),
+            Some(TodoSequence {
+                kind: TodoSequenceKind::Type,
+                todo: vec!["Fred".to_string(), "Sara".to_string()],
+                done: vec!["Lucy".to_string()],
+            })
+        );
+        assert_eq!(TodoKeywords::parse_line("#+SEQ_TODO:"), None);
+        assert_eq!(TodoKeywords::parse_line("#+TITLE: TODO"), None);
+    }
+
+    #[test]
+    fn test_is_done() {
+        let mut keywords = TodoKeywords::new();
+        keywords.add_sequence(TodoKeywords::parse_line("#+TODO: TODO NEXT | DONE").unwrap());
+        keywords.add_sequence(TodoKeywords::parse_line("#+TODO: REPORT BUG | FIXED").unwrap());
+
+        assert!(keywords.is_todo("NEXT"));
+        assert!(!keywords.is_done("NEXT"));
+        assert!(keywords.is_done("FIXED"));
+        assert!(!keywords.contains("CANCELLED"));
+        assert_eq!(
+            keywords.keywords().collect::<Vec<_>>(),
+            vec!["TODO", "NEXT", "DONE", "REPORT", "BUG", "FIXED"]
+        );
+    }
+}
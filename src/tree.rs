@@ -35,40 +35,44 @@ impl<Node> Tree<Node> {
         self.nodes.get_mut(&id)
     }
 
+    /// All node ids in document order
     pub fn all_ids(&self) -> impl Iterator<Item=NodeId> + '_ {
-        self.nodes.keys().map(|id| *id)
+        self.pre_order(self.root_id).map(|(id, _)| id)
     }
 
+    /// All nodes in document order
     pub fn all_nodes(&self) -> impl Iterator<Item=&Node> {
-        self.nodes.values()
+        self.all_ids().map(move |id| &self.nodes[&id])
     }
 
-    pub fn root_id(&self) -> NodeId {
-        self.root_id
-    }
-
-    pub fn root(&self) -> Option<&Node> {
-        self.nodes.get(&self.root_id)
+    /// Walks the descendants of a node, visiting each node before its children
+    pub fn pre_order(&self, id: NodeId) -> PreOrder<'_, Node> {
+        PreOrder {
+            tree: self,
+            stack: self.child_slice(id).iter().rev().map(|&id| (id, 1)).collect()
+        }
     }
 
-    pub fn root_mut(&mut self) -> Option<&mut Node> {
-        self.nodes.get_mut(&self.root_id)
+    /// Walks the descendants of a node, visiting each node after its children
+    pub fn post_order(&self, id: NodeId) -> PostOrder<'_, Node> {
+        PostOrder {
+            tree: self,
+            stack: self.child_slice(id).iter().rev().map(|&id| (id, 1, false)).collect()
+        }
     }
 
-    pub fn set_root(&mut self, node: Option<Node>) {
-        if let Some(node) = node {
-            self.nodes.insert(self.root_id, node);
-        } else {
-            self.nodes.remove(&self.root_id);
-        }
+    pub fn root_id(&self) -> NodeId {
+        self.root_id
     }
 
-    pub fn child_ids(&self, id: NodeId) -> impl Iterator<Item=NodeId> + '_ {
+    fn child_slice(&self, id: NodeId) -> &[NodeId] {
         self.children.get(&id)
             .map(|vec| vec.as_slice())
             .unwrap_or(&[])
-            .iter()
-            .map(|id| *id)
+    }
+
+    pub fn child_ids(&self, id: NodeId) -> impl Iterator<Item=NodeId> + '_ {
+        self.child_slice(id).iter().copied()
     }
 
     pub fn children(&self, id: NodeId) -> impl Iterator<Item=&Node> {
@@ -87,14 +91,101 @@ impl<Node> Tree<Node> {
         self.parents.get(&id).cloned().and_then(move |id| self.node_mut(id))
     }
 
+    /// The ids of the parent, grandparent and so on up to the root, which is included last
+    pub fn ancestor_ids(&self, id: NodeId) -> impl Iterator<Item=NodeId> + '_ {
+        let mut current = id;
+        ::std::iter::from_fn(move || {
+            let parent_id = self.parent_id(current)?;
+            current = parent_id;
+            Some(parent_id)
+        })
+    }
+
+    pub fn next_sibling_id(&self, id: NodeId) -> Option<NodeId> {
+        let index = self.index_in_parent(id)?;
+        self.child_slice(self.parent_id(id)?).get(index + 1).copied()
+    }
+
+    pub fn prev_sibling_id(&self, id: NodeId) -> Option<NodeId> {
+        let index = self.index_in_parent(id)?.checked_sub(1)?;
+        self.child_slice(self.parent_id(id)?).get(index).copied()
+    }
+
     pub fn insert_node(&mut self, parent_id: NodeId, node: Node) -> NodeId {
+        let index = self.child_slice(parent_id).len();
+        self.insert_node_at(parent_id, index, node)
+    }
+
+    /// Inserts a node at the given position among the children of the parent
+    pub fn insert_node_at(&mut self, parent_id: NodeId, index: usize, node: Node) -> NodeId {
         let id = self.next_id;
         self.next_id += 1;
         self.nodes.insert(id, node);
-        self.children.entry(parent_id).or_insert_with(Vec::new).push(id);
-        self.parents.insert(id, parent_id);
+        self.attach(id, parent_id, index);
         id
     }
+
+    /// Removes a node along with all of its descendants, returning the node itself
+    pub fn remove_node(&mut self, id: NodeId) -> Option<Node> {
+        self.take_subtree(id).into_iter().next().map(|(_, node)| node)
+    }
+
+    /// Removes a node along with all of its descendants, returning them in pre-order along with
+    /// the depth of each one below the removed node, which has a depth of 0
+    pub fn take_subtree(&mut self, id: NodeId) -> Vec<(usize, Node)> {
+        if id == self.root_id || !self.nodes.contains_key(&id) {
+            return Vec::new();
+        }
+
+        let ids: Vec<(NodeId, usize)> = Some((id, 0)).into_iter().chain(self.pre_order(id)).collect();
+        self.detach(id);
+
+        ids.into_iter()
+            .map(|(id, depth)| {
+                self.children.remove(&id);
+                self.parents.remove(&id);
+                (depth, self.nodes.remove(&id).unwrap())
+            })
+            .collect()
+    }
+
+    /// Moves a node and its descendants to the given position among the children of a new
+    /// parent. Returns false if the new parent is the node itself or one of its descendants.
+    pub fn move_node(&mut self, id: NodeId, parent_id: NodeId, index: usize) -> bool {
+        if id == self.root_id
+            || !self.nodes.contains_key(&id)
+            || (parent_id != self.root_id && !self.nodes.contains_key(&parent_id))
+            || parent_id == id
+            || self.ancestor_ids(parent_id).any(|ancestor_id| ancestor_id == id)
+        {
+            return false;
+        }
+
+        self.detach(id);
+        self.attach(id, parent_id, index);
+        true
+    }
+
+    /// The position of a node among the children of its parent
+    pub fn index_in_parent(&self, id: NodeId) -> Option<usize> {
+        let siblings = self.child_slice(self.parent_id(id)?);
+        siblings.iter().position(|&sibling| sibling == id)
+    }
+
+    fn attach(&mut self, id: NodeId, parent_id: NodeId, index: usize) {
+        let children = self.children.entry(parent_id).or_default();
+        let index = index.min(children.len());
+        children.insert(index, id);
+        self.parents.insert(id, parent_id);
+    }
+
+    fn detach(&mut self, id: NodeId) {
+        if let Some(parent_id) = self.parents.remove(&id) {
+            if let Some(children) = self.children.get_mut(&parent_id) {
+                children.retain(|&child_id| child_id != id);
+            }
+        }
+    }
 }
 
 impl<Node> Index<NodeId> for Tree<Node> {
@@ -110,3 +201,44 @@ impl<Node> IndexMut<NodeId> for Tree<Node> {
         self.node_mut(id).unwrap()
     }
 }
+
+/// A depth-first walk that yields each node id along with its depth below the starting node
+pub struct PreOrder<'a, Node: 'a> {
+    tree: &'a Tree<Node>,
+    stack: Vec<(NodeId, usize)>
+}
+
+impl<'a, Node> Iterator for PreOrder<'a, Node> {
+    type Item = (NodeId, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, depth) = self.stack.pop()?;
+        let children = self.tree.child_slice(id).iter().rev();
+        self.stack.extend(children.map(|&child_id| (child_id, depth + 1)));
+        Some((id, depth))
+    }
+}
+
+/// Like `PreOrder`, but each node comes after all of its descendants
+pub struct PostOrder<'a, Node: 'a> {
+    tree: &'a Tree<Node>,
+    /// Each entry is marked once its children have been pushed onto the stack
+    stack: Vec<(NodeId, usize, bool)>
+}
+
+impl<'a, Node> Iterator for PostOrder<'a, Node> {
+    type Item = (NodeId, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (id, depth, expanded) = self.stack.pop()?;
+            if expanded {
+                return Some((id, depth));
+            }
+
+            self.stack.push((id, depth, true));
+            let children = self.tree.child_slice(id).iter().rev();
+            self.stack.extend(children.map(|&child_id| (child_id, depth + 1, false)));
+        }
+    }
+}
@@ -1,12 +1,14 @@
 use itertools::Itertools;
+use regex::{Captures, Regex};
 
 pub trait StringUtils {
     fn indent(&self) -> usize;
     fn add_indent(&self, indent: usize) -> String;
     fn capped(&self, cap: &str) -> String;
+    fn trim_indent(&self, max: usize) -> &str;
 }
 
-impl<'a> StringUtils for &'a str {
+impl StringUtils for &str {
     fn indent(&self) -> usize {
         self.chars().take_while(|c| c.is_whitespace()).count()
     }
@@ -20,6 +22,10 @@ impl<'a> StringUtils for &'a str {
     fn capped(&self, cap: &str) -> String {
         String::from(cap) + self + cap
     }
+
+    fn trim_indent(&self, max: usize) -> &str {
+        trim_indent(self, max)
+    }
 }
 
 impl StringUtils for String {
@@ -36,4 +42,28 @@ impl StringUtils for String {
     fn capped(&self, cap: &str) -> String {
         String::from(cap) + self + cap
     }
+
+    fn trim_indent(&self, max: usize) -> &str {
+        trim_indent(self, max)
+    }
+}
+
+/// Removes up to `max` whitespace characters from the start of the text
+fn trim_indent(text: &str, max: usize) -> &str {
+    let start = text
+        .char_indices()
+        .take_while(|(_, c)| c.is_whitespace())
+        .take(max)
+        .last()
+        .map(|(index, c)| index + c.len_utf8())
+        .unwrap_or(0);
+    &text[start..]
+}
+
+/// Captures a regex that is anchored at the start of the text. Unlike `find`, `captures` can
+/// look at all of the text when there is no match, which is slow when checking for objects at
+/// every character of a long paragraph.
+pub fn captures_at_start<'t>(regex: &Regex, text: &'t str) -> Option<Captures<'t>> {
+    let end = regex.find(text)?.end();
+    regex.captures(&text[..end])
 }
@@ -0,0 +1,217 @@
+//! Parses a corpus of malformed documents, along with deterministic mutations of a well-formed
+//! one, to make sure that no input can make the parser panic
+
+extern crate orgmode;
+extern crate serde_json;
+
+use orgmode::{AgendaRange, Date, Document, Library};
+
+const DOCUMENT: &str = "#+TITLE: Robustness
+#+TODO: TODO NEXT | DONE
+#+CAPTION[Short]: A table
+| Name | Value |
+|------+-------|
+| a    | 1     |
+
+* TODO [#A] A headline with a link to [[https://orgmode.org][Org]]   :work:urgent:
+SCHEDULED: <2018-09-15 Sat 10:00 +1w> DEADLINE: <2018-09-20 Thu -2d>
+:PROPERTIES:
+:Effort:   1:30
+:CATEGORY: robust
+:END:
+:LOGBOOK:
+CLOCK: [2018-09-15 Sat 10:00]--[2018-09-15 Sat 11:30] =>  1:30
+:END:
+Some *bold*, /italic/ and =verbatim= text with a footnote[fn:1].
+  1. [X] First item
+  2. [@5] [ ] Second item
+     - Nested :: definition
+** NEXT Child <2018-09-16 Sun 09:00-10:00>
+#+BEGIN_SRC rust
+,* escaped
+#+END_SRC
+[fn:1] The footnote definition.
+* DONE Closed
+CLOSED: [2018-09-14 Fri 18:00]
+-----
+: fixed width
+# comment
+";
+
+const CORPUS: &[&str] = &[
+    "",
+    "\n",
+    "\r\n\r\n",
+    "*",
+    "*\t",
+    "**\tTODO",
+    "* \u{a0}title",
+    "*** Too deep\n* Shallow",
+    "* Headline with a very very very very very very very very very very very long title :tag:",
+    "<2018-02-31 Sat>",
+    "<2018-13-01>",
+    "<99999999999999999999-01-01>",
+    "<2018-09-15 Sat 99:99>",
+    "<2018-09-15 Sat 10:00-99:00>",
+    "<2018-09-15 Sat +99999999999999h>",
+    "<2018-09-15 Sat +0d>",
+    "<2018-09-15 Sat +4294967295w>",
+    "<2018-09-15 Sat 12:00 PM --3d>",
+    "<2018-09-15 Sat>--<2018-09-14 Fri>",
+    "<2018-09-15 Sat +1d>--<2018-09-16 Sun +1d>",
+    "[2018-09-15 Sat]--",
+    "* Heading\nSCHEDULED: <2018-02-30 Fri>",
+    "* Heading\nSCHEDULED: <2018-09-15> SCHEDULED: <2018-09-16>",
+    "SCHEDULED: <2018-09-15 Sat>",
+    "* Heading\ntext\nDEADLINE: <2018-09-15 Sat>",
+    "* Heading\n:PROPERTIES:\n:Effort: 99999999999999999999:00\n:END:",
+    "* Heading\n:PROPERTIES:\n:Effort: 1:99999999999999999999\n:END:",
+    "* Heading\n:PROPERTIES:\n:Effort: 9999999999:00\n:END:",
+    "* Heading\n:PROPERTIES:\n:Effort:",
+    "* Heading\n:LOGBOOK:\nCLOCK: [2018-09-15 Sat 10:00]--[2018-02-31 Sat 11:00]\n:END:",
+    "* Heading\n:LOGBOOK:\nCLOCK: [2018-09-15 Sat 10:00]--\n:END:",
+    ":PROPERTIES:\n:CATEGORY: top\n:END:",
+    ":END:",
+    "- [\t] tab checkbox",
+    "- [@z] [-] letter counter",
+    "1) [@99999999999999999999] overflowing counter",
+    "- item\n\u{a0}\u{a0}\u{a0}continued with unicode spaces",
+    "- item\n  #+BEGIN_EXAMPLE\n\n  #+END_EXAMPLE",
+    "- item\n  - nested\n\n    text",
+    "#+BEGIN_SRC",
+    "#+BEGIN_",
+    "#+END_SRC",
+    "#+BEGIN_EXAMPLE\n\u{a0},* text\n#+END_EXAMPLE",
+    "#+BEGIN_QUOTE\n* headline inside a block",
+    "#+:",
+    "#+[]:",
+    "#+CAPTION[: unterminated",
+    "#+NAME: orphan",
+    "#+TODO:",
+    "#+TODO: |",
+    "#+TODO: ( ) |",
+    "[fn:",
+    "[fn::",
+    "[fn::[[]]",
+    "[fn:1]",
+    "[[",
+    "[[]]",
+    "[[][]]",
+    "<>",
+    "<https:>",
+    "https://",
+    "*/_+=~",
+    "**bold**",
+    "* * *",
+    "=\u{1f600}=",
+    "|",
+    "|-",
+    "||||",
+    "| a | b |\n|---\n| \u{1f600} |",
+];
+
+/// Malformed documents that formatting must print unchanged, rather than dropping the lines that
+/// couldn't be parsed
+const FORMATTED_UNCHANGED: &[&str] = &[
+    "* Heading\nSCHEDULED: <2018-02-31 Sat>",
+    "* Heading\nSCHEDULED: <2018-10-20 Sat> DEADLINE: <2018-13-01 Thu>",
+    "* Heading\nCLOSED: [2018-09-15 Sat 99:99]",
+    "* Heading\nSCHEDULED: <2018-09-15 Sat>\nDEADLINE: <2018-02-30 Fri>",
+];
+
+/// Strings that are inserted into the document to break its structure in different ways
+const INSERTIONS: &[&str] = &[
+    "*", "* ", "\n", "\t", "[", "]", "<", ">", "--", ":", "|", "#+", "\u{a0}", "\u{1f600}", "9",
+];
+
+fn check(source: &str) {
+    let document = Document::from_string(source);
+    let formatted = document.to_string();
+    serde_json::to_string(&document).unwrap();
+
+    // The formatted document must be parseable again
+    Document::from_string(&formatted).to_string();
+
+    let lossless = Document::from_string_lossless(source);
+    assert_eq!(lossless.to_string(), source, "lossless round trip of {:?}", source);
+
+    for node in document.all_nodes() {
+        node.effort();
+        node.logbook().time_spent();
+        document.node_time_spent(node.id);
+        document.node_category(node.id);
+    }
+
+    let mut library = Library::new();
+    library.add(document);
+    library.agenda(AgendaRange::Week, Date::from_ymd(2018, 9, 10));
+    library.agenda_this_week();
+}
+
+fn char_boundaries(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(index, _)| index)
+        .chain(Some(text.len()))
+        .collect()
+}
+
+#[test]
+fn test_corpus() {
+    for source in CORPUS {
+        check(source);
+    }
+}
+
+#[test]
+fn test_formatted_round_trip() {
+    for source in FORMATTED_UNCHANGED {
+        check(source);
+        assert_eq!(&Document::from_string(source).to_string(), source);
+    }
+}
+
+#[test]
+fn test_truncated_documents() {
+    for index in char_boundaries(DOCUMENT).into_iter().step_by(3) {
+        check(&DOCUMENT[..index]);
+        check(&DOCUMENT[index..]);
+    }
+}
+
+#[test]
+fn test_removed_and_duplicated_lines() {
+    let lines: Vec<&str> = DOCUMENT.lines().collect();
+
+    for index in 0..lines.len() {
+        let mut removed = lines.clone();
+        removed.remove(index);
+        check(&removed.join("\n"));
+
+        let mut duplicated = lines.clone();
+        duplicated.insert(index, lines[index]);
+        check(&duplicated.join("\n"));
+    }
+}
+
+#[test]
+fn test_inserted_characters() {
+    // Insert at a spread of positions rather than every one, to keep the test fast
+    let positions: Vec<usize> = char_boundaries(DOCUMENT).into_iter().step_by(16).collect();
+
+    for insertion in INSERTIONS {
+        for &position in positions.iter() {
+            let mut source = DOCUMENT.to_string();
+            source.insert_str(position, insertion);
+            check(&source);
+        }
+    }
+}
+
+#[test]
+fn test_corpus_combinations() {
+    for first in CORPUS {
+        for second in CORPUS {
+            check(&format!("{}\n{}", first, second));
+        }
+    }
+}